@@ -16,23 +16,199 @@
 
 #![feature(associated_type_defaults)]
 
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use monologvm_core::disassembler::{disassemble, disassemble_at, mnemonic, SymbolTable};
+use monologvm_core::instruction::{decode_stream, instruction_length};
+use monologvm_core::loader::{elf_code, elf_symbols, is_elf};
 use monologvm_core::machine::Machine;
+use monologvm_core::register::dump_registers;
+use monologvm_core::trace::TraceEvent;
+
+#[derive(Parser)]
+#[command(name = "mvm", version, about = "The Monolog Virtual Machine")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Loads a flat binary or an ELF executable and runs it until it halts
+    Run(RunArgs),
+    /// Disassembles a flat binary or the code of an ELF executable, without running it
+    Disasm(DisasmArgs),
+    /// Counts the instructions of a program by mnemonic, as executed (by default) or as found in it
+    Stats(StatsArgs),
+}
+
+#[derive(Args)]
+struct RunArgs {
+    program: PathBuf,
+
+    /// Load address of flat binaries (ELF files carry their own)
+    #[arg(long, default_value = "0x0", value_parser = parse_address)]
+    base: u32,
+
+    /// Maximum number of instructions to execute
+    #[arg(long, default_value_t = u64::MAX)]
+    max_steps: u64,
+
+    /// Print each instruction as it is retired
+    #[arg(long)]
+    trace: bool,
+}
 
-fn main() {
-    println!("Hello, world!");
+#[derive(Args)]
+struct DisasmArgs {
+    program: PathBuf,
+
+    /// Load address of flat binaries, which the listed addresses start from (ELF files carry their own)
+    #[arg(long, default_value = "0x0", value_parser = parse_address)]
+    base: u32,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    program: PathBuf,
+
+    /// Load address of flat binaries (ELF files carry their own)
+    #[arg(long, default_value = "0x0", value_parser = parse_address)]
+    base: u32,
+
+    /// Maximum number of instructions to execute
+    #[arg(long, default_value_t = u64::MAX)]
+    max_steps: u64,
+
+    /// Count the instructions decoded from the program (the executable segments of ELF files), without running it
+    #[arg(long = "static", conflicts_with = "dynamic")]
+    static_count: bool,
+
+    /// Count the instructions executed (the default)
+    #[arg(long)]
+    dynamic: bool,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Run(args) => run(&args),
+        Command::Disasm(args) => disasm(&args),
+        Command::Stats(args) => stats(&args),
+    }
+}
 
-    let machine = Machine::new();
-    let ram_2 = machine.hart.peek(2, 1).unwrap();
-    let instruction = machine.hart.fetch();
-    let opcode = instruction.get_opcode();
+fn run(args: &RunArgs) -> Result<()> {
+    let mut machine = load(&args.program, args.base)?;
 
-    let imm = instruction
-        .get_imm()
-        .map(|it| it.to_string())
-        .unwrap_or("(None)".to_string());
+    if args.trace {
+        machine.set_x0_watch(true);
+        machine.set_trace_hook(|event| match event {
+            TraceEvent::Retired { pc, instruction } => println!("{:#010x}: {}", pc, disassemble(instruction)),
+            TraceEvent::AddressOverflow { pc, address } => println!("{:#010x}: address overflow ({:#x})", pc, address),
+            TraceEvent::DiscardedWrite { pc, .. } => println!("{:#010x}: warning: result written to x0", pc),
+        });
+    }
+
+    let result = machine.run(args.max_steps);
+    println!("Stopped after {} instructions: {:?}", result.retired, result.stop);
+
+    print!("{}", dump_registers(machine.hart(0).registers()));
+    Ok(())
+}
+
+// Lists each instruction as its address, its encoding in hex and its assembly, with jump and branch
+// targets labeled; what does not decode is listed as data. The symbols of ELF files name their targets,
+// and head the instructions they name, e.g. "<main>:".
+fn disasm(args: &DisasmArgs) -> Result<()> {
+    let bytes = fs::read(&args.program)?;
+    let (code, symbols) = if is_elf(&bytes) {
+        (elf_code(&bytes)?, elf_symbols(&bytes)?)
+    } else {
+        (vec![(args.base, &bytes[..])], SymbolTable::new())
+    };
+
+    for (base, code) in code {
+        for (address, instruction) in decode_stream(code, base) {
+            if let Some(name) = symbols.get(&address) {
+                println!("<{}>:", name);
+            }
+
+            let encoding = &code[address.wrapping_sub(base) as usize..];
+            let length = match encoding {
+                [low, high, ..] => instruction_length(u16::from_le_bytes([*low, *high])).bytes().min(encoding.len()),
+                _ => encoding.len(),
+            };
+            let hex: String = encoding[..length].iter().rev().map(|byte| format!("{:02x}", byte)).collect();
+
+            let assembly = match instruction {
+                Some(instruction) => disassemble_at(&instruction, address, &symbols),
+                None if length == 4 => format!(".word 0x{}", hex),
+                None if length == 2 => format!(".half 0x{}", hex),
+                None => format!(".byte 0x{}", hex),
+            };
+            println!("{:#010x}: {:<8}  {}", address, hex, assembly);
+        }
+    }
+
+    Ok(())
+}
+
+// Prints how many times each mnemonic occurs, the most frequent first
+fn stats(args: &StatsArgs) -> Result<()> {
+    let histogram = Arc::new(Mutex::new(BTreeMap::<&str, u64>::new()));
+
+    if args.dynamic || !args.static_count {
+        let mut machine = load(&args.program, args.base)?;
+        let sink = histogram.clone();
+        machine.set_trace_hook(move |event| {
+            if let TraceEvent::Retired { instruction, .. } = event {
+                *sink.lock().unwrap().entry(mnemonic(instruction)).or_default() += 1;
+            }
+        });
+        machine.run(args.max_steps);
+    } else {
+        let bytes = fs::read(&args.program)?;
+        let code = if is_elf(&bytes) { elf_code(&bytes)? } else { vec![(args.base, &bytes[..])] };
+
+        for (base, code) in code {
+            for instruction in decode_stream(code, base).filter_map(|(_, instruction)| instruction) {
+                *histogram.lock().unwrap().entry(mnemonic(&instruction)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut counts: Vec<(&str, u64)> =
+        histogram.lock().unwrap().iter().map(|(&mnemonic, &count)| (mnemonic, count)).collect();
+    counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+    for (mnemonic, count) in counts {
+        println!("{:>10}  {}", count, mnemonic);
+    }
+
+    Ok(())
+}
+
+fn load(program: &Path, base: u32) -> Result<Machine> {
+    let bytes = fs::read(program)?;
+    let mut machine = Machine::new();
+
+    if is_elf(&bytes) {
+        machine.load_elf(&bytes)?;
+    } else {
+        machine.load_binary(&bytes, base)?;
+    }
+
+    Ok(machine)
+}
 
-    println!(
-        "ram[2] = {}, instruction = {}, opcode = {}, imm = {}",
-        ram_2, instruction, opcode, imm
-    );
+fn parse_address(value: &str) -> Result<u32, ParseIntError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
 }
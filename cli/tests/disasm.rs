@@ -0,0 +1,101 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::process::Command;
+use std::{env, fs, process};
+
+// addi x1, x0, 5
+// ecall
+const PROGRAM: [u32; 2] = [0x0050_0093, 0x0000_0073];
+
+#[test]
+fn should_disassemble_a_flat_binary_without_running_it() {
+    let program = env::temp_dir().join(format!("mvm-{}-disasm.bin", process::id()));
+    fs::write(&program, PROGRAM.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mvm"))
+        .arg("disasm")
+        .arg(&program)
+        .arg("--base")
+        .arg("0x1000")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), [
+        "0x00001000: 00500093  li x1, 5",
+        "0x00001004: 00000073  ecall",
+    ]);
+}
+
+// An executable loading `code` at `base`, with a single symbol `name` naming it, as the loader tests build
+fn elf(base: u32, code: &[u32], name: &str) -> Vec<u8> {
+    let code: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let strings = 0x54 + code.len();
+    let symbols = (strings + name.len() + 2).next_multiple_of(4);
+    let sections = symbols + 2 * 16;
+
+    let mut elf = vec![0; sections + 3 * 40];
+    let mut put = |offset: usize, bytes: &[u8]| elf[offset..offset + bytes.len()].copy_from_slice(bytes);
+    let word = |value: usize| (value as u32).to_le_bytes();
+
+    put(0x00, &[0x7F, b'E', b'L', b'F', 1, 1, 1]); // 32 bits, little-endian
+    put(0x12, &[0xF3, 0]); // RISC-V
+    put(0x18, &base.to_le_bytes()); // entry
+    put(0x1C, &word(0x34)); // program headers
+    put(0x20, &word(sections)); // section headers
+    put(0x2A, &[32, 0, 1, 0, 40, 0, 3, 0]); // one 32-byte program header, three 40-byte section headers
+
+    put(0x34, &word(1)); // loadable
+    put(0x38, &word(0x54));
+    put(0x3C, &base.to_le_bytes());
+    put(0x44, &word(code.len())); // file size
+    put(0x48, &word(code.len())); // memory size
+    put(0x4C, &word(5)); // readable and executable
+    put(0x54, &code);
+
+    put(strings + 1, name.as_bytes()); // after the empty name
+    put(symbols + 16, &word(1));
+    put(symbols + 20, &base.to_le_bytes());
+    put(symbols + 28, &[0x12, 0, 1, 0]); // global function, defined
+
+    put(sections + 44, &word(2)); // symbol table
+    put(sections + 56, &word(symbols));
+    put(sections + 60, &word(2 * 16));
+    put(sections + 64, &word(2)); // link to .strtab
+    put(sections + 84, &word(3)); // string table
+    put(sections + 96, &word(strings));
+    put(sections + 100, &word(name.len() + 2));
+
+    elf
+}
+
+#[test]
+fn should_label_the_disassembly_of_an_elf_file_with_its_symbols() {
+    let program = env::temp_dir().join(format!("mvm-{}-disasm.elf", process::id()));
+    fs::write(&program, elf(0x1000, &PROGRAM, "main")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mvm")).arg("disasm").arg(&program).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), [
+        "<main>:",
+        "0x00001000: 00500093  li x1, 5",
+        "0x00001004: 00000073  ecall",
+    ]);
+}
@@ -0,0 +1,60 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::{env, fs, process};
+
+// addi x1, x0, 5
+// ecall
+const PROGRAM: [u32; 2] = [0x0050_0093, 0x0000_0073];
+
+// Named after the test and the process, so that tests running in parallel do not share their files
+fn write_program(name: &str, words: &[u32]) -> PathBuf {
+    let path = env::temp_dir().join(format!("mvm-{}-{}", process::id(), name));
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn should_run_a_flat_binary_until_ecall() {
+    let program = write_program("run.bin", &PROGRAM);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mvm")).arg("run").arg(&program).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Stopped after 1 instructions: Trap(EnvironmentCallFromMMode)"));
+    assert!(stdout.contains("ra: 0x0000000000000005"));
+}
+
+#[test]
+fn should_trace_retired_instructions() {
+    let program = write_program("trace.bin", &PROGRAM);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mvm"))
+        .arg("run")
+        .arg("--trace")
+        .arg(&program)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("0x00000000: li x1, 5"));
+}
@@ -0,0 +1,53 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::process::Command;
+use std::{env, fs, process};
+
+// addi x1, x1, 1
+// jal x0, -4
+const LOOP: [u32; 2] = [0x0010_8093, 0xFFDF_F06F];
+
+fn stats(mode: &str) -> Vec<String> {
+    let program = env::temp_dir().join(format!("mvm-{}-stats{}.bin", process::id(), mode));
+    fs::write(&program, LOOP.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mvm"))
+        .arg("stats")
+        .arg(mode)
+        .arg("--max-steps")
+        .arg("10")
+        .arg(&program)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect()
+}
+
+#[test]
+fn should_count_executed_instructions_apart_from_decoded_ones() {
+    let executed = stats("--dynamic");
+    let decoded = stats("--static");
+
+    assert_eq!(executed, ["5 addi", "5 jal"]);
+    assert_eq!(decoded, ["1 addi", "1 jal"]);
+    assert_ne!(executed, decoded);
+}
@@ -0,0 +1,47 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use monologvm_core::encoder::assemble;
+use monologvm_core::machine::Machine;
+
+const STEPS: u64 = 10_000;
+
+// Counts x1 up, forever
+fn tight_loop() -> Vec<u8> {
+    let program = assemble("addi x1, x1, 1; addi x2, x2, -1; jal x0, -8").unwrap();
+    program.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn decode_cache(c: &mut Criterion) {
+    let program = tight_loop();
+    let mut group = c.benchmark_group("tight loop");
+
+    for enabled in [false, true] {
+        group.bench_with_input(BenchmarkId::new("decode cache", enabled), &enabled, |b, &enabled| {
+            let mut machine = Machine::with_memory_size(4096);
+            machine.load_binary(&program, 0).unwrap();
+            machine.set_decode_cache(enabled);
+
+            b.iter(|| machine.run(STEPS));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decode_cache);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use monologvm_core::memory::{Memory, VecMemory};
+
+const SEGMENT_SIZE: usize = 1024 * 1024;
+
+fn write_bytes(c: &mut Criterion) {
+    let segment: Vec<u8> = (0..SEGMENT_SIZE).map(|i| i as u8).collect();
+    let mut memory = VecMemory::new(SEGMENT_SIZE);
+    let mut group = c.benchmark_group("1MB segment");
+
+    group.bench_function("byte by byte", |b| {
+        b.iter(|| segment.iter().enumerate().for_each(|(i, byte)| memory.write_byte(i as u32, byte)))
+    });
+    group.bench_function("in bulk", |b| b.iter(|| memory.write_bytes(0, &segment).unwrap()));
+
+    group.finish();
+}
+
+criterion_group!(benches, write_bytes);
+criterion_main!(benches);
@@ -0,0 +1,238 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use crate::instruction::XLEN;
+use crate::memory::InstructionLength;
+use crate::register::RegisterValue64;
+
+// Sign-extends the lowest `bits` bits of `value` to the full register width.
+pub fn sign_extend(value: RegisterValue64, bits: u32) -> RegisterValue64 {
+    let shift = RegisterValue64::BITS - bits;
+    (((value << shift) as i64) >> shift) as RegisterValue64
+}
+
+// Registers hold XLEN-wide values sign-extended to 64 bits, the same way RV64 keeps the results of
+// its 32-bit ("W") operations.
+pub fn truncate_xlen(value: RegisterValue64) -> RegisterValue64 { sign_extend(value, XLEN as u32) }
+
+// Adds two values as `xlen`-wide signed integers, telling whether the result wrapped around.
+// The sum is sign-extended to 64 bits, like any register value.
+pub(crate) fn add(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> (RegisterValue64, bool) {
+    let bits = xlen as u32;
+    let sum = a.wrapping_add(b);
+    let sign = |value: RegisterValue64| (value >> (bits - 1)) & 1;
+
+    // Overflow happens when both operands share a sign that differs from the sum's
+    (sign_extend(sum, bits), sign(a) == sign(b) && sign(a) != sign(sum))
+}
+
+pub(crate) fn add_xlen(a: RegisterValue64, b: RegisterValue64) -> (RegisterValue64, bool) { add(XLEN, a, b) }
+
+// Shifts of `xlen`-wide values: only the low log2(xlen) bits of `shamt` count (5 on RV32, 6 on
+// RV64), and the result is sign-extended to 64 bits. At InstructionLength::Word, they are also the
+// RV64 "W" shifts (SLLW, SRLW and SRAW), which only see the low 32 bits of their operand.
+pub(crate) fn sll(xlen: InstructionLength, value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 {
+    sign_extend(value << shift_amount(xlen, shamt), xlen as u32)
+}
+
+pub(crate) fn srl(xlen: InstructionLength, value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 {
+    let bits = xlen as u32;
+    let unsigned = value & (RegisterValue64::MAX >> (RegisterValue64::BITS - bits));
+
+    sign_extend(unsigned >> shift_amount(xlen, shamt), bits)
+}
+
+pub(crate) fn sra(xlen: InstructionLength, value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 {
+    let bits = xlen as u32;
+    let signed = sign_extend(value, bits) as i64;
+
+    sign_extend((signed >> shift_amount(xlen, shamt)) as RegisterValue64, bits)
+}
+
+pub(crate) fn sll_xlen(value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 { sll(XLEN, value, shamt) }
+
+pub(crate) fn srl_xlen(value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 { srl(XLEN, value, shamt) }
+
+pub(crate) fn sra_xlen(value: RegisterValue64, shamt: RegisterValue64) -> RegisterValue64 { sra(XLEN, value, shamt) }
+
+fn shift_amount(xlen: InstructionLength, shamt: RegisterValue64) -> u32 { shamt as u32 & (xlen as u32 - 1) }
+
+// High halves of the 2×xlen bits products of the MULH family, with both operands signed (MULH), a
+// signed one by an unsigned one (MULHSU), or both unsigned (MULHU). Products are widened to 128
+// bits, which RV64 needs; the high half is sign-extended to 64 bits, like any register value.
+pub(crate) fn mulh(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    high_half(xlen, (signed(xlen, a) * signed(xlen, b)) as u128)
+}
+
+pub(crate) fn mulhsu(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    high_half(xlen, (signed(xlen, a) * unsigned(xlen, b) as i128) as u128)
+}
+
+pub(crate) fn mulhu(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    high_half(xlen, unsigned(xlen, a) * unsigned(xlen, b))
+}
+
+pub(crate) fn mulh_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { mulh(XLEN, a, b) }
+
+pub(crate) fn mulhsu_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { mulhsu(XLEN, a, b) }
+
+pub(crate) fn mulhu_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { mulhu(XLEN, a, b) }
+
+// Divisions of the M extension, which round towards zero and never trap. Dividing by zero gives all
+// ones (DIV, DIVU) or the dividend (REM, REMU). Dividing the most negative value by -1 overflows,
+// giving that value back (DIV) with no remainder (REM): operands are widened to 128 bits, and the
+// quotient wraps around when sign-extended to 64 bits, like any register value.
+pub(crate) fn div(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    match signed(xlen, b) {
+        0 => RegisterValue64::MAX,
+        b => sign_extend((signed(xlen, a) / b) as RegisterValue64, xlen as u32),
+    }
+}
+
+pub(crate) fn divu(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    match unsigned(xlen, b) {
+        0 => RegisterValue64::MAX,
+        b => sign_extend((unsigned(xlen, a) / b) as RegisterValue64, xlen as u32),
+    }
+}
+
+pub(crate) fn rem(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    match signed(xlen, b) {
+        0 => sign_extend(a, xlen as u32),
+        b => sign_extend((signed(xlen, a) % b) as RegisterValue64, xlen as u32),
+    }
+}
+
+pub(crate) fn remu(xlen: InstructionLength, a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 {
+    match unsigned(xlen, b) {
+        0 => sign_extend(a, xlen as u32),
+        b => sign_extend((unsigned(xlen, a) % b) as RegisterValue64, xlen as u32),
+    }
+}
+
+pub(crate) fn div_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { div(XLEN, a, b) }
+
+pub(crate) fn divu_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { divu(XLEN, a, b) }
+
+pub(crate) fn rem_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { rem(XLEN, a, b) }
+
+pub(crate) fn remu_xlen(a: RegisterValue64, b: RegisterValue64) -> RegisterValue64 { remu(XLEN, a, b) }
+
+fn signed(xlen: InstructionLength, value: RegisterValue64) -> i128 { sign_extend(value, xlen as u32) as i64 as i128 }
+
+fn unsigned(xlen: InstructionLength, value: RegisterValue64) -> u128 {
+    (value & (RegisterValue64::MAX >> (RegisterValue64::BITS - xlen as u32))) as u128
+}
+
+fn high_half(xlen: InstructionLength, product: u128) -> RegisterValue64 {
+    sign_extend((product >> (xlen as u32)) as RegisterValue64, xlen as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_sign_extend_negative_immediates() {
+        assert_eq!(sign_extend(0xFFF, 12), u64::MAX);
+        assert_eq!(sign_extend(0x800, 12), -2048i64 as u64);
+    }
+
+    #[test]
+    fn should_not_sign_extend_positive_immediates() {
+        assert_eq!(sign_extend(0x7FF, 12), 2047);
+        assert_eq!(sign_extend(0x005, 12), 5);
+    }
+
+    #[test]
+    fn should_truncate_to_xlen() {
+        assert_eq!(truncate_xlen(0x0000_0000_8000_0000), 0xFFFF_FFFF_8000_0000);
+        assert_eq!(truncate_xlen(0x0000_0001_0000_0005), 5);
+    }
+
+    #[test]
+    fn should_detect_overflow_on_32_bits_boundaries() {
+        assert_eq!(add(InstructionLength::Word, 0x7FFF_FFFF, 1), (0xFFFF_FFFF_8000_0000, true));
+        assert_eq!(add(InstructionLength::Word, 0xFFFF_FFFF_8000_0000, u64::MAX), (0x7FFF_FFFF, true));
+        assert_eq!(add(InstructionLength::Word, 0x7FFF_FFFE, 1), (0x7FFF_FFFF, false));
+        assert_eq!(add(InstructionLength::Word, u64::MAX, 1), (0, false));
+    }
+
+    #[test]
+    fn should_detect_overflow_on_64_bits_boundaries() {
+        assert_eq!(add(InstructionLength::DoubleWord, i64::MAX as u64, 1), (i64::MIN as u64, true));
+        assert_eq!(add(InstructionLength::DoubleWord, i64::MIN as u64, u64::MAX), (i64::MAX as u64, true));
+        assert_eq!(add(InstructionLength::DoubleWord, 0x7FFF_FFFF, 1), (0x8000_0000, false));
+        assert_eq!(add(InstructionLength::DoubleWord, u64::MAX, 1), (0, false));
+    }
+
+    #[test]
+    fn should_mask_the_shift_amount_to_the_xlen() {
+        assert_eq!(sll(InstructionLength::Word, 1, 33), 2);
+        assert_eq!(sll(InstructionLength::DoubleWord, 1, 33), 0x2_0000_0000);
+        assert_eq!(sll(InstructionLength::DoubleWord, 1, 65), 2);
+        assert_eq!(srl(InstructionLength::DoubleWord, u64::MAX, 60), 0xF);
+        assert_eq!(sra(InstructionLength::DoubleWord, i64::MIN as u64, 63), u64::MAX);
+    }
+
+    #[test]
+    fn should_sign_extend_the_32_bits_result_of_word_shifts() {
+        // sraw of 0x8000_0000 (negative on 32 bits) by 4, with garbage in the upper half
+        assert_eq!(sra(InstructionLength::Word, 0x1234_5678_8000_0000, 4), 0xFFFF_FFFF_F800_0000);
+        assert_eq!(srl(InstructionLength::Word, 0x1234_5678_8000_0000, 4), 0x0800_0000);
+        assert_eq!(sll(InstructionLength::Word, 0x4000_0000, 1), 0xFFFF_FFFF_8000_0000);
+    }
+
+    #[test]
+    fn should_take_the_high_half_of_32_bits_products_by_signedness() {
+        let (min, minus_one) = (0xFFFF_FFFF_8000_0000, u64::MAX);
+
+        assert_eq!(mulh(InstructionLength::Word, min, minus_one), 0);
+        assert_eq!(mulhsu(InstructionLength::Word, min, minus_one), 0xFFFF_FFFF_8000_0000);
+        assert_eq!(mulhu(InstructionLength::Word, min, minus_one), 0x7FFF_FFFF);
+    }
+
+    #[test]
+    fn should_take_the_high_half_of_64_bits_products_by_signedness() {
+        assert_eq!(mulh(InstructionLength::DoubleWord, u64::MAX, u64::MAX), 0);
+        assert_eq!(mulhsu(InstructionLength::DoubleWord, u64::MAX, u64::MAX), u64::MAX);
+        assert_eq!(mulhu(InstructionLength::DoubleWord, u64::MAX, u64::MAX), 0xFFFF_FFFF_FFFF_FFFE);
+    }
+
+    #[test]
+    fn should_divide_by_zero_and_overflow_without_trapping() {
+        let min = 0xFFFF_FFFF_8000_0000;
+        let minus_one = u64::MAX;
+
+        assert_eq!(div(InstructionLength::Word, 7, 0), u64::MAX);
+        assert_eq!(divu(InstructionLength::Word, 7, 0), u64::MAX);
+        assert_eq!(rem(InstructionLength::Word, 7, 0), 7);
+        assert_eq!(remu(InstructionLength::Word, min, 0), min);
+        assert_eq!(div(InstructionLength::Word, min, minus_one), min);
+        assert_eq!(rem(InstructionLength::Word, min, minus_one), 0);
+        assert_eq!(div(InstructionLength::DoubleWord, i64::MIN as u64, minus_one), i64::MIN as u64);
+    }
+
+    #[test]
+    fn should_round_divisions_towards_zero() {
+        let minus_seven = -7i64 as u64;
+
+        assert_eq!(div(InstructionLength::Word, minus_seven, 2), -3i64 as u64);
+        assert_eq!(rem(InstructionLength::Word, minus_seven, 2), u64::MAX);
+        assert_eq!(divu(InstructionLength::Word, minus_seven, 2), 0x7FFF_FFFC);
+        assert_eq!(remu(InstructionLength::Word, minus_seven, 2), 1);
+    }
+}
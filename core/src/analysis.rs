@@ -0,0 +1,152 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::collections::BTreeSet;
+
+use crate::architecture::RV32Instruction;
+use crate::instruction::decode_stream;
+use crate::memory::{Byte, Word};
+
+// A straight run of instructions, entered at `start` only and left at its last instruction only
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: Word,
+    pub end: Word, // past the last instruction
+    pub terminator: Terminator,
+}
+
+// How a basic block is left
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Terminator {
+    Branch { target: Word }, // or falls through, when the condition does not hold
+    Jump { target: Word },   // JAL
+    IndirectJump,            // JALR
+    System,                  // ECALL, EBREAK and MRET, which enter or leave a trap handler
+    Invalid,                 // undecodable, hence trapping
+    FallThrough,             // the next instruction is a jump target (or there is none)
+}
+
+// Splits the code loaded at `base` into basic blocks: a block starts at `base`, at any jump or
+// branch target, and after any terminator. Targets outside of the code are not followed.
+pub fn analyze_blocks(bytes: &[Byte], base: Word) -> Vec<BasicBlock> {
+    let end = base.wrapping_add(bytes.len() as Word);
+    let instructions: Vec<(Word, Option<Terminator>)> = decode_stream(bytes, base)
+        .map(|(address, instruction)| (address, terminator(address, instruction)))
+        .collect();
+    let next = |index: usize| instructions.get(index + 1).map_or(end, |(address, _)| *address);
+
+    let mut leaders = BTreeSet::new();
+    for (index, (_, terminator)) in instructions.iter().enumerate() {
+        match terminator {
+            Some(Terminator::Branch { target } | Terminator::Jump { target }) => {
+                leaders.insert(*target);
+                leaders.insert(next(index));
+            }
+            Some(_) => {
+                leaders.insert(next(index));
+            }
+            None => {}
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let mut start = base;
+    for (index, (_, terminator)) in instructions.iter().enumerate() {
+        let next = next(index);
+
+        if terminator.is_some() || leaders.contains(&next) || next == end {
+            blocks.push(BasicBlock {
+                start,
+                end: next,
+                terminator: terminator.unwrap_or(Terminator::FallThrough),
+            });
+            start = next;
+        }
+    }
+
+    blocks
+}
+
+fn terminator(address: Word, instruction: Option<RV32Instruction>) -> Option<Terminator> {
+    let target = |offset: i64| address.wrapping_add(offset as Word);
+
+    match instruction {
+        None => Some(Terminator::Invalid),
+        Some(RV32Instruction::ConditionBranch(b_type)) => Some(Terminator::Branch {
+            target: target(b_type.immediate()),
+        }),
+        Some(RV32Instruction::UnconditionalJump(j_type)) => Some(Terminator::Jump {
+            target: target(j_type.immediate()),
+        }),
+        Some(RV32Instruction::JumpAndLinkRegister(_)) => Some(Terminator::IndirectJump),
+        Some(RV32Instruction::EnvironmentCallAndBreakpoint(_)) => Some(Terminator::System),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitfield::{Funct3BranchTable, Opcode7Table};
+    use crate::encoder::b_type;
+
+    fn bytes(program: &[Word]) -> Vec<Byte> { program.iter().flat_map(|word| word.to_le_bytes()).collect() }
+
+    #[test]
+    fn should_split_blocks_around_a_forward_branch() {
+        let beq = b_type(Opcode7Table::Branch, Funct3BranchTable::BEQ.into(), 1, 0, 8);
+        let program = crate::rv32asm! { addi x1, x0, 1 }
+            .into_iter()
+            .chain([beq]) // beq x1, x0, 8
+            .chain(crate::rv32asm! { addi x2, x0, 2; addi x3, x0, 3; ecall })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            analyze_blocks(&bytes(&program), 0x100),
+            vec![
+                BasicBlock {
+                    start: 0x100,
+                    end: 0x108,
+                    terminator: Terminator::Branch { target: 0x10C },
+                },
+                BasicBlock {
+                    start: 0x108,
+                    end: 0x10C,
+                    terminator: Terminator::FallThrough,
+                },
+                BasicBlock {
+                    start: 0x10C,
+                    end: 0x114,
+                    terminator: Terminator::System,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_end_the_last_block_with_the_code() {
+        let program = bytes(&crate::rv32asm! { addi x1, x0, 1; addi x1, x1, 1 });
+
+        assert_eq!(
+            analyze_blocks(&program, 0),
+            vec![BasicBlock {
+                start: 0,
+                end: 8,
+                terminator: Terminator::FallThrough,
+            }]
+        );
+    }
+}
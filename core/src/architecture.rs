@@ -14,22 +14,75 @@
 // limitations under the Licence.
 //
 
+use std::ops::RangeInclusive;
+
 use arbitrary_int::Number;
-use derive_more::Display;
+use derive_more::{Display, Error};
 use kinded::Kinded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::bitfield::{
-    BType32Bitfield, Funct3, Funct7, IFenceType32Bitfield, IType32Bitfield, JType32Bitfield, Opcode7, Opcode7Table,
-    RType32Bitfield, SType32Bitfield,
+    AType32Bitfield, BType32Bitfield, Funct3, Funct3Expr, Funct7, IFenceType32Bitfield, IType32Bitfield,
+    Immediate11Table, JType32Bitfield, Opcode7, Opcode7Table, RType32Bitfield, SType32Bitfield, UType32Bitfield,
+};
+use crate::instruction::{
+    ChompRV32, Descriptor, NOP, PRIVILEGED_INSTRUCTIONS, RV32A_INSTRUCTIONS, RV32I_INSTRUCTIONS, RV32M_INSTRUCTIONS,
+    RV64I_INSTRUCTIONS, ZICSR_INSTRUCTIONS,
 };
-use crate::instruction::{ChompRV32, Descriptor};
 use crate::memory::Word;
 
+// funct3 of LD and SD, which are RV64I only
+const DOUBLEWORD: u8 = 0b011;
+
 // TODO: YAEM - Yet Another Enum Macro (instead of enum_aliases)
 //       - This macro should be able to generate the enum alias and the conversion functions
 //       - The conversion functions should be able to convert from the enum alias to the enum and vice-versa
 
-pub(crate) trait Architecture<C, I>
+/// An instruction set architecture: decodes chomps of type `C` (the raw bits fetched for one
+/// instruction) into instructions of type `I`, and tells which descriptor each one belongs to.
+///
+/// Implementors are unit structs tagged with `InstructionSet`, decoding into a type tagged with
+/// `Instruction`. `decode` must reject (rather than panic on) any chomp outside the set; `get_opcode`,
+/// `explain_match` and `describe` are only ever called with instructions `decode` returned.
+///
+/// ```
+/// use arbitrary_int::u7;
+/// use monologvm_core::architecture::{Architecture, DecodeError, FieldName, Instruction, InstructionSet, MatchResult};
+/// use monologvm_core::bitfield::Opcode7;
+/// use monologvm_core::instruction::{Descriptor, ADDI};
+///
+/// // An architecture knowing nothing but `addi x0, x0, 0`
+/// struct NopOnly;
+/// impl InstructionSet for NopOnly {}
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// struct Nop;
+/// impl Instruction for Nop {}
+///
+/// impl Architecture<u32, Nop> for NopOnly {
+///     fn decode(&self, chomp: u32) -> Result<Nop, DecodeError> {
+///         match chomp {
+///             0x0000_0013 => Ok(Nop),
+///             _ => Err(DecodeError::UnknownOpcode((chomp & 0x7F) as u8)),
+///         }
+///     }
+///
+///     fn get_opcode(&self, _: Nop) -> Opcode7 { u7::new(0x13) }
+///
+///     fn explain_match(&self, _: Nop, descr: &Descriptor) -> MatchResult {
+///         if descr == &ADDI { MatchResult::Matched } else { MatchResult::Mismatch(FieldName::Opcode) }
+///     }
+///
+///     fn describe(&self, _: Nop) -> Option<&'static Descriptor> { Some(&ADDI) }
+/// }
+///
+/// assert_eq!(NopOnly.decode(0x0000_0013), Ok(Nop));
+/// assert_eq!(NopOnly.decode_opt(0x0050_0093), None);
+/// assert!(NopOnly.match_instruction(Nop, &ADDI));
+/// assert_eq!(NopOnly.describe(Nop).unwrap().mnemonic, "addi");
+/// ```
+pub trait Architecture<C, I>
 where
     Self: Sized,
     Self: InstructionSet,
@@ -39,12 +92,41 @@ where
     type Chomp = C;
     type Instruction = I;
 
-    fn decode(&self, chomp: C) -> Option<I>;
+    fn decode(&self, chomp: C) -> Result<I, DecodeError>;
+    fn decode_opt(&self, chomp: C) -> Option<I> { self.decode(chomp).ok() }
     fn get_opcode(&self, instruction: I) -> Opcode7;
-    fn match_instruction(&self, instruction: I, descr: Descriptor) -> bool;
+    fn match_instruction(&self, instruction: I, descr: &Descriptor) -> bool {
+        self.explain_match(instruction, descr) == MatchResult::Matched
+    }
+
+    // Like `match_instruction`, naming the first field found to differ from the descriptor, e.g. to
+    // debug the descriptor tables
+    fn explain_match(&self, instruction: I, descr: &Descriptor) -> MatchResult;
+
+    // Finds the descriptor of a decoded instruction among the ones known by the instruction set
+    fn describe(&self, instruction: I) -> Option<&'static Descriptor>;
+}
+
+// Why a chomp is not an instruction
+#[derive(Clone, Copy, Debug, Display, Eq, Error, PartialEq)]
+pub enum DecodeError {
+    #[display("Unknown opcode {_0:#09b}")]
+    UnknownOpcode(u8),
+    #[display("Unknown funct3 {funct3:#05b} for opcode {opcode}")]
+    BadFunct3 { opcode: Opcode7Table, funct3: u8 },
+    #[display("Unknown funct7 {funct7:#09b} for opcode {opcode} and funct3 {funct3:#05b}")]
+    BadFunct7 { opcode: Opcode7Table, funct3: u8, funct7: u8 },
 }
 
-// Abstract "Tag" trait for the instruction enum types
+// See `Architecture::explain_match`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchResult {
+    Matched,
+    Mismatch(FieldName),
+}
+
+// Abstract "Tag" traits: for the instruction types an `Architecture` decodes into, and for the
+// architectures themselves. Implement them to plug a custom ISA in (see `Architecture`).
 pub trait Instruction {}
 pub trait InstructionSet {}
 
@@ -54,19 +136,66 @@ impl RV32I {
     pub const fn name(&self) -> &str { "RV32I" }
 }
 
+// The "A" (atomic instructions) extension, on top of RV32I
+pub struct RV32A;
+impl InstructionSet for RV32A {}
+impl RV32A {
+    pub const fn name(&self) -> &str { "RV32A" }
+}
+
+// The "M" (integer multiplication and division) extension, on top of RV32I
+pub struct RV32M;
+impl InstructionSet for RV32M {}
+impl RV32M {
+    pub const fn name(&self) -> &str { "RV32M" }
+}
+
+// The "Zicsr" (control and status register instructions) extension, on top of RV32I
+pub struct Zicsr;
+impl InstructionSet for Zicsr {}
+impl Zicsr {
+    pub const fn name(&self) -> &str { "Zicsr" }
+}
+
+// Standard extensions a machine can be built with, on top of the base integer instruction set
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Ext {
+    M,     // Integer multiplication and division
+    A,     // Atomic instructions
+    C,     // Compressed instructions
+    Zicsr, // Control and status register instructions
+    E,     // RV32E: the embedded base, with only x0 to x15
+}
+
+impl Ext {
+    // E takes registers away rather than adding instructions, so it is left out
+    pub const ALL: [Ext; 4] = [Ext::M, Ext::A, Ext::C, Ext::Zicsr];
+}
+
 pub struct RV64I;
 impl InstructionSet for RV64I {}
 impl RV64I {
     pub const fn name(&self) -> &str { "RV64I" }
 }
 
-#[derive(Debug, Kinded, PartialEq)]
-#[kinded(kind = InstructionKind)]
+// Width of the integer registers a hart runs with. So far, an XLEN of 64 only brings in the doubleword
+// loads and stores of RV64I (see `RV64I`): the ALU still computes on 32 bits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Xlen {
+    #[default]
+    X32,
+    X64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Kinded, PartialEq)]
+#[cfg_attr(not(feature = "serde"), kinded(kind = InstructionKind))]
+#[cfg_attr(feature = "serde", kinded(kind = InstructionKind, derive(Deserialize, Serialize)))]
 #[repr(u8)]
-pub enum Format<I, R, J, B, S, F> {
+pub enum Format<I, R, J, B, S, F, A, U> {
     IntegerRegisterImmediate(I),     // I Type
     IntegerRegisterRegister(R),      // R Type
     UnconditionalJump(J),            // J Type
+    JumpAndLinkRegister(I),          // I Type
     ConditionBranch(B),              // B Type
     Load(I),                         // I Type
     Store(S),                        // S Type
@@ -74,53 +203,168 @@ pub enum Format<I, R, J, B, S, F> {
     ControlAndStatusRegister(I),     // I Type
     TimeAndCounter(I),               // I Type
     EnvironmentCallAndBreakpoint(R), // R Type-like ... TODO review
+    AtomicMemoryOperation(A),        // R Type-like (A Type)
+    UpperImmediate(U),               // U Type (LUI and AUIPC)
 }
 
 // TODO: move to rv32i.rs
-pub type RV32Instruction =
-    Format<IType32Bitfield, RType32Bitfield, JType32Bitfield, BType32Bitfield, SType32Bitfield, IFenceType32Bitfield>;
+pub type RV32Instruction = Format<
+    IType32Bitfield,
+    RType32Bitfield,
+    JType32Bitfield,
+    BType32Bitfield,
+    SType32Bitfield,
+    IFenceType32Bitfield,
+    AType32Bitfield,
+    UType32Bitfield,
+>;
 impl Instruction for RV32Instruction {}
 
+// The fields an instruction format is made of, as reported by `RV32Instruction::fields`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FieldName {
+    Opcode,
+    Rd,
+    Funct3,
+    Rs1,
+    Rs2,
+    Funct5, // atomic instructions only, whose funct7 also holds the aq and rl bits
+    Funct7,
+    Imm,
+}
+
+impl RV32Instruction {
+    // Same as `Kinded::kind`, without having to import the trait
+    pub fn kind(&self) -> InstructionKind { Kinded::kind(self) }
+
+    pub fn is_nop(&self) -> bool {
+        matches!(self, RV32Instruction::IntegerRegisterImmediate(i_type) if i_type.raw_value() == NOP)
+    }
+
+    // Lists the fields present in the format of the instruction, with their values. Immediates are
+    // rebuilt and sign-extended (see `IType32Bitfield::immediate`), except for CSR numbers and the funct12
+    // telling ECALL, EBREAK and the like apart, which are unsigned; a fence reports its fm, pred and succ
+    // bits as a single immediate.
+    pub fn fields(&self) -> Vec<(FieldName, u64)> {
+        use FieldName as F;
+
+        let i_type = |i_type: IType32Bitfield, imm: u64| {
+            vec![
+                (F::Opcode, i_type.opcode().value() as u64),
+                (F::Rd, i_type.rd().value() as u64),
+                (F::Funct3, i_type.funct3().value() as u64),
+                (F::Rs1, i_type.rs1().value() as u64),
+                (F::Imm, imm),
+            ]
+        };
+
+        match *self {
+            RV32Instruction::IntegerRegisterImmediate(i)
+            | RV32Instruction::JumpAndLinkRegister(i)
+            | RV32Instruction::Load(i) => i_type(i, i.immediate() as u64),
+            RV32Instruction::ControlAndStatusRegister(i) | RV32Instruction::TimeAndCounter(i) => {
+                i_type(i, i.imm().value() as u64)
+            }
+            // Though decoded as an R-type, an I-type whose immediate is funct12
+            RV32Instruction::EnvironmentCallAndBreakpoint(r) => vec![
+                (F::Opcode, r.opcode().value() as u64),
+                (F::Rd, r.rd().value() as u64),
+                (F::Funct3, r.funct3().value() as u64),
+                (F::Rs1, r.rs1().value() as u64),
+                (F::Imm, (r.raw_value() >> 20) as u64),
+            ],
+            RV32Instruction::IntegerRegisterRegister(r) => vec![
+                (F::Opcode, r.opcode().value() as u64),
+                (F::Rd, r.rd().value() as u64),
+                (F::Funct3, r.funct3().value() as u64),
+                (F::Rs1, r.rs1().value() as u64),
+                (F::Rs2, r.rs2().value() as u64),
+                (F::Funct7, r.funct7().value() as u64),
+            ],
+            RV32Instruction::UnconditionalJump(j) => vec![
+                (F::Opcode, j.opcode().value() as u64),
+                (F::Rd, j.rd().value() as u64),
+                (F::Imm, j.immediate() as u64),
+            ],
+            RV32Instruction::UpperImmediate(u) => vec![
+                (F::Opcode, u.opcode().value() as u64),
+                (F::Rd, u.rd().value() as u64),
+                (F::Imm, u.immediate() as u64),
+            ],
+            RV32Instruction::ConditionBranch(b) => vec![
+                (F::Opcode, b.opcode().value() as u64),
+                (F::Funct3, b.funct3().value() as u64),
+                (F::Rs1, b.rs1().value() as u64),
+                (F::Rs2, b.rs2().value() as u64),
+                (F::Imm, b.immediate() as u64),
+            ],
+            RV32Instruction::Store(s) => vec![
+                (F::Opcode, s.opcode().value() as u64),
+                (F::Funct3, s.funct3().value() as u64),
+                (F::Rs1, s.rs1().value() as u64),
+                (F::Rs2, s.rs2().value() as u64),
+                (F::Imm, s.immediate() as u64),
+            ],
+            RV32Instruction::Fence(f) => vec![
+                (F::Opcode, f.opcode().value() as u64),
+                (F::Rd, f.rd().value() as u64),
+                (F::Funct3, f.funct3().value() as u64),
+                (F::Rs1, f.rs1().value() as u64),
+                (F::Imm, (f.raw_value() >> 20) as u64),
+            ],
+            RV32Instruction::AtomicMemoryOperation(a) => vec![
+                (F::Opcode, a.opcode().value() as u64),
+                (F::Rd, a.rd().value() as u64),
+                (F::Funct3, a.funct3().value() as u64),
+                (F::Rs1, a.rs1().value() as u64),
+                (F::Rs2, a.rs2().value() as u64),
+                (F::Funct5, a.funct5().value() as u64),
+            ],
+        }
+    }
+
+    // Where the fields of `fields` lie in the encoding, as ranges of bit positions, e.g. to highlight
+    // them. Immediates scattered over several ranges list them from their most significant bits on:
+    // the 13-bit immediate of a branch is imm[12] (bit 31), imm[11] (bit 7), then imm[10:5] and imm[4:1].
+    pub fn field_bits(&self) -> Vec<(FieldName, RangeInclusive<u32>)> {
+        use FieldName as F;
+
+        let opcode = (F::Opcode, 0..=6);
+        let rd = (F::Rd, 7..=11);
+        let funct3 = (F::Funct3, 12..=14);
+        let rs1 = (F::Rs1, 15..=19);
+        let rs2 = (F::Rs2, 20..=24);
+
+        match self {
+            RV32Instruction::IntegerRegisterImmediate(_)
+            | RV32Instruction::JumpAndLinkRegister(_)
+            | RV32Instruction::Load(_)
+            | RV32Instruction::ControlAndStatusRegister(_)
+            | RV32Instruction::TimeAndCounter(_)
+            | RV32Instruction::EnvironmentCallAndBreakpoint(_)
+            | RV32Instruction::Fence(_) => vec![opcode, rd, funct3, rs1, (F::Imm, 20..=31)],
+            RV32Instruction::IntegerRegisterRegister(_) => vec![opcode, rd, funct3, rs1, rs2, (F::Funct7, 25..=31)],
+            RV32Instruction::UnconditionalJump(_) => {
+                vec![opcode, rd, (F::Imm, 31..=31), (F::Imm, 12..=19), (F::Imm, 20..=20), (F::Imm, 21..=30)]
+            }
+            RV32Instruction::UpperImmediate(_) => vec![opcode, rd, (F::Imm, 12..=31)],
+            RV32Instruction::ConditionBranch(_) => {
+                vec![opcode, funct3, rs1, rs2, (F::Imm, 31..=31), (F::Imm, 7..=7), (F::Imm, 25..=30), (F::Imm, 8..=11)]
+            }
+            RV32Instruction::Store(_) => vec![opcode, funct3, rs1, rs2, (F::Imm, 25..=31), (F::Imm, 7..=11)],
+            RV32Instruction::AtomicMemoryOperation(_) => vec![opcode, rd, funct3, rs1, rs2, (F::Funct5, 27..=31)],
+        }
+    }
+}
+
 // TODO
 #[derive(Debug, Display, PartialEq)]
 pub enum RV64Instruction {}
 impl Instruction for RV64Instruction {}
 
 impl Architecture<Word, RV32Instruction> for RV32I {
-    fn decode(&self, chomp: Self::Chomp) -> Option<Self::Instruction> {
-        let union = ChompRV32 { raw: chomp };
-
-        unsafe {
-            let opcode: Opcode7 = union.integer_register_register.opcode();
-            let opcode: Option<Opcode7Table> = opcode.value().try_into().ok();
-
-            match opcode {
-                Some(Opcode7Table::OpImmediate) => Some(RV32Instruction::IntegerRegisterImmediate(
-                    union.integer_register_immediate,
-                )),
-                Some(Opcode7Table::OpRegister) => Some(RV32Instruction::IntegerRegisterRegister(
-                    union.integer_register_register,
-                )),
-                Some(Opcode7Table::JumpAndLink) => Some(RV32Instruction::UnconditionalJump(union.unconditional_jump)),
-                Some(Opcode7Table::Branch) => Some(RV32Instruction::ConditionBranch(union.condition_branch)),
-                Some(Opcode7Table::LoadUpperImmediate) | Some(Opcode7Table::AddUpperImmediatePC) => {
-                    Some(RV32Instruction::Load(union.load))
-                }
-                Some(Opcode7Table::Store) => Some(RV32Instruction::Store(union.store)),
-
-                // Not used in RV32I:
-                //
-                // Some(Opcode7Table::Fence) => Some(InstructionFormat32::Fence(union)),
-                // Some(Opcode7Table::ControlAndStatusRegister) => {
-                //     Some(InstructionFormat32::ControlAndStatusRegister(union))
-                // }
-                // Some(Opcode7Table::TimeAndCounter) => Some(InstructionFormat32::TimeAndCounter(union)),
-                // Some(Opcode7Table::EnvironmentCallAndBreakpoint) => {
-                //     Some(InstructionFormat32::EnvironmentCallAndBreakpoint(union))
-                // }
-                _ => None,
-            }
-        }
+    fn decode(&self, chomp: Self::Chomp) -> Result<Self::Instruction, DecodeError> {
+        decode_formats(chomp, false)
     }
 
     fn get_opcode(&self, instruction: RV32Instruction) -> Opcode7 {
@@ -128,6 +372,7 @@ impl Architecture<Word, RV32Instruction> for RV32I {
             RV32Instruction::IntegerRegisterImmediate(chomp) => chomp.opcode(),
             RV32Instruction::IntegerRegisterRegister(chomp) => chomp.opcode(),
             RV32Instruction::UnconditionalJump(chomp) => chomp.opcode(),
+            RV32Instruction::JumpAndLinkRegister(chomp) => chomp.opcode(),
             RV32Instruction::ConditionBranch(chomp) => chomp.opcode(),
             RV32Instruction::Load(chomp) => chomp.opcode(),
             RV32Instruction::Store(chomp) => chomp.opcode(),
@@ -135,39 +380,353 @@ impl Architecture<Word, RV32Instruction> for RV32I {
             RV32Instruction::ControlAndStatusRegister(chomp) => chomp.opcode(),
             RV32Instruction::TimeAndCounter(chomp) => chomp.opcode(),
             RV32Instruction::EnvironmentCallAndBreakpoint(chomp) => chomp.opcode(),
+            RV32Instruction::AtomicMemoryOperation(chomp) => chomp.opcode(),
+            RV32Instruction::UpperImmediate(chomp) => chomp.opcode(),
         };
 
         opcode
     }
 
-    fn match_instruction(&self, instruction: RV32Instruction, descr: Descriptor) -> bool {
-        let check_o7f3f7 = |opcode: Opcode7, funct3: Funct3, funct7: Funct7| -> bool {
-            descr.opcode == opcode.try_into().ok()
-                // TODO: missing information to convert funct3 into Funct3Expr (same value can be in different tables)
-                && descr.funct3 == funct3.try_into().ok()
-                && descr.funct7 == funct7.try_into().ok()
+    // funct7 is checked before funct3, whose meaning it may change (e.g. ADD and SUB): both instructions
+    // have the same funct3 bits, but differ by their funct7
+    fn explain_match(&self, instruction: RV32Instruction, descr: &Descriptor) -> MatchResult {
+        use FieldName as F;
+
+        let first_mismatch = |checks: &[(FieldName, bool)]| {
+            checks
+                .iter()
+                .find(|(_, matched)| !matched)
+                .map_or(MatchResult::Matched, |&(field, _)| MatchResult::Mismatch(field))
+        };
+
+        let fields = |opcode: Opcode7, funct3: Funct3, funct7: Option<Funct7>| {
+            [
+                (F::Opcode, descr.opcode == opcode.try_into().ok()),
+                (F::Funct7, descr.funct7 == funct7.and_then(|funct7| funct7.value().try_into().ok())),
+                (F::Funct3, descr.funct3 == Funct3Expr::try_from(opcode, funct3, funct7).ok()),
+            ]
         };
 
-        let check_o7f3 = |opcode: Opcode7, funct3: Funct3| -> bool {
-            descr.opcode == opcode.try_into().ok() && descr.funct3 == funct3.try_into().ok() && descr.funct7.is_none()
+        let check_o7f3f7 = |opcode: Opcode7, funct3: Funct3, funct7: Funct7| {
+            first_mismatch(&fields(opcode, funct3, Some(funct7)))
         };
 
+        let check_o7f3 = |opcode: Opcode7, funct3: Funct3| first_mismatch(&fields(opcode, funct3, None));
+
         match instruction {
+            // See SLLI: shifts by an immediate keep a funct7 in the upper bits of the immediate
+            RV32Instruction::IntegerRegisterImmediate(i_type) if descr.funct7.is_some() => {
+                check_o7f3f7(i_type.opcode(), i_type.funct3(), Funct7::new((i_type.imm().value() >> 5) as u8))
+            }
+
             RV32Instruction::Load(i_type)
+            | RV32Instruction::JumpAndLinkRegister(i_type)
             | RV32Instruction::ControlAndStatusRegister(i_type)
             | RV32Instruction::TimeAndCounter(i_type)
             | RV32Instruction::IntegerRegisterImmediate(i_type) => check_o7f3(i_type.opcode(), i_type.funct3()),
 
-            RV32Instruction::UnconditionalJump(j_type) => j_type.opcode().try_into().ok() == descr.opcode,
+            RV32Instruction::UnconditionalJump(j_type) => {
+                first_mismatch(&[(F::Opcode, j_type.opcode().try_into().ok() == descr.opcode)])
+            }
+            RV32Instruction::UpperImmediate(u_type) => {
+                first_mismatch(&[(F::Opcode, u_type.opcode().try_into().ok() == descr.opcode)])
+            }
 
-            RV32Instruction::IntegerRegisterRegister(r_type)
-            | RV32Instruction::EnvironmentCallAndBreakpoint(r_type) => {
+            RV32Instruction::IntegerRegisterRegister(r_type) => {
                 check_o7f3f7(r_type.opcode(), r_type.funct3(), r_type.funct7())
             }
 
+            // The immediate (funct12) tells ECALL, EBREAK, MRET, SRET and WFI apart, funct7 and rs2 being
+            // part of it
+            RV32Instruction::EnvironmentCallAndBreakpoint(r_type) => {
+                let imm11 = Immediate11Table::try_from((r_type.raw_value() >> 20) as u16).ok();
+
+                match check_o7f3(r_type.opcode(), r_type.funct3()) {
+                    MatchResult::Matched => first_mismatch(&[(F::Imm, descr.imm11 == imm11)]),
+                    mismatch => mismatch,
+                }
+            }
+
             RV32Instruction::ConditionBranch(b_type) => check_o7f3(b_type.opcode(), b_type.funct3()),
             RV32Instruction::Store(s_type) => check_o7f3(s_type.opcode(), s_type.funct3()),
             RV32Instruction::Fence(i_fence_type) => check_o7f3(i_fence_type.opcode(), i_fence_type.funct3()),
+
+            // funct5 tells the atomic operations apart; aq and rl only order them
+            RV32Instruction::AtomicMemoryOperation(a_type) => match check_o7f3(a_type.opcode(), a_type.funct3()) {
+                MatchResult::Matched => {
+                    first_mismatch(&[(F::Funct5, descr.funct5 == a_type.funct5().value().try_into().ok())])
+                }
+                mismatch => mismatch,
+            },
+        }
+    }
+
+    fn describe(&self, instruction: RV32Instruction) -> Option<&'static Descriptor> {
+        RV32I_INSTRUCTIONS
+            .iter()
+            .chain(RV32M_INSTRUCTIONS)
+            .chain(RV32A_INSTRUCTIONS)
+            .chain(ZICSR_INSTRUCTIONS)
+            .chain(PRIVILEGED_INSTRUCTIONS)
+            .filter(|descr| descr.format == instruction.kind())
+            .find(|descr| self.match_instruction(instruction, descr))
+    }
+}
+
+// RV64I, as far as it goes: RV32I (and its extensions), plus the doubleword loads and stores, decoded
+// into the same formats. Only harts running with an XLEN of 64 decode with it (see `Xlen`).
+impl Architecture<Word, RV32Instruction> for RV64I {
+    fn decode(&self, chomp: Self::Chomp) -> Result<Self::Instruction, DecodeError> { decode_formats(chomp, true) }
+
+    fn get_opcode(&self, instruction: RV32Instruction) -> Opcode7 { RV32I.get_opcode(instruction) }
+
+    fn explain_match(&self, instruction: RV32Instruction, descr: &Descriptor) -> MatchResult {
+        RV32I.explain_match(instruction, descr)
+    }
+
+    fn describe(&self, instruction: RV32Instruction) -> Option<&'static Descriptor> {
+        RV32I
+            .describe(instruction)
+            .or_else(|| RV64I_INSTRUCTIONS.iter().find(|descr| self.match_instruction(instruction, descr)))
+    }
+}
+
+// Decodes the RV32I formats, and the doubleword loads and stores (LD and SD, funct3 = DOUBLEWORD) of
+// RV64I along with them if asked to
+fn decode_formats(chomp: Word, doublewords: bool) -> Result<RV32Instruction, DecodeError> {
+    let union = ChompRV32 { raw: chomp };
+
+    unsafe {
+        let r_type = union.integer_register_register;
+        let opcode = Opcode7Table::try_from(r_type.opcode().value())
+            .map_err(|_| DecodeError::UnknownOpcode(r_type.opcode().value()))?;
+
+        let instruction = match opcode {
+            Opcode7Table::OpImmediate => RV32Instruction::IntegerRegisterImmediate(union.integer_register_immediate),
+            Opcode7Table::OpRegister => RV32Instruction::IntegerRegisterRegister(union.integer_register_register),
+            Opcode7Table::JumpAndLink => RV32Instruction::UnconditionalJump(union.unconditional_jump),
+            Opcode7Table::JumpAndLinkRegister => RV32Instruction::JumpAndLinkRegister(union.jump_and_link_register),
+            Opcode7Table::Branch => RV32Instruction::ConditionBranch(union.condition_branch),
+            Opcode7Table::Load => RV32Instruction::Load(union.load),
+            Opcode7Table::LoadUpperImmediate | Opcode7Table::AddUpperImmediatePC => {
+                RV32Instruction::UpperImmediate(union.upper_immediate)
+            }
+            Opcode7Table::Store => RV32Instruction::Store(union.store),
+            Opcode7Table::MiscMemory => RV32Instruction::Fence(union.fence),
+
+            Opcode7Table::AtomicMemoryOp => RV32Instruction::AtomicMemoryOperation(union.atomic_memory_operation),
+
+            // ECALL and EBREAK; the remaining System encodings (funct3 != 0) are the CSR instructions
+            Opcode7Table::System if r_type.funct3().value() == 0 => {
+                RV32Instruction::EnvironmentCallAndBreakpoint(union.environment_call_and_breakpoint)
+            }
+            Opcode7Table::System => RV32Instruction::ControlAndStatusRegister(union.control_and_status_register),
+
+            // Not used in RV32I:
+            //
+            // Opcode7Table::TimeAndCounter => InstructionFormat32::TimeAndCounter(union),
+            _ => return Err(DecodeError::UnknownOpcode(opcode.into())),
+        };
+
+        match opcode {
+            // No funct3 to check: the bits are part of the immediate
+            Opcode7Table::JumpAndLink | Opcode7Table::LoadUpperImmediate | Opcode7Table::AddUpperImmediatePC => {}
+            Opcode7Table::Load | Opcode7Table::Store if !doublewords && r_type.funct3().value() == DOUBLEWORD => {
+                return Err(DecodeError::BadFunct3 {
+                    opcode,
+                    funct3: DOUBLEWORD,
+                });
+            }
+            // FENCE is the only instruction of its opcode (FENCE.I belongs to Zifencei)
+            Opcode7Table::MiscMemory if r_type.funct3().value() != 0 => {
+                return Err(DecodeError::BadFunct3 {
+                    opcode,
+                    funct3: r_type.funct3().value(),
+                });
+            }
+            Opcode7Table::MiscMemory => {}
+            _ => check_funct3(opcode, r_type)?,
         }
+
+        Ok(instruction)
+    }
+}
+
+// funct7 only selects among the instructions of the OP opcode (e.g. ADD and SUB); elsewhere, it is
+// part of an immediate or of other fields
+fn check_funct3(opcode: Opcode7Table, r_type: RType32Bitfield) -> Result<(), DecodeError> {
+    let funct3 = r_type.funct3();
+    let funct7 = (opcode == Opcode7Table::OpRegister).then(|| r_type.funct7());
+
+    match Funct3Expr::try_from(opcode.into(), funct3, funct7) {
+        Ok(expr) if expr.is_known() => Ok(()),
+        Ok(_) => Err(DecodeError::BadFunct3 {
+            opcode,
+            funct3: funct3.value(),
+        }),
+        Err(_) => Err(DecodeError::BadFunct7 {
+            opcode,
+            funct3: funct3.value(),
+            funct7: r_type.funct7().value(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::disassembler::disassemble;
+    use crate::instruction::{ADD, ADDI, AMOADD_W, AMOSWAP_W, EBREAK, ECALL, SLL, SUB};
+
+    #[test]
+    fn should_decode_the_same_word_to_equal_instructions() {
+        let addi = RV32I.decode(0x0050_0093).unwrap(); // addi x1, x0, 5
+
+        assert_eq!(RV32I.decode(0x0050_0093).unwrap(), addi);
+        assert_ne!(RV32I.decode(0x0060_0093).unwrap(), addi); // addi x1, x0, 6
+    }
+
+    #[test]
+    fn should_decode_loads_as_i_types_and_upper_immediates_as_u_types() {
+        let lw = RV32I.decode(0x0001_2083).unwrap(); // lw x1, 0(x2)
+        let lui = RV32I.decode(0x1234_50B7).unwrap(); // lui x1, 0x12345
+        let auipc = RV32I.decode(0x0000_1117).unwrap(); // auipc x2, 0x1
+
+        assert_eq!(lw.kind(), InstructionKind::Load);
+        assert_eq!(lui.kind(), InstructionKind::UpperImmediate);
+        assert_eq!(auipc.kind(), InstructionKind::UpperImmediate);
+        assert_eq!(lui.fields(), [(FieldName::Opcode, 0b0110111), (FieldName::Rd, 1), (FieldName::Imm, 0x1234_5000)]);
+        assert_eq!(disassemble(&auipc), "auipc x2, 0x1");
+    }
+
+    #[test]
+    fn should_report_the_kind_of_a_decoded_store() {
+        let sw = RV32I.decode(0x0011_2223).unwrap(); // sw x1, 4(x2)
+
+        assert_eq!(sw.kind(), InstructionKind::Store);
+        assert_eq!(RV32I.describe(sw).map(|descr| descr.format), Some(InstructionKind::Store));
+    }
+
+    #[test]
+    fn should_tell_ecall_from_ebreak_by_imm11_alone() {
+        let ecall = RV32I.decode(0x0000_0073).unwrap();
+        let ebreak = RV32I.decode(0x0010_0073).unwrap();
+
+        assert_eq!(
+            Descriptor {
+                name: EBREAK.name,
+                mnemonic: EBREAK.mnemonic,
+                imm11: EBREAK.imm11,
+                ..ECALL
+            },
+            EBREAK
+        );
+        assert_eq!(RV32I.describe(ecall), Some(&ECALL));
+        assert_eq!(RV32I.describe(ebreak), Some(&EBREAK));
+        assert_eq!(RV32I.explain_match(ecall, &EBREAK), MatchResult::Mismatch(FieldName::Imm));
+    }
+
+    #[test]
+    fn should_decode_the_system_instructions_apart() {
+        let decode = |word| RV32I.describe(RV32I.decode(word).unwrap()).map(|descr| descr.mnemonic);
+
+        assert_eq!(decode(0x0000_0073), Some("ecall"));
+        assert_eq!(decode(0x0010_0073), Some("ebreak"));
+        assert_eq!(decode(0x3020_0073), Some("mret"));
+        assert_eq!(decode(0x1020_0073), Some("sret"));
+        assert_eq!(decode(0x1050_0073), Some("wfi"));
+        assert_eq!(decode(0x7FF0_0073), None);
+    }
+
+    #[test]
+    fn should_explain_which_field_does_not_match() {
+        let add = RV32I.decode(0x0020_80B3).unwrap(); // add x1, x1, x2
+
+        assert_eq!(RV32I.explain_match(add, &ADD), MatchResult::Matched);
+        assert_eq!(RV32I.explain_match(add, &SUB), MatchResult::Mismatch(FieldName::Funct7));
+        assert_eq!(RV32I.explain_match(add, &SLL), MatchResult::Mismatch(FieldName::Funct3));
+        assert_eq!(RV32I.explain_match(add, &ADDI), MatchResult::Mismatch(FieldName::Opcode));
+        assert_eq!(RV32I.explain_match(add, &AMOADD_W), MatchResult::Mismatch(FieldName::Opcode));
+    }
+
+    #[test]
+    fn should_match_atomics_by_funct5_only() {
+        let amoadd = RV32I.decode(0x0075_22AF).unwrap(); // amoadd.w x5, x7, (x10)
+        let amoadd_aqrl = RV32I.decode(0x0675_22AF).unwrap();
+        let amoswap = RV32I.decode(0x0875_22AF).unwrap(); // amoswap.w x5, x7, (x10)
+
+        assert!(RV32I.match_instruction(amoadd, &AMOADD_W));
+        assert!(RV32I.match_instruction(amoadd_aqrl, &AMOADD_W));
+        assert!(!RV32I.match_instruction(amoswap, &AMOADD_W));
+        assert!(!RV32I.match_instruction(amoadd, &AMOSWAP_W));
+    }
+
+    #[test]
+    fn should_list_the_fields_of_an_i_type() {
+        let fields = RV32I.decode(0xFFF0_8093).unwrap().fields(); // addi x1, x1, -1
+        let names: Vec<_> = fields.iter().map(|(name, _)| *name).collect();
+
+        assert_eq!(names, [FieldName::Opcode, FieldName::Rd, FieldName::Funct3, FieldName::Rs1, FieldName::Imm]);
+        assert!(fields.contains(&(FieldName::Rd, 1)));
+        assert!(fields.contains(&(FieldName::Imm, u64::MAX)));
+        assert!(!names.contains(&FieldName::Rs2));
+    }
+
+    #[test]
+    fn should_list_the_fields_of_an_r_type() {
+        let fields = RV32I.decode(0x4020_81B3).unwrap().fields(); // sub x3, x1, x2
+
+        assert!(fields.contains(&(FieldName::Rs2, 2)));
+        assert!(fields.contains(&(FieldName::Funct7, 0b0100000)));
+        assert!(!fields.iter().any(|(name, _)| *name == FieldName::Imm));
+    }
+
+    #[test]
+    fn should_list_the_funct12_of_ebreak_as_an_immediate() {
+        let ebreak = RV32I.decode(0x0010_0073).unwrap();
+
+        assert_eq!(ebreak.fields().last(), Some(&(FieldName::Imm, 1)));
+        assert_eq!(ebreak.field_bits().last(), Some(&(FieldName::Imm, 20..=31)));
+        assert!(!ebreak.field_bits().iter().any(|(name, _)| matches!(name, FieldName::Rs2 | FieldName::Funct7)));
+    }
+
+    #[test]
+    fn should_report_a_bad_funct3_under_a_known_opcode() {
+        // funct3 = 0b011 is not a load width
+        assert_eq!(
+            RV32I.decode(0x0000_3083),
+            Err(DecodeError::BadFunct3 {
+                opcode: Opcode7Table::Load,
+                funct3: 0b011,
+            })
+        );
+        assert_eq!(RV32I.decode_opt(0x0000_3083), None);
+    }
+
+    #[test]
+    fn should_report_unknown_opcodes_and_funct7() {
+        assert_eq!(RV32I.decode(0x0000_007F), Err(DecodeError::UnknownOpcode(0b1111111)));
+        assert_eq!(
+            RV32I.decode(0x0420_81B3), // funct7 = 0b0000010 under OP
+            Err(DecodeError::BadFunct7 {
+                opcode: Opcode7Table::OpRegister,
+                funct3: 0,
+                funct7: 0b0000010,
+            })
+        );
+    }
+
+    #[test]
+    fn should_key_maps_by_instruction() {
+        let mut counts = HashMap::new();
+
+        for chomp in [0x0050_0093, 0x0050_0093, 0x0000_0073] {
+            *counts.entry(RV32I.decode(chomp).unwrap()).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts[&RV32I.decode(0x0050_0093).unwrap()], 2);
+        assert_eq!(counts[&RV32I.decode(0x0000_0073).unwrap()], 1);
     }
 }
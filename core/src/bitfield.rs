@@ -21,18 +21,25 @@ use anyhow::{Error, Result};
 use arbitrary_int::{u12, u3, u4, u5, u7};
 use bitbybit::bitfield;
 use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-use crate::impl_common_bitfield_traits;
+use crate::memory::InstructionLength;
+use crate::{enum_aliases, impl_common_bitfield_traits, impl_mnemonic_display};
 
 // TODO: bitbybit does not work with type aliases
 pub type Opcode7 = u7;
 pub type Funct3 = u3;
+pub type Funct5 = u5;
 pub type Funct7 = u7;
 pub type Rd5 = u5;
 pub type Rs5 = u5;
 pub type Immediate12 = u12;
 
+const UNKNOWN_MNEMONIC: &str = "unknown";
+
 #[bitfield(u32, default = 0)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct RType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -48,8 +55,30 @@ pub struct RType32Bitfield {
     funct7: Funct7,
 }
 
+// Note: an RType32 whose funct7 is split in funct5 and the aq/rl ordering bits
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
+pub struct AType32Bitfield {
+    #[bits(0..=6, rw)]
+    opcode: Opcode7,
+    #[bits(7..=11, rw)]
+    rd: Rd5,
+    #[bits(12..=14, rw)]
+    funct3: Funct3,
+    #[bits(15..=19, rw)]
+    rs1: Rs5,
+    #[bits(20..=24, rw)]
+    rs2: Rs5,
+    #[bit(25, rw)]
+    rl: bool,
+    #[bit(26, rw)]
+    aq: bool,
+    #[bits(27..=31, rw)]
+    funct5: Funct5,
+}
+
+#[bitfield(u32, default = 0)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct IType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -64,7 +93,7 @@ pub struct IType32Bitfield {
 }
 
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct IFenceType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -95,7 +124,7 @@ pub struct IFenceType32Bitfield {
 }
 
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct SType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -111,7 +140,7 @@ pub struct SType32Bitfield {
 
 // Note: similar to SType32, just the imm bits are different
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct BType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -121,25 +150,23 @@ pub struct BType32Bitfield {
     rs1: Rs5,
     #[bits(20..=24, rw)]
     rs2: Rs5,
-    // TODO: Implement imm: must shift left by 1 bit
     #[bits([8..=11, 25..=30, 7, 31], rw)]
     imm_raw: Immediate12,
 }
 
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct UType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
     #[bits(7..=11, rw)]
     rd: Rd5,
-    // TODO: Implement imm: must shift left by 12 bits
     #[bits([12..=31], rw)]
     imm_raw: Immediate12,
 }
 
 #[bitfield(u32, default = 0)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq)]
 pub struct JType32Bitfield {
     #[bits(0..=6, rw)]
     opcode: Opcode7,
@@ -149,7 +176,40 @@ pub struct JType32Bitfield {
     imm_raw: Immediate12,
 }
 
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+// The immediate accessors below rebuild the full, sign-extended immediate of each format from the
+// raw instruction bits, as laid out in the spec (see "Immediate Encoding Variants").
+
+impl IType32Bitfield {
+    pub fn immediate(&self) -> i64 { sign_extend(self.raw_value() >> 20, 12) }
+}
+
+impl SType32Bitfield {
+    pub fn immediate(&self) -> i64 {
+        let raw = self.raw_value();
+        sign_extend((raw >> 25) << 5 | (raw >> 7 & 0x1F), 12)
+    }
+}
+
+impl BType32Bitfield {
+    pub fn immediate(&self) -> i64 {
+        let raw = self.raw_value();
+        sign_extend((raw >> 31) << 12 | (raw >> 7 & 1) << 11 | (raw >> 25 & 0x3F) << 5 | (raw >> 8 & 0xF) << 1, 13)
+    }
+}
+
+impl UType32Bitfield {
+    pub fn immediate(&self) -> i64 { sign_extend(self.raw_value() & 0xFFFF_F000, 32) }
+}
+
+impl JType32Bitfield {
+    pub fn immediate(&self) -> i64 {
+        let raw = self.raw_value();
+        sign_extend((raw >> 31) << 20 | (raw >> 12 & 0xFF) << 12 | (raw >> 20 & 1) << 11 | (raw >> 21 & 0x3FF) << 1, 21)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[repr(u8)]
 pub enum Opcode7Table {
     Zero                = 0,
@@ -185,8 +245,9 @@ pub enum Opcode7Table {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
-enum Funct3JALRTable {
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3JALRTable {
     JALR = 0b000, // 0
 
     #[num_enum(catch_all)]
@@ -194,8 +255,9 @@ enum Funct3JALRTable {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
-enum Funct3BranchTable {
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3BranchTable {
     BEQ  = 0b000, // 0
     BNE  = 0b001, // 1
     BLT  = 0b100, // 4
@@ -208,11 +270,13 @@ enum Funct3BranchTable {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
-enum Funct3LoadTable {
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3LoadTable {
     LB  = 0b000, // 0
     LH  = 0b001, // 1
     LW  = 0b010, // 2
+    LD  = 0b011, // 3 (RV64I)
     LBU = 0b100, // 4
     LHU = 0b101, // 5
 
@@ -221,17 +285,20 @@ enum Funct3LoadTable {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
-enum Funct3StoreTable {
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3StoreTable {
     SB = 0b000, // 0
     SH = 0b001, // 1
     SW = 0b010, // 2
+    SD = 0b011, // 3 (RV64I)
 
     #[num_enum(catch_all)]
     Unknown(u8),
 }
 
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[repr(u8)]
 //#[EnumAlias(SUB = ADD, SRL = SRA)]
 pub enum Funct3OpRegisterTable {
@@ -249,9 +316,10 @@ pub enum Funct3OpRegisterTable {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 //#[EnumAlias(SRLI = SRAI)]
-enum Funct3OpImmediateTable {
+pub enum Funct3OpImmediateTable {
     ADDI  = 0b000, // 0
     SLLI  = 0b001, // 1
     SLTI  = 0b010, // 2
@@ -266,16 +334,51 @@ enum Funct3OpImmediateTable {
 }
 
 #[repr(u8)]
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 //#[EnumAlias(EBREAK = ECALL)]
-enum Funct3SystemTable {
-    ECALL = 0b000, // 0
+pub enum Funct3SystemTable {
+    ECALL  = 0b000, // 0
+    CSRRW  = 0b001, // 1
+    CSRRS  = 0b010, // 2
+    CSRRC  = 0b011, // 3
+    CSRRWI = 0b101, // 5
+    CSRRSI = 0b110, // 6
+    CSRRCI = 0b111, // 7
 
     #[num_enum(catch_all)]
     Unknown(u8),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3AtomicTable {
+    W = 0b010, // 2
+
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum Funct3MulDivTable {
+    MUL    = 0b000, // 0
+    MULH   = 0b001, // 1
+    MULHSU = 0b010, // 2
+    MULHU  = 0b011, // 3
+    DIV    = 0b100, // 4
+    DIVU   = 0b101, // 5
+    REM    = 0b110, // 6
+    REMU   = 0b111, // 7
+
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[repr(u8)]
 pub enum Funct3Expr {
     JumpAndLinkRegister(Funct3JALRTable),
@@ -283,72 +386,577 @@ pub enum Funct3Expr {
     Load(Funct3LoadTable),
     Store(Funct3StoreTable),
     OpRegister(Funct3OpRegisterTable),
+    OpRegisterArithmetic(Funct3OpRegisterTable), // SUB and SRA (funct7 = Funct7Table::Arithmetic)
+    MulDiv(Funct3MulDivTable),                   // M extension (funct7 = Funct7Table::MulDiv)
     OpImmediate(Funct3OpImmediateTable),
     System(Funct3SystemTable),
+    Atomic(Funct3AtomicTable),
     Unknown(u8),
 }
 
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[repr(u8)]
 pub enum Funct7Table {
     Logical    = 0,
     Arithmetic = 0b0100000,
+    MulDiv     = 0b0000001,
+
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[repr(u8)]
+pub enum Funct5AtomicTable {
+    AMOADD  = 0b00000, // 0
+    AMOSWAP = 0b00001, // 1
+    LR      = 0b00010, // 2
+    SC      = 0b00011, // 3
+    AMOXOR  = 0b00100, // 4
+    AMOOR   = 0b01000, // 8
+    AMOAND  = 0b01100, // 12
+    AMOMIN  = 0b10000, // 16
+    AMOMAX  = 0b10100, // 20
+    AMOMINU = 0b11000, // 24
+    AMOMAXU = 0b11100, // 28
 
     #[num_enum(catch_all)]
     Unknown(u8),
 }
 
 // TODO: Identify variants
-#[derive(Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 #[repr(u16)]
 pub enum Immediate11Table {
+    // SYSTEM instructions with funct3 = 0 are told apart by their whole immediate (funct12)
+    ECALL  = 0b0000_0000_0000,
+    EBREAK = 0b0000_0000_0001,
+    SRET   = 0b0001_0000_0010,
+    WFI    = 0b0001_0000_0101,
+    MRET   = 0b0011_0000_0010,
+
     #[num_enum(catch_all)]
     Unknown(u16),
 }
 
-impl_common_bitfield_traits!(RType32Bitfield);
-impl_common_bitfield_traits!(IType32Bitfield);
-impl_common_bitfield_traits!(IFenceType32Bitfield);
-impl_common_bitfield_traits!(SType32Bitfield);
-impl_common_bitfield_traits!(BType32Bitfield);
-impl_common_bitfield_traits!(UType32Bitfield);
-impl_common_bitfield_traits!(JType32Bitfield);
+impl_common_bitfield_traits!(RType32Bitfield: OpRegister | System);
+impl_common_bitfield_traits!(AType32Bitfield: AtomicMemoryOp);
+impl_common_bitfield_traits!(IType32Bitfield: OpImmediate | Load | JumpAndLinkRegister | System);
+impl_common_bitfield_traits!(IFenceType32Bitfield: MiscMemory);
+impl_common_bitfield_traits!(SType32Bitfield: Store);
+impl_common_bitfield_traits!(BType32Bitfield: Branch);
+impl_common_bitfield_traits!(UType32Bitfield: LoadUpperImmediate | AddUpperImmediatePC);
+impl_common_bitfield_traits!(JType32Bitfield: JumpAndLink);
+
+// funct7 tells these apart (see Funct7Table):
+enum_aliases!(Funct3OpRegisterTable: SUB = ADD, SRL = SRA);
+
+// The immediate tells these apart (see Immediate11Table):
+enum_aliases!(Funct3OpImmediateTable: SRLI = SRAI);
 
 impl Into<Funct3> for Funct3Expr {
     fn into(self) -> Funct3 {
-        match self {
+        let funct3: u8 = match self {
             Funct3Expr::JumpAndLinkRegister(funct3) => funct3.into(),
             Funct3Expr::Branch(funct3) => funct3.into(),
             Funct3Expr::Load(funct3) => funct3.into(),
             Funct3Expr::Store(funct3) => funct3.into(),
             Funct3Expr::OpRegister(funct3) => funct3.into(),
+            Funct3Expr::OpRegisterArithmetic(funct3) => funct3.into(),
+            Funct3Expr::MulDiv(funct3) => funct3.into(),
             Funct3Expr::OpImmediate(funct3) => funct3.into(),
             Funct3Expr::System(funct3) => funct3.into(),
+            Funct3Expr::Atomic(funct3) => funct3.into(),
 
             // TODO: log this match?
-            Funct3Expr::Unknown(funct3) => funct3.into(),
-        }
+            Funct3Expr::Unknown(funct3) => funct3,
+        };
+
+        Funct3::masked_new(funct3)
     }
 }
 
 impl Funct3Expr {
-    fn try_from(opcode7: Opcode7, funct3: Funct3) -> Result<Funct3Expr> {
+    pub fn encode(&self) -> Funct3 { (*self).into() }
+
+    // Whether the funct3 names an instruction of its opcode
+    pub fn is_known(&self) -> bool {
+        !matches!(
+            self,
+            Funct3Expr::JumpAndLinkRegister(Funct3JALRTable::Unknown(_))
+                | Funct3Expr::Branch(Funct3BranchTable::Unknown(_))
+                | Funct3Expr::Load(Funct3LoadTable::Unknown(_))
+                | Funct3Expr::Store(Funct3StoreTable::Unknown(_))
+                | Funct3Expr::OpRegister(Funct3OpRegisterTable::Unknown(_))
+                | Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::Unknown(_))
+                | Funct3Expr::MulDiv(Funct3MulDivTable::Unknown(_))
+                | Funct3Expr::OpImmediate(Funct3OpImmediateTable::Unknown(_))
+                | Funct3Expr::System(Funct3SystemTable::Unknown(_))
+                | Funct3Expr::Atomic(Funct3AtomicTable::Unknown(_))
+                | Funct3Expr::Unknown(_)
+        )
+    }
+
+    // funct7, when given, tells apart instructions sharing the same funct3 (e.g. ADD and SUB)
+    pub(crate) fn try_from(opcode7: Opcode7, funct3: Funct3, funct7: Option<Funct7>) -> Result<Funct3Expr> {
         let opcode = Opcode7Table::try_from(opcode7)?;
 
         match opcode {
-            Opcode7Table::OpRegister => Ok(Funct3Expr::OpRegister(funct3.try_into()?)),
-            Opcode7Table::Load => Ok(Funct3Expr::Load(funct3.try_into()?)),
-            Opcode7Table::Store => Ok(Funct3Expr::Store(funct3.try_into()?)),
-            Opcode7Table::OpImmediate => Ok(Funct3Expr::OpImmediate(funct3.try_into()?)),
-            Opcode7Table::Branch => Ok(Funct3Expr::Branch(funct3.try_into()?)),
-            Opcode7Table::System => Ok(Funct3Expr::System(funct3.try_into()?)),
+            Opcode7Table::OpRegister => Self::try_from_op_register(funct3, funct7),
+            Opcode7Table::JumpAndLinkRegister => Ok(Funct3Expr::JumpAndLinkRegister(funct3.value().try_into()?)),
+            Opcode7Table::Load => Ok(Funct3Expr::Load(funct3.value().try_into()?)),
+            Opcode7Table::Store => Ok(Funct3Expr::Store(funct3.value().try_into()?)),
+            Opcode7Table::OpImmediate => Ok(Funct3Expr::OpImmediate(funct3.value().try_into()?)),
+            Opcode7Table::Branch => Ok(Funct3Expr::Branch(funct3.value().try_into()?)),
+            Opcode7Table::System => Ok(Funct3Expr::System(funct3.value().try_into()?)),
+            Opcode7Table::AtomicMemoryOp => Ok(Funct3Expr::Atomic(funct3.value().try_into()?)),
             _ => Err(Error::msg("Unknown opcode")),
         }
     }
+
+    fn try_from_op_register(funct3: Funct3, funct7: Option<Funct7>) -> Result<Funct3Expr> {
+        let funct7: Option<Funct7Table> = funct7.map(|funct7| funct7.value().try_into()).transpose()?;
+
+        if funct7 == Some(Funct7Table::MulDiv) {
+            return Ok(Funct3Expr::MulDiv(funct3.value().try_into()?));
+        }
+
+        let funct3: Funct3OpRegisterTable = funct3.value().try_into()?;
+
+        match (funct7, &funct3) {
+            (None | Some(Funct7Table::Logical), _) => Ok(Funct3Expr::OpRegister(funct3)),
+            (Some(Funct7Table::Arithmetic), Funct3OpRegisterTable::ADD | Funct3OpRegisterTable::SRA) => {
+                Ok(Funct3Expr::OpRegisterArithmetic(funct3))
+            }
+            _ => Err(Error::msg("Unknown funct7")),
+        }
+    }
+}
+
+impl Funct3LoadTable {
+    // How many bytes the load reads
+    pub fn access_size(&self) -> Option<InstructionLength> {
+        match self {
+            Self::LB | Self::LBU => Some(InstructionLength::Byte),
+            Self::LH | Self::LHU => Some(InstructionLength::HalfWord),
+            Self::LW => Some(InstructionLength::Word),
+            Self::LD => Some(InstructionLength::DoubleWord),
+            Self::Unknown(_) => None,
+        }
+    }
+
+    // Unsigned loads zero-extend the value read, instead of sign-extending it
+    pub fn is_unsigned(&self) -> bool { matches!(self, Self::LBU | Self::LHU) }
+}
+
+impl Funct3StoreTable {
+    // How many bytes the store writes
+    pub fn access_size(&self) -> Option<InstructionLength> {
+        match self {
+            Self::SB => Some(InstructionLength::Byte),
+            Self::SH => Some(InstructionLength::HalfWord),
+            Self::SW => Some(InstructionLength::Word),
+            Self::SD => Some(InstructionLength::DoubleWord),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+impl Opcode7Table {
+    // The names of the spec's base opcode map, lowercase as assembly listings use them for the opcode
+    // groups, e.g. "auipc" or "op-imm"
+    pub const fn asm_group(&self) -> &'static str {
+        match self {
+            Self::Zero => "zero",
+            Self::AddUpperImmediatePC => "auipc",
+            Self::AtomicMemoryOp => "amo",
+            Self::Branch => "branch",
+            Self::Custom0 => "custom-0",
+            Self::Custom1 => "custom-1",
+            Self::Custom2Rv128 => "custom-2",
+            Self::Custom3Rv128 => "custom-3",
+            Self::JumpAndLink => "jal",
+            Self::JumpAndLinkRegister => "jalr",
+            Self::Load => "load",
+            Self::LoadFloatingPoint => "load-fp",
+            Self::LoadUpperImmediate => "lui",
+            Self::MiscMemory => "misc-mem",
+            Self::MultiplyAdd => "madd",
+            Self::MultiplySubtract => "msub",
+            Self::NegMultiplyAdd => "nmadd",
+            Self::NegMultiplySubtract => "nmsub",
+            Self::OpFloatingPoint => "op-fp",
+            Self::OpImmediate => "op-imm",
+            Self::OpImmediate32 => "op-imm-32",
+            Self::OpRegister => "op",
+            Self::OpRegister32 => "op-32",
+            Self::OpVector => "op-v",
+            Self::OpVectorElement => "op-ve",
+            Self::Reserved => "reserved",
+            Self::Store => "store",
+            Self::StoreFloatingPoint => "store-fp",
+            Self::System => "system",
+        }
+    }
+}
+
+impl Funct3JALRTable {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::JALR => "jalr",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3BranchTable {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::BEQ => "beq",
+            Self::BNE => "bne",
+            Self::BLT => "blt",
+            Self::BGE => "bge",
+            Self::BLTU => "bltu",
+            Self::BGEU => "bgeu",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3LoadTable {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::LB => "lb",
+            Self::LH => "lh",
+            Self::LW => "lw",
+            Self::LD => "ld",
+            Self::LBU => "lbu",
+            Self::LHU => "lhu",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3StoreTable {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::SB => "sb",
+            Self::SH => "sh",
+            Self::SW => "sw",
+            Self::SD => "sd",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3OpRegisterTable {
+    // funct3 alone can't tell SUB from ADD, nor SRA from SRL: these are named after their funct7 = 0
+    // form (see Funct3Expr::mnemonic)
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::ADD => "add",
+            Self::SLL => "sll",
+            Self::SLT => "slt",
+            Self::SLTU => "sltu",
+            Self::XOR => "xor",
+            Self::SRA => "srl",
+            Self::OR => "or",
+            Self::AND => "and",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3OpImmediateTable {
+    // Same as above: SRAI and SRLI are told apart by the immediate (see Immediate11Table)
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::ADDI => "addi",
+            Self::SLLI => "slli",
+            Self::SLTI => "slti",
+            Self::SLTIU => "sltiu",
+            Self::XORI => "xori",
+            Self::SRAI => "srli",
+            Self::ORI => "ori",
+            Self::ANDI => "andi",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3MulDivTable {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::MUL => "mul",
+            Self::MULH => "mulh",
+            Self::MULHSU => "mulhsu",
+            Self::MULHU => "mulhu",
+            Self::DIV => "div",
+            Self::DIVU => "divu",
+            Self::REM => "rem",
+            Self::REMU => "remu",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
 }
 
+impl Funct3SystemTable {
+    // EBREAK, MRET, SRET and WFI share ECALL's funct3, and are told apart by the immediate
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::ECALL => "ecall",
+            Self::CSRRW => "csrrw",
+            Self::CSRRS => "csrrs",
+            Self::CSRRC => "csrrc",
+            Self::CSRRWI => "csrrwi",
+            Self::CSRRSI => "csrrsi",
+            Self::CSRRCI => "csrrci",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3AtomicTable {
+    // The width suffix of the atomic instructions (e.g. amoadd.w)
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::W => "w",
+            Self::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl Funct3Expr {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Funct3Expr::JumpAndLinkRegister(funct3) => funct3.mnemonic(),
+            Funct3Expr::Branch(funct3) => funct3.mnemonic(),
+            Funct3Expr::Load(funct3) => funct3.mnemonic(),
+            Funct3Expr::Store(funct3) => funct3.mnemonic(),
+            Funct3Expr::OpRegister(funct3) => funct3.mnemonic(),
+            Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SUB) => "sub",
+            Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SRA) => "sra",
+            Funct3Expr::MulDiv(funct3) => funct3.mnemonic(),
+            Funct3Expr::OpImmediate(funct3) => funct3.mnemonic(),
+            Funct3Expr::System(funct3) => funct3.mnemonic(),
+            Funct3Expr::Atomic(funct3) => funct3.mnemonic(),
+            Funct3Expr::OpRegisterArithmetic(_) | Funct3Expr::Unknown(_) => UNKNOWN_MNEMONIC,
+        }
+    }
+}
+
+impl fmt::Display for Opcode7Table {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(self.asm_group()) }
+}
+
+impl_mnemonic_display!(
+    Funct3JALRTable,
+    Funct3BranchTable,
+    Funct3LoadTable,
+    Funct3StoreTable,
+    Funct3OpRegisterTable,
+    Funct3OpImmediateTable,
+    Funct3MulDivTable,
+    Funct3SystemTable,
+    Funct3AtomicTable,
+    Funct3Expr,
+);
+
 impl TryFrom<Opcode7> for Opcode7Table {
     type Error = Error;
 
-    fn try_from(value: Opcode7) -> Result<Self> { value.try_into() }
+    fn try_from(value: Opcode7) -> Result<Self> { Ok(Self::try_from_primitive(value.value())?) }
+}
+
+// Same as `Opcode7Table::try_from`, but usable in constant expressions (e.g. to build tables at
+// compile time)
+pub const fn classify_opcode(opcode: u8) -> Option<Opcode7Table> {
+    use Opcode7Table as O;
+
+    Some(match opcode {
+        0 => O::Zero,
+        0b0010111 => O::AddUpperImmediatePC,
+        0b0101111 => O::AtomicMemoryOp,
+        0b1100011 => O::Branch,
+        0b0001011 => O::Custom0,
+        0b0101011 => O::Custom1,
+        0b1011011 => O::Custom2Rv128,
+        0b1111011 => O::Custom3Rv128,
+        0b1101111 => O::JumpAndLink,
+        0b1100111 => O::JumpAndLinkRegister,
+        0b0000011 => O::Load,
+        0b0000111 => O::LoadFloatingPoint,
+        0b0110111 => O::LoadUpperImmediate,
+        0b0001111 => O::MiscMemory,
+        0b1000011 => O::MultiplyAdd,
+        0b1000111 => O::MultiplySubtract,
+        0b1001111 => O::NegMultiplyAdd,
+        0b1001011 => O::NegMultiplySubtract,
+        0b1010011 => O::OpFloatingPoint,
+        0b0010011 => O::OpImmediate,
+        0b0011011 => O::OpImmediate32,
+        0b0110011 => O::OpRegister,
+        0b0111011 => O::OpRegister32,
+        0b1010111 => O::OpVector,
+        0b1110111 => O::OpVectorElement,
+        0b1101011 => O::Reserved,
+        0b0100011 => O::Store,
+        0b0100111 => O::StoreFloatingPoint,
+        0b1110011 => O::System,
+        _ => return None,
+    })
+}
+
+// Every opcode of the table fits in 7 bits
+impl From<Opcode7Table> for Opcode7 {
+    fn from(opcode: Opcode7Table) -> Self { Opcode7::new(opcode.into()) }
+}
+
+// Sign-extends the lowest `bits` bits of an immediate
+fn sign_extend(value: u32, bits: u32) -> i64 {
+    let shift = u32::BITS - bits;
+    ((value << shift) as i32 >> shift) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const _: () = assert!(matches!(classify_opcode(0b0010011), Some(Opcode7Table::OpImmediate)));
+
+    const OP: u8 = 0b0110011;
+    const LOGICAL: u8 = 0b0000000;
+    const ARITHMETIC: u8 = 0b0100000;
+
+    fn classify(funct3: u8, funct7: u8) -> Funct3Expr {
+        Funct3Expr::try_from(u7::new(OP), u3::new(funct3), Some(u7::new(funct7))).unwrap()
+    }
+
+    #[test]
+    fn should_classify_add_and_sub_by_funct7() {
+        assert_eq!(classify(0b000, LOGICAL), Funct3Expr::OpRegister(Funct3OpRegisterTable::ADD));
+        assert_eq!(classify(0b000, ARITHMETIC), Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SUB));
+    }
+
+    #[test]
+    fn should_classify_logical_and_arithmetic_shifts_by_funct7() {
+        assert_eq!(classify(0b101, LOGICAL), Funct3Expr::OpRegister(Funct3OpRegisterTable::SRL));
+        assert_eq!(classify(0b101, ARITHMETIC), Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SRA));
+        assert_ne!(classify(0b101, LOGICAL), classify(0b101, ARITHMETIC));
+    }
+
+    #[test]
+    fn should_round_trip_raw_words() {
+        let r_type = RType32Bitfield::new_with_raw_value(0)
+            .with_opcode(u7::new(OP))
+            .with_rd(u5::new(3))
+            .with_funct3(u3::new(0b000))
+            .with_rs1(u5::new(1))
+            .with_rs2(u5::new(2))
+            .with_funct7(u7::new(ARITHMETIC)); // sub x3, x1, x2
+
+        assert_eq!(r_type.raw(), 0x4020_81B3);
+        assert_eq!(RType32Bitfield::try_from(r_type.raw()).unwrap(), r_type);
+    }
+
+    #[test]
+    fn should_convert_opcodes_to_and_from_their_bitfield_value() {
+        assert_eq!(Opcode7::from(Opcode7Table::OpImmediate), u7::new(0b0010011));
+        assert_eq!(Opcode7Table::try_from(Opcode7::from(Opcode7Table::System)).unwrap(), Opcode7Table::System);
+
+        let i_type = IType32Bitfield::new_with_raw_value(0).with_opcode(Opcode7Table::OpImmediate.into());
+        assert_eq!(i_type.raw(), 0b0010011);
+    }
+
+    #[test]
+    fn should_accept_words_matching_the_format_opcode() {
+        assert!(RType32Bitfield::try_from(0x4020_81B3).is_ok()); // sub x3, x1, x2
+        assert!(SType32Bitfield::try_from(0x00A5_A223).is_ok()); // sw x10, 4(x11)
+    }
+
+    #[test]
+    fn should_reject_words_forced_into_the_wrong_format() {
+        assert!(RType32Bitfield::try_from(0x00A5_A223).is_err()); // sw x10, 4(x11)
+        assert!(JType32Bitfield::try_from(0x0050_0093).is_err()); // addi x1, x0, 5
+    }
+
+    #[test]
+    fn should_map_loads_and_stores_to_their_access_size() {
+        assert_eq!(Funct3LoadTable::LBU.access_size().map(|size| size.bytes()), Some(1));
+        assert_eq!(Funct3LoadTable::LH.access_size().map(|size| size.bytes()), Some(2));
+        assert_eq!(Funct3StoreTable::SW.access_size().map(|size| size.bytes()), Some(4));
+        assert_eq!(Funct3StoreTable::Unknown(0b111).access_size(), None);
+    }
+
+    #[test]
+    fn should_rebuild_i_type_immediates() {
+        assert_eq!(IType32Bitfield::new_with_raw_value(0x0050_0093).immediate(), 5); // addi x1, x0, 5
+        assert_eq!(IType32Bitfield::new_with_raw_value(0xFFF0_0093).immediate(), -1); // addi x1, x0, -1
+        assert_eq!(IType32Bitfield::new_with_raw_value(0x8000_0093).immediate(), -2048); // addi x1, x0, -2048
+    }
+
+    #[test]
+    fn should_rebuild_s_type_immediates() {
+        assert_eq!(SType32Bitfield::new_with_raw_value(0x00A5_A223).immediate(), 4); // sw x10, 4(x11)
+        assert_eq!(SType32Bitfield::new_with_raw_value(0xFEA1_2C23).immediate(), -8); // sw x10, -8(x2)
+    }
+
+    #[test]
+    fn should_rebuild_b_type_immediates() {
+        assert_eq!(BType32Bitfield::new_with_raw_value(0xFE00_0EE3).immediate(), -4); // beq x0, x0, -4
+        assert_eq!(BType32Bitfield::new_with_raw_value(0x0020_90E3).immediate(), 2048); // bne x1, x2, 2048
+    }
+
+    #[test]
+    fn should_rebuild_u_type_immediates() {
+        assert_eq!(UType32Bitfield::new_with_raw_value(0x1234_50B7).immediate(), 0x1234_5000); // lui x1, 0x12345
+        assert_eq!(UType32Bitfield::new_with_raw_value(0xFFFF_F0B7).immediate(), -4096); // lui x1, 0xFFFFF
+    }
+
+    #[test]
+    fn should_rebuild_j_type_immediates() {
+        assert_eq!(JType32Bitfield::new_with_raw_value(0x0080_00EF).immediate(), 8); // jal x1, 8
+        assert_eq!(JType32Bitfield::new_with_raw_value(0xFFDF_F06F).immediate(), -4); // jal x0, -4
+    }
+
+    #[test]
+    fn should_reject_arithmetic_funct7_on_other_operations() {
+        let result = Funct3Expr::try_from(u7::new(OP), u3::new(0b111), Some(u7::new(ARITHMETIC)));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_display_canonical_mnemonics() {
+        assert_eq!(Funct3OpRegisterTable::ADD.mnemonic(), "add");
+        assert_eq!(Funct3LoadTable::LBU.mnemonic(), "lbu");
+        assert_eq!(Funct3BranchTable::BGEU.to_string(), "bgeu");
+        assert_eq!(Funct3StoreTable::Unknown(0b111).to_string(), "unknown");
+        assert_eq!(Opcode7Table::OpImmediate.to_string(), "op-imm");
+        assert_eq!(Opcode7Table::AtomicMemoryOp.to_string(), "amo");
+    }
+
+    #[test]
+    fn should_tell_mnemonics_sharing_a_funct3_apart_by_funct7() {
+        assert_eq!(classify(0b000, ARITHMETIC).mnemonic(), "sub");
+        assert_eq!(classify(0b101, LOGICAL).to_string(), "srl");
+        assert_eq!(classify(0b101, ARITHMETIC).to_string(), "sra");
+    }
+
+    #[test]
+    fn should_name_opcode_groups_as_assembly_does() {
+        assert_eq!(Opcode7Table::AddUpperImmediatePC.asm_group(), "auipc");
+        assert_eq!(Opcode7Table::OpImmediate.asm_group(), "op-imm");
+        assert_eq!(Opcode7Table::MiscMemory.asm_group(), "misc-mem");
+    }
+
+    #[test]
+    fn should_classify_opcodes_as_try_from_does() {
+        for opcode in 0..=u8::MAX {
+            assert_eq!(classify_opcode(opcode), Opcode7Table::try_from(opcode).ok(), "opcode {:#09b}", opcode);
+        }
+    }
 }
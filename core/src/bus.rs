@@ -0,0 +1,352 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::ops::Range;
+
+use crate::memory::{Byte, DoubleWord, HalfWord, InstructionLength, Memory, MemoryError, VecMemory, Word};
+
+const RESERVATION_SIZE: Word = InstructionLength::Word.bytes() as Word;
+
+// A memory-mapped device. Offsets are relative to the start of the range the device is mapped at.
+// Devices are Send, as the bus is shared between harts.
+pub trait Device: Send {
+    fn read(&self, offset: Word, size: InstructionLength) -> DoubleWord;
+    fn write(&mut self, offset: Word, size: InstructionLength, value: DoubleWord);
+
+    // Advances the device by one tick of the machine clock
+    fn tick(&mut self) {}
+
+    // Whether the device raises the machine timer interrupt of a hart
+    fn timer_interrupt(&self, _hart_id: usize) -> bool { false }
+}
+
+pub enum Region {
+    Ram(VecMemory),
+    Device(Box<dyn Device>),
+}
+
+// Routes accesses to the RAM and devices mapped in the address space. Unmapped addresses read as
+// zero and ignore writes when accessed through `Memory`; use `load`/`store` to detect them.
+//
+// The bus is shared by every hart of a machine, which takes turns to access it, one instruction at
+// a time: all harts observe the same, single order of memory accesses (sequential consistency).
+// This is stronger than RVWMO requires, hence the aq/rl bits of atomic instructions have nothing
+// left to order, and are ignored.
+#[derive(Default)]
+pub struct Bus {
+    regions: Vec<MappedRegion>,
+    reservations: HashMap<usize, Word>, // hart id => word reserved by LR.W
+}
+
+struct MappedRegion {
+    range: Range<Word>,
+    region: Region,
+}
+
+impl Bus {
+    pub fn new() -> Self { Self::default() }
+
+    // A bus with `size` bytes of RAM mapped at address 0
+    pub fn with_ram(size: usize) -> Self { Self::with_ram_at(0, size) }
+
+    // A bus with `size` bytes of RAM mapped at `base`: as with any region, addresses are translated
+    // to offsets from its start, so e.g. a guest linked at 0x8000_0000 only needs as much RAM as it
+    // uses from there. The RAM must end below the top of the address space.
+    pub fn with_ram_at(base: Word, size: usize) -> Self {
+        let end = Word::try_from(size).ok().and_then(|size| base.checked_add(size));
+        let end =
+            end.unwrap_or_else(|| panic!("{:#x} bytes of RAM at {:#010x} overflow the address space.", size, base));
+
+        let mut bus = Self::new();
+        bus.map(base..end, Region::Ram(VecMemory::new(size)));
+        bus
+    }
+
+    pub fn map(&mut self, range: Range<Word>, region: Region) {
+        let overlaps = self
+            .regions
+            .iter()
+            .any(|mapped| mapped.range.start < range.end && range.start < mapped.range.end);
+
+        assert!(!overlaps, "Region {:#010x?} overlaps an existing region.", range);
+        self.regions.push(MappedRegion { range, region });
+    }
+
+    // Reads `size` bytes at `address`, or None when the access is not fully inside a single region
+    pub fn load(&self, address: Word, size: InstructionLength) -> Option<DoubleWord> {
+        let (mapped, offset) = self.find(address, size)?;
+
+        Some(match &mapped.region {
+            Region::Ram(ram) => match size {
+                InstructionLength::Byte => ram.read_byte(offset) as DoubleWord,
+                InstructionLength::HalfWord => ram.read_half_word(offset) as DoubleWord,
+                InstructionLength::Word => ram.read_word(offset) as DoubleWord,
+                InstructionLength::DoubleWord => ram.read_double_word(offset),
+            },
+            Region::Device(device) => device.read(offset, size),
+        })
+    }
+
+    // Writes the lowest `size` bytes of `value` at `address`, or None when the access is not fully
+    // inside a single region
+    pub fn store(&mut self, address: Word, size: InstructionLength, value: DoubleWord) -> Option<()> {
+        let index = self.find_index(address, size)?;
+        let mapped = &mut self.regions[index];
+        let offset = address - mapped.range.start;
+
+        match &mut mapped.region {
+            Region::Ram(ram) => match size {
+                InstructionLength::Byte => ram.write_byte(offset, &(value as Byte)),
+                InstructionLength::HalfWord => ram.write_half_word(offset, &(value as HalfWord)),
+                InstructionLength::Word => ram.write_word(offset, &(value as Word)),
+                InstructionLength::DoubleWord => ram.write_double_word(offset, &value),
+            },
+            Region::Device(device) => device.write(offset, size, value),
+        }
+
+        self.break_reservations(&(address..address + size.bytes() as Word));
+        Some(())
+    }
+
+    // LR.W: loads a word, and reserves it for `hart_id`, replacing its previous reservation
+    pub fn load_reserved(&mut self, hart_id: usize, address: Word) -> Option<DoubleWord> {
+        let value = self.load(address, InstructionLength::Word)?;
+        self.reservations.insert(hart_id, address);
+        Some(value)
+    }
+
+    // SC.W: stores a word only if `hart_id` still holds a reservation on it, telling whether it did.
+    // The reservation is consumed either way.
+    pub fn store_conditional(&mut self, hart_id: usize, address: Word, value: DoubleWord) -> Option<bool> {
+        if self.reservations.remove(&hart_id) != Some(address) {
+            return Some(false);
+        }
+
+        self.store(address, InstructionLength::Word, value)?;
+        Some(true)
+    }
+
+    pub fn tick(&mut self) { self.devices_mut().for_each(|device| device.tick()) }
+
+    pub fn timer_interrupt(&self, hart_id: usize) -> bool {
+        self.devices().any(|device| device.timer_interrupt(hart_id))
+    }
+
+    // Borrows a contiguous range of RAM without copying. Device regions have no backing bytes, and
+    // yield None, as do ranges spanning more than one region.
+    pub fn region_slice(&self, range: Range<Word>) -> Option<&[Byte]> {
+        let mapped = self.regions.iter().find(|mapped| contains(&mapped.range, &range))?;
+
+        match &mapped.region {
+            Region::Ram(ram) => ram.as_slice().get(relative(&mapped.range, &range)),
+            Region::Device(_) => None,
+        }
+    }
+
+    // Being written through, the slice breaks the reservations on the words it covers, as stores do
+    pub fn region_slice_mut(&mut self, range: Range<Word>) -> Option<&mut [Byte]> {
+        let index = self.regions.iter().position(|mapped| contains(&mapped.range, &range))?;
+        if let Region::Ram(_) = self.regions[index].region {
+            self.break_reservations(&range);
+        }
+
+        let mapped = &mut self.regions[index];
+        match &mut mapped.region {
+            Region::Ram(ram) => ram.as_mut_slice().get_mut(relative(&mapped.range, &range)),
+            Region::Device(_) => None,
+        }
+    }
+
+    // Any write to a reserved word, from whichever hart, breaks the reservation
+    fn break_reservations(&mut self, range: &Range<Word>) {
+        self.reservations
+            .retain(|_, reserved| range.end <= *reserved || *reserved + RESERVATION_SIZE <= range.start);
+    }
+
+    fn devices(&self) -> impl Iterator<Item = &dyn Device> {
+        self.regions.iter().filter_map(|mapped| match &mapped.region {
+            Region::Device(device) => Some(device.as_ref()),
+            Region::Ram(_) => None,
+        })
+    }
+
+    fn devices_mut(&mut self) -> impl Iterator<Item = &mut dyn Device> {
+        self.regions.iter_mut().filter_map(|mapped| match &mut mapped.region {
+            Region::Device(device) => Some(device.as_mut()),
+            Region::Ram(_) => None,
+        })
+    }
+
+    fn find(&self, address: Word, size: InstructionLength) -> Option<(&MappedRegion, Word)> {
+        let mapped = &self.regions[self.find_index(address, size)?];
+        Some((mapped, address - mapped.range.start))
+    }
+
+    fn find_index(&self, address: Word, size: InstructionLength) -> Option<usize> {
+        let end = address.checked_add(size.bytes() as Word)?;
+        self.regions
+            .iter()
+            .position(|mapped| contains(&mapped.range, &(address..end)))
+    }
+}
+
+impl Memory for Bus {
+    fn read_byte(&self, address: Word) -> Byte { self.load(address, InstructionLength::Byte).unwrap_or(0) as Byte }
+
+    fn read_half_word(&self, address: Word) -> HalfWord {
+        self.load(address, InstructionLength::HalfWord).unwrap_or(0) as HalfWord
+    }
+
+    fn read_word(&self, address: Word) -> Word { self.load(address, InstructionLength::Word).unwrap_or(0) as Word }
+
+    fn read_double_word(&self, address: Word) -> DoubleWord {
+        self.load(address, InstructionLength::DoubleWord).unwrap_or(0)
+    }
+
+    fn write_byte(&mut self, address: Word, value: &Byte) {
+        self.store(address, InstructionLength::Byte, *value as DoubleWord);
+    }
+
+    fn write_half_word(&mut self, address: Word, value: &HalfWord) {
+        self.store(address, InstructionLength::HalfWord, *value as DoubleWord);
+    }
+
+    fn write_word(&mut self, address: Word, value: &Word) {
+        self.store(address, InstructionLength::Word, *value as DoubleWord);
+    }
+
+    fn write_double_word(&mut self, address: Word, value: &DoubleWord) {
+        self.store(address, InstructionLength::DoubleWord, *value);
+    }
+
+    // Copies to RAM in one go (see `region_slice_mut`), e.g. to load a program, breaking reservations
+    // as stores do. Devices, and ranges spanning more than one region, are out of bounds.
+    fn write_bytes(&mut self, address: Word, data: &[Byte]) -> Result<(), MemoryError> {
+        let out_of_bounds = MemoryError::OutOfBounds { address, len: data.len() };
+        let end = Word::try_from(data.len())
+            .ok()
+            .and_then(|len| address.checked_add(len))
+            .ok_or(out_of_bounds)?;
+
+        self.region_slice_mut(address..end).ok_or(out_of_bounds)?.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+impl Debug for Bus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+
+        for mapped in &self.regions {
+            let kind = match mapped.region {
+                Region::Ram(_) => "RAM",
+                Region::Device(_) => "Device",
+            };
+            list.entry(&format_args!("{:#010x}..{:#010x} {}", mapped.range.start, mapped.range.end, kind));
+        }
+
+        list.finish()
+    }
+}
+
+fn contains(outer: &Range<Word>, inner: &Range<Word>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end && inner.start <= inner.end
+}
+
+fn relative(outer: &Range<Word>, inner: &Range<Word>) -> Range<usize> {
+    (inner.start - outer.start) as usize..(inner.end - outer.start) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NullDevice;
+
+    impl Device for NullDevice {
+        fn read(&self, _offset: Word, _size: InstructionLength) -> DoubleWord { 0 }
+
+        fn write(&mut self, _offset: Word, _size: InstructionLength, _value: DoubleWord) {}
+    }
+
+    #[test]
+    fn should_borrow_ram_without_copying() {
+        let mut bus = Bus::with_ram(64);
+        bus.write_word(8, &0xDEAD_BEEF);
+
+        assert_eq!(bus.region_slice(8..12), Some(&[0xEF, 0xBE, 0xAD, 0xDE][..]));
+    }
+
+    #[test]
+    fn should_not_borrow_device_regions() {
+        let mut bus = Bus::with_ram(64);
+        bus.map(0x1000..0x1010, Region::Device(Box::new(NullDevice)));
+
+        assert_eq!(bus.region_slice(0x1000..0x1004), None);
+    }
+
+    #[test]
+    fn should_not_borrow_across_regions() {
+        let mut bus = Bus::with_ram(64);
+        bus.map(64..128, Region::Ram(VecMemory::new(64)));
+
+        assert_eq!(bus.region_slice(60..68), None);
+        assert_eq!(bus.load(62, InstructionLength::Word), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow the address space")]
+    fn should_refuse_ram_ending_past_the_top_of_the_address_space() { Bus::with_ram_at(0xFFFF_F000, 0x1000); }
+
+    #[test]
+    fn should_store_conditionally_only_while_reserved() {
+        let mut bus = Bus::with_ram(64);
+
+        bus.load_reserved(0, 8).unwrap();
+        assert_eq!(bus.store_conditional(0, 8, 1), Some(true));
+        assert_eq!(bus.store_conditional(0, 8, 2), Some(false)); // reservation consumed
+
+        assert_eq!(bus.load(8, InstructionLength::Word), Some(1));
+    }
+
+    #[test]
+    fn should_break_reservations_on_stores_to_the_reserved_word() {
+        let mut bus = Bus::with_ram(64);
+
+        bus.load_reserved(0, 8).unwrap();
+        bus.load_reserved(1, 16).unwrap();
+        bus.store(11, InstructionLength::Byte, 0xFF).unwrap();
+        bus.store(20, InstructionLength::Word, 0xFF).unwrap();
+
+        assert_eq!(bus.store_conditional(0, 8, 1), Some(false));
+        assert_eq!(bus.store_conditional(1, 16, 1), Some(true));
+    }
+
+    #[test]
+    fn should_break_reservations_on_bulk_writes_over_the_reserved_word() {
+        let mut bus = Bus::with_ram(64);
+
+        bus.load_reserved(0, 8).unwrap();
+        bus.load_reserved(1, 16).unwrap();
+        bus.write_bytes(4, &[0xFF; 8]).unwrap();
+
+        assert_eq!(bus.store_conditional(0, 8, 1), Some(false));
+        assert_eq!(bus.store_conditional(1, 16, 1), Some(true));
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use anyhow::{Context, Result};
+
+use crate::machine::{Machine, StopReason};
+use crate::memory::{Byte, InstructionLength};
+
+// Instructions a compliance test may retire before it is considered hung
+pub const COMPLIANCE_STEP_LIMIT: u64 = 10_000_000;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComplianceResult {
+    Pass,
+    // The number of the failing test case
+    Fail(u32),
+    // The test stopped (or hung) without writing to tohost
+    Stopped(StopReason),
+}
+
+impl Machine {
+    // Runs a test of the riscv-tests / riscv-arch-test suites, built as an ELF executable, until it
+    // writes its outcome to tohost (see `HostInterface`): 1 when it passed, otherwise the number of
+    // the failing test case shifted left by one, with the low bit set.
+    pub fn run_compliance(&mut self, elf: &[Byte]) -> Result<ComplianceResult> {
+        self.load_elf(elf)?;
+        let host = self.host_interface().context("The test has no tohost symbol.")?;
+        let result = self.run(COMPLIANCE_STEP_LIMIT);
+
+        Ok(match self.bus().load(host.tohost, InstructionLength::Word) {
+            Some(1) => ComplianceResult::Pass,
+            Some(value) if value & 1 == 1 => ComplianceResult::Fail((value >> 1) as u32),
+            _ => ComplianceResult::Stopped(result.stop),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::tests::elf;
+    use crate::trap::TrapCause;
+
+    #[test]
+    fn should_pass_once_the_test_writes_1_to_tohost() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x0, 1; sw x1, 0x400(x0); jal x0, 0 };
+
+        let result = machine.run_compliance(&elf(0x200, &program, "tohost", 0x400)).unwrap();
+
+        assert_eq!(result, ComplianceResult::Pass);
+    }
+
+    #[test]
+    fn should_pass_through_a_fence_before_writing_to_tohost() {
+        let mut machine = Machine::with_memory_size(4096);
+        let mut program = vec![0x0FF0_000F]; // fence iorw, iorw
+        program.extend(crate::rv32asm! { addi x1, x0, 1; sw x1, 0x400(x0); jal x0, 0 });
+
+        let result = machine.run_compliance(&elf(0x200, &program, "tohost", 0x400)).unwrap();
+
+        assert_eq!(result, ComplianceResult::Pass);
+    }
+
+    #[test]
+    fn should_report_the_failing_test_case_or_why_it_stopped() {
+        let mut machine = Machine::with_memory_size(4096);
+        let failing = crate::rv32asm! { addi x1, x0, 7; sw x1, 0x400(x0); jal x0, 0 };
+        assert_eq!(machine.run_compliance(&elf(0x200, &failing, "tohost", 0x400)).unwrap(), ComplianceResult::Fail(3));
+
+        let mut machine = Machine::with_memory_size(4096);
+        let trapping = crate::rv32asm! { ebreak };
+        assert_eq!(
+            machine.run_compliance(&elf(0x200, &trapping, "tohost", 0x400)).unwrap(),
+            ComplianceResult::Stopped(StopReason::Trap(TrapCause::Breakpoint))
+        );
+    }
+}
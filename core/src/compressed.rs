@@ -0,0 +1,179 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use crate::architecture::{Architecture, RV32Instruction, RV32I};
+use crate::bitfield::{
+    Funct3JALRTable, Funct3LoadTable, Funct3OpImmediateTable, Funct3OpRegisterTable, Funct3StoreTable, Opcode7Table,
+};
+use crate::encoder::{i_type, j_type, r_type, s_type};
+use crate::memory::{HalfWord, Word};
+
+// Compressed (RVC) instructions are grouped in quadrants, given by their lowest 2 bits; the 3rd
+// quadrant (0b11) is taken by the 32 bits instructions
+const QUADRANT_0: HalfWord = 0b00;
+const QUADRANT_1: HalfWord = 0b01;
+const QUADRANT_2: HalfWord = 0b10;
+
+const STACK_POINTER: Word = 2; // sp
+
+// Compressed instructions have no semantics of their own: each of them is expanded to the 32 bits
+// instruction it stands for, which is then handled as usual.
+pub fn decode_compressed(halfword: HalfWord) -> Option<RV32Instruction> { RV32I.decode_opt(expand(halfword)?) }
+
+// Expands C.ADDI, C.ADDI4SPN, C.ADDI16SP, C.LW, C.SW, C.J, C.JR and C.MV; other compressed
+// instructions are unsupported yet.
+fn expand(halfword: HalfWord) -> Option<Word> {
+    let c = halfword as Word;
+    let funct3 = bits(c, 15, 13);
+
+    match (halfword & 0b11, funct3) {
+        // C.ADDI4SPN: addi rd', x2, nzuimm, whose scrambled bits are a multiple of 4 up to 1020. A zero
+        // immediate is reserved, and the all-zero halfword is the defined illegal instruction.
+        (QUADRANT_0, 0b000) => {
+            let nzuimm = bits(c, 12, 11) << 4 | bits(c, 10, 7) << 6 | bits(c, 6, 6) << 2 | bits(c, 5, 5) << 3;
+            let rd = compressed_register(bits(c, 4, 2));
+
+            (nzuimm != 0).then(|| addi(rd, STACK_POINTER, nzuimm))
+        }
+
+        // C.LW: lw rd', offset(rs1')
+        (QUADRANT_0, 0b010) => Some(i_type(
+            Opcode7Table::Load,
+            u8::from(Funct3LoadTable::LW),
+            compressed_register(bits(c, 4, 2)),
+            compressed_register(bits(c, 9, 7)),
+            word_offset(c),
+        )),
+
+        // C.SW: sw rs2', offset(rs1')
+        (QUADRANT_0, 0b110) => Some(s_type(
+            Opcode7Table::Store,
+            u8::from(Funct3StoreTable::SW),
+            compressed_register(bits(c, 9, 7)),
+            compressed_register(bits(c, 4, 2)),
+            word_offset(c),
+        )),
+
+        // C.ADDI: addi rd, rd, imm (C.NOP when rd = x0)
+        (QUADRANT_1, 0b000) => {
+            let rd = bits(c, 11, 7);
+            let imm = sign_extend(bits(c, 12, 12) << 5 | bits(c, 6, 2), 6);
+
+            Some(addi(rd, rd, imm))
+        }
+
+        // C.ADDI16SP: addi x2, x2, nzimm, a multiple of 16 from -512 to 496, zero being reserved. The
+        // same quadrant and funct3 with any other rd is C.LUI.
+        (QUADRANT_1, 0b011) if bits(c, 11, 7) == STACK_POINTER => {
+            let nzimm = bits(c, 12, 12) << 9
+                | bits(c, 6, 6) << 4
+                | bits(c, 5, 5) << 6
+                | bits(c, 4, 3) << 7
+                | bits(c, 2, 2) << 5;
+
+            (nzimm != 0).then(|| addi(STACK_POINTER, STACK_POINTER, sign_extend(nzimm, 10)))
+        }
+
+        // C.J: jal x0, offset
+        (QUADRANT_1, 0b101) => {
+            let offset = bits(c, 12, 12) << 11
+                | bits(c, 11, 11) << 4
+                | bits(c, 10, 9) << 8
+                | bits(c, 8, 8) << 10
+                | bits(c, 7, 7) << 6
+                | bits(c, 6, 6) << 7
+                | bits(c, 5, 3) << 1
+                | bits(c, 2, 2) << 5;
+
+            Some(j_type(Opcode7Table::JumpAndLink, 0, sign_extend(offset, 12)))
+        }
+
+        // C.JR: jalr x0, 0(rs1), and C.MV: add rd, x0, rs2
+        (QUADRANT_2, 0b100) if bits(c, 12, 12) == 0 => {
+            let rd_rs1 = bits(c, 11, 7);
+            let rs2 = bits(c, 6, 2);
+
+            match (rd_rs1, rs2) {
+                (0, _) => None,
+                (rs1, 0) => Some(i_type(Opcode7Table::JumpAndLinkRegister, u8::from(Funct3JALRTable::JALR), 0, rs1, 0)),
+                (rd, rs2) => {
+                    Some(r_type(Opcode7Table::OpRegister, u8::from(Funct3OpRegisterTable::ADD), 0, rd, 0, rs2))
+                }
+            }
+        }
+
+        _ => None,
+    }
+}
+
+// Extracts bits `high..=low` of a compressed instruction
+fn bits(c: Word, high: u32, low: u32) -> Word { (c >> low) & ((1 << (high - low + 1)) - 1) }
+
+fn addi(rd: Word, rs1: Word, imm: Word) -> Word {
+    i_type(Opcode7Table::OpImmediate, u8::from(Funct3OpImmediateTable::ADDI), rd, rs1, imm)
+}
+
+// The 3 bits register fields of compressed instructions address x8 to x15 only
+fn compressed_register(register: Word) -> Word { register + 8 }
+
+// Unsigned, word-aligned offset shared by C.LW and C.SW
+fn word_offset(c: Word) -> Word { bits(c, 5, 5) << 6 | bits(c, 12, 10) << 3 | bits(c, 6, 6) << 2 }
+
+fn sign_extend(value: Word, bits: u32) -> Word {
+    let shift = Word::BITS - bits;
+    (((value << shift) as i32) >> shift) as Word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_expand_c_addi_to_addi() {
+        assert_eq!(expand(0x0505), Some(0x0015_0513)); // c.addi x10, 1 => addi x10, x10, 1
+        assert_eq!(expand(0x15FD), Some(0xFFF5_8593)); // c.addi x11, -1 => addi x11, x11, -1
+        assert_eq!(decode_compressed(0x0505), RV32I.decode_opt(0x0015_0513));
+    }
+
+    #[test]
+    fn should_expand_the_stack_pointer_additions_with_their_scaled_immediates() {
+        assert_eq!(expand(0x0800), Some(0x0101_0413)); // c.addi4spn x8, sp, 16 => addi x8, x2, 16
+        assert_eq!(expand(0x1FFC), Some(0x3FC1_0793)); // c.addi4spn x15, sp, 1020 => addi x15, x2, 1020
+        assert_eq!(expand(0x7139), Some(0xFC01_0113)); // c.addi16sp sp, -64 => addi x2, x2, -64
+        assert_eq!(expand(0x6121), Some(0x0401_0113)); // c.addi16sp sp, 64 => addi x2, x2, 64
+        assert_eq!(expand(0x6101), None); // c.addi16sp with a zero immediate is reserved
+    }
+
+    #[test]
+    fn should_expand_c_lw_and_c_sw() {
+        assert_eq!(expand(0x41C8), Some(0x0045_A503)); // c.lw x10, 4(x11) => lw x10, 4(x11)
+        assert_eq!(expand(0xC1C8), Some(0x00A5_A223)); // c.sw x10, 4(x11) => sw x10, 4(x11)
+    }
+
+    #[test]
+    fn should_expand_jumps_and_moves() {
+        assert_eq!(expand(0xBFF5), Some(0xFFDF_F06F)); // c.j -4 => jal x0, -4
+        assert_eq!(expand(0x8082), Some(0x0000_8067)); // c.jr x1 => jalr x0, 0(x1)
+        assert_eq!(expand(0x852E), Some(0x00B0_0533)); // c.mv x10, x11 => add x10, x0, x11
+    }
+
+    #[test]
+    fn should_not_expand_illegal_or_unsupported_instructions() {
+        assert_eq!(expand(0x0000), None); // defined illegal instruction
+        assert_eq!(expand(0x8002), None); // c.jr x0 is reserved
+        assert_eq!(expand(0x6505), None); // c.lui
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use crate::alu::truncate_xlen;
+use crate::register::RegisterValue64;
+
+// CSR addresses are 12 bits wide; bits 11:10 set mean read-only
+pub type CsrAddress = u16;
+
+pub const MSTATUS: CsrAddress = 0x300;
+pub const MIE: CsrAddress = 0x304;
+pub const MTVEC: CsrAddress = 0x305;
+pub const MSCRATCH: CsrAddress = 0x340;
+pub const MEPC: CsrAddress = 0x341;
+pub const MCAUSE: CsrAddress = 0x342;
+pub const MTVAL: CsrAddress = 0x343;
+pub const MIP: CsrAddress = 0x344;
+pub const MHARTID: CsrAddress = 0xF14;
+
+// mstatus fields
+pub const MSTATUS_MIE: RegisterValue64 = 1 << 3; // interrupts enabled
+pub const MSTATUS_MPIE: RegisterValue64 = 1 << 7; // MIE before the trap
+pub const MSTATUS_MPP: RegisterValue64 = 0b11 << 11; // privilege mode before the trap (always M)
+
+// mtvec fields: the handler base address is 4-byte aligned, leaving the two low bits to the mode
+pub const MTVEC_MODE: RegisterValue64 = 0b11;
+pub const MTVEC_VECTORED: RegisterValue64 = 0b01; // interrupts go to BASE + 4 * cause (direct: all to BASE)
+
+// mie and mip fields
+pub const MIE_MTIE: RegisterValue64 = 1 << 7; // timer interrupt enabled
+pub const MIP_MTIP: RegisterValue64 = 1 << 7; // timer interrupt pending
+
+const READ_ONLY: CsrAddress = 0b11 << 10;
+
+// The machine-mode CSRs needed to take traps and return from them. Others are not implemented, and
+// accessing them is an illegal instruction. Like the integer registers, CSRs hold XLEN-wide values,
+// sign-extended to 64 bits.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ControlStatusRegisters {
+    pub mstatus: RegisterValue64,
+    pub mie: RegisterValue64,
+    pub mtvec: RegisterValue64, // trap handler address; 0 means none (see `SimpleRV32IHart::step`)
+    pub mscratch: RegisterValue64,
+    pub mepc: RegisterValue64,
+    pub mcause: RegisterValue64,
+    pub mtval: RegisterValue64,
+    pub mip: RegisterValue64,
+    mhartid: RegisterValue64,
+}
+
+impl ControlStatusRegisters {
+    pub fn new(hart_id: usize) -> Self {
+        Self {
+            mstatus: MSTATUS_MPP,
+            mhartid: hart_id as RegisterValue64,
+            ..Self::default()
+        }
+    }
+
+    pub fn read(&self, address: CsrAddress) -> Option<RegisterValue64> {
+        Some(match address {
+            MSTATUS => self.mstatus,
+            MIE => self.mie,
+            MTVEC => self.mtvec,
+            MSCRATCH => self.mscratch,
+            MEPC => self.mepc,
+            MCAUSE => self.mcause,
+            MTVAL => self.mtval,
+            MIP => self.mip,
+            MHARTID => self.mhartid,
+            _ => return None,
+        })
+    }
+
+    // None when the CSR does not exist, or is read-only
+    pub fn write(&mut self, address: CsrAddress, value: RegisterValue64) -> Option<()> {
+        if address & READ_ONLY == READ_ONLY {
+            return None;
+        }
+
+        // mepc holds instruction addresses, always aligned to IALIGN (2 bytes)
+        let value = if address == MEPC { truncate_xlen(value) & !1 } else { truncate_xlen(value) };
+        let csr = match address {
+            MSTATUS => &mut self.mstatus,
+            MIE => &mut self.mie,
+            MTVEC => &mut self.mtvec,
+            MSCRATCH => &mut self.mscratch,
+            MEPC => &mut self.mepc,
+            MCAUSE => &mut self.mcause,
+            MTVAL => &mut self.mtval,
+            MIP => &mut self.mip,
+            _ => return None,
+        };
+
+        *csr = value;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_back_written_csrs() {
+        let mut csrs = ControlStatusRegisters::new(0);
+
+        csrs.write(MTVEC, 0x100).unwrap();
+        csrs.write(MSCRATCH, 0x8000_0000).unwrap();
+
+        assert_eq!(csrs.read(MTVEC), Some(0x100));
+        assert_eq!(csrs.read(MSCRATCH), Some(0xFFFF_FFFF_8000_0000));
+    }
+
+    #[test]
+    fn should_reject_unknown_and_read_only_csrs() {
+        let mut csrs = ControlStatusRegisters::new(3);
+
+        assert_eq!(csrs.read(0x7C0), None);
+        assert_eq!(csrs.write(0x7C0, 1), None);
+        assert_eq!(csrs.write(MHARTID, 1), None);
+        assert_eq!(csrs.read(MHARTID), Some(3));
+    }
+}
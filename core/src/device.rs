@@ -0,0 +1,180 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::Write;
+
+use crate::bus::Device;
+use crate::memory::{Byte, DoubleWord, InstructionLength, Word};
+
+// A write-only serial console: each byte stored at offset 0 is written out as is. Reads always
+// return 0, as there is no input yet.
+pub struct Console {
+    output: Box<dyn Write + Send>,
+}
+
+impl Console {
+    pub fn new(output: impl Write + Send + 'static) -> Self { Self { output: Box::new(output) } }
+
+    pub fn stdout() -> Self { Self::new(io::stdout()) }
+}
+
+impl Device for Console {
+    fn read(&self, _offset: Word, _size: InstructionLength) -> DoubleWord { 0 }
+
+    // Output errors can't be reported to the guest, which has no way to handle them anyway
+    fn write(&mut self, offset: Word, _size: InstructionLength, value: DoubleWord) {
+        if offset == 0 {
+            let _ = self.output.write_all(&[value as Byte]);
+            let _ = self.output.flush();
+        }
+    }
+}
+
+impl Debug for Console {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Console") }
+}
+
+// The core-local interruptor, with the same layout as SiFive's (and QEMU's "virt" machine):
+//
+//     0x0000 + 4 * hart   msip      (not implemented: reads 0, ignores writes)
+//     0x4000 + 8 * hart   mtimecmp
+//     0xBFF8              mtime
+//
+// mtime counts machine clock ticks (see `Bus::tick`); a hart's timer interrupt is pending while
+// mtime >= its mtimecmp.
+pub struct Clint {
+    mtime: DoubleWord,
+    mtimecmp: Vec<DoubleWord>, // one per hart
+}
+
+pub const CLINT_MTIMECMP: Word = 0x4000;
+pub const CLINT_MTIME: Word = 0xBFF8;
+pub const CLINT_SIZE: Word = 0x1_0000;
+
+impl Clint {
+    // mtimecmp starts at its maximum, so that no interrupt is pending until it is set
+    pub fn new(harts: usize) -> Self {
+        Self {
+            mtime: 0,
+            mtimecmp: vec![DoubleWord::MAX; harts],
+        }
+    }
+
+    fn register(&self, offset: Word) -> Option<&DoubleWord> {
+        if (CLINT_MTIME..CLINT_MTIME + 8).contains(&offset) {
+            return Some(&self.mtime);
+        }
+
+        self.mtimecmp.get(offset.checked_sub(CLINT_MTIMECMP)? as usize / 8)
+    }
+
+    fn register_mut(&mut self, offset: Word) -> Option<&mut DoubleWord> {
+        if (CLINT_MTIME..CLINT_MTIME + 8).contains(&offset) {
+            return Some(&mut self.mtime);
+        }
+
+        self.mtimecmp.get_mut(offset.checked_sub(CLINT_MTIMECMP)? as usize / 8)
+    }
+}
+
+// Accesses narrower than the registers read or write the bytes of the register at their offset
+impl Device for Clint {
+    fn read(&self, offset: Word, size: InstructionLength) -> DoubleWord {
+        let shift = (offset % 8) * Byte::BITS;
+        self.register(offset).map_or(0, |register| (register >> shift) & mask(size))
+    }
+
+    fn write(&mut self, offset: Word, size: InstructionLength, value: DoubleWord) {
+        let shift = (offset % 8) * Byte::BITS;
+
+        if let Some(register) = self.register_mut(offset) {
+            let mask = mask(size) << shift;
+            *register = *register & !mask | (value << shift) & mask;
+        }
+    }
+
+    fn tick(&mut self) { self.mtime = self.mtime.wrapping_add(1) }
+
+    fn timer_interrupt(&self, hart_id: usize) -> bool {
+        self.mtimecmp.get(hart_id).is_some_and(|mtimecmp| self.mtime >= *mtimecmp)
+    }
+}
+
+fn mask(size: InstructionLength) -> DoubleWord { DoubleWord::MAX >> (DoubleWord::BITS - size as u32) }
+
+// Console output that tests can read back
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(pub(crate) std::sync::Arc<std::sync::Mutex<Vec<Byte>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[Byte]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_write_the_lowest_byte_stored_at_offset_0() {
+        let buffer = SharedBuffer::default();
+        let mut console = Console::new(buffer.clone());
+
+        console.write(0, InstructionLength::Word, 0x1234_5668);
+        console.write(0, InstructionLength::Byte, b'i' as DoubleWord);
+        console.write(4, InstructionLength::Byte, b'!' as DoubleWord);
+
+        assert_eq!(*buffer.0.lock().unwrap(), b"hi");
+        assert_eq!(console.read(0, InstructionLength::Byte), 0);
+    }
+
+    #[test]
+    fn should_raise_the_timer_interrupt_once_mtime_reaches_mtimecmp() {
+        let mut clint = Clint::new(2);
+        clint.write(CLINT_MTIMECMP + 8, InstructionLength::DoubleWord, 2);
+
+        clint.tick();
+        assert!(!clint.timer_interrupt(1));
+
+        clint.tick();
+        assert!(clint.timer_interrupt(1));
+        assert!(!clint.timer_interrupt(0));
+        assert_eq!(clint.read(CLINT_MTIME, InstructionLength::DoubleWord), 2);
+    }
+
+    #[test]
+    fn should_access_clint_registers_by_halves() {
+        let mut clint = Clint::new(1);
+
+        clint.write(CLINT_MTIME + 4, InstructionLength::Word, 1);
+        clint.write(CLINT_MTIME, InstructionLength::Word, 0xFFFF_FFFF);
+        clint.tick();
+
+        assert_eq!(clint.read(CLINT_MTIME, InstructionLength::Word), 0);
+        assert_eq!(clint.read(CLINT_MTIME + 4, InstructionLength::Word), 2);
+        assert_eq!(clint.read(CLINT_MTIMECMP + 4, InstructionLength::Word), 0xFFFF_FFFF);
+        assert_eq!(clint.read(0, InstructionLength::Word), 0); // msip
+    }
+}
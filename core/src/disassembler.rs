@@ -0,0 +1,320 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::collections::BTreeMap;
+
+use arbitrary_int::Number;
+#[cfg(feature = "serde")]
+use serde_json::{Map, Value};
+
+#[cfg(feature = "serde")]
+use crate::architecture::FieldName;
+use crate::architecture::{Architecture, InstructionKind, RV32Instruction, RV32I};
+use crate::bitfield::{classify_opcode, Funct3BranchTable, Rs5};
+use crate::instruction::{ADDI, JAL, JALR, LR_W};
+use crate::memory::Word;
+
+const UNKNOWN_MNEMONIC: &str = "unknown";
+
+// Names of addresses, e.g. of functions, shown instead of the jump and branch targets they match
+pub type SymbolTable = BTreeMap<Word, String>;
+
+// Renders an instruction in assembly syntax, e.g. "addi x1, x2, 5". Registers are shown by number.
+pub fn disassemble(instruction: &RV32Instruction) -> String {
+    if instruction.is_nop() {
+        return "nop".to_string();
+    }
+
+    if instruction.kind() == InstructionKind::EnvironmentCallAndBreakpoint {
+        return mnemonic(instruction).to_string();
+    }
+
+    if let Some(pseudo) = pseudo_instruction(instruction) {
+        return pseudo;
+    }
+
+    let descr = RV32I.describe(*instruction);
+    let mnemonic = mnemonic(instruction);
+
+    match instruction {
+        // Shifts by an immediate (see SLLI) show the shift amount only
+        RV32Instruction::IntegerRegisterImmediate(i_type) if descr.is_some_and(|descr| descr.funct7.is_some()) => {
+            format!("{} {}, {}, {}", mnemonic, x(i_type.rd()), x(i_type.rs1()), i_type.imm().value() & 0x1F)
+        }
+        RV32Instruction::IntegerRegisterImmediate(i_type) => {
+            format!("{} {}, {}, {}", mnemonic, x(i_type.rd()), x(i_type.rs1()), i_type.immediate())
+        }
+        RV32Instruction::IntegerRegisterRegister(r_type) => {
+            format!("{} {}, {}, {}", mnemonic, x(r_type.rd()), x(r_type.rs1()), x(r_type.rs2()))
+        }
+        RV32Instruction::UnconditionalJump(j_type) => {
+            format!("{} {}, {}", mnemonic, x(j_type.rd()), j_type.immediate())
+        }
+        RV32Instruction::ConditionBranch(b_type) => {
+            format!("{} {}, {}, {}", mnemonic, x(b_type.rs1()), x(b_type.rs2()), b_type.immediate())
+        }
+        // The upper 20 bits, as written in assembly
+        RV32Instruction::UpperImmediate(u_type) => {
+            format!("{} {}, {:#x}", mnemonic, x(u_type.rd()), u_type.raw_value() >> 12)
+        }
+        RV32Instruction::Load(i_type) | RV32Instruction::JumpAndLinkRegister(i_type) => {
+            format!("{} {}, {}({})", mnemonic, x(i_type.rd()), i_type.immediate(), x(i_type.rs1()))
+        }
+        RV32Instruction::Store(s_type) => {
+            format!("{} {}, {}({})", mnemonic, x(s_type.rs2()), s_type.immediate(), x(s_type.rs1()))
+        }
+        RV32Instruction::ControlAndStatusRegister(i_type) if mnemonic.ends_with('i') => {
+            format!("{} {}, {:#x}, {}", mnemonic, x(i_type.rd()), i_type.imm().value(), i_type.rs1().value())
+        }
+        RV32Instruction::ControlAndStatusRegister(i_type) => {
+            format!("{} {}, {:#x}, {}", mnemonic, x(i_type.rd()), i_type.imm().value(), x(i_type.rs1()))
+        }
+        RV32Instruction::AtomicMemoryOperation(a_type) if mnemonic == LR_W.mnemonic => {
+            format!("{} {}, ({})", mnemonic, x(a_type.rd()), x(a_type.rs1()))
+        }
+        RV32Instruction::AtomicMemoryOperation(a_type) => {
+            format!("{} {}, {}, ({})", mnemonic, x(a_type.rd()), x(a_type.rs2()), x(a_type.rs1()))
+        }
+        _ => mnemonic.to_string(),
+    }
+}
+
+// The mnemonic of an instruction, without its operands, nor any pseudo-instruction it stands for: a
+// NOP is an "addi"
+pub fn mnemonic(instruction: &RV32Instruction) -> &'static str {
+    match instruction {
+        // Branches are told apart by funct3 alone
+        RV32Instruction::ConditionBranch(b_type) => Funct3BranchTable::try_from(b_type.funct3().value())
+            .map_or(UNKNOWN_MNEMONIC, |funct3| funct3.mnemonic()),
+        _ => RV32I.describe(*instruction).map_or_else(|| opcode_group(instruction), |descr| descr.mnemonic),
+    }
+}
+
+// Like `disassemble`, for a raw word. One that does not decode is shown as data, e.g. ".word 0xffffffff",
+// the way GNU objdump does, so that a whole section can be disassembled.
+pub fn disassemble_word(word: Word) -> String {
+    RV32I
+        .decode(word)
+        .map_or_else(|_| format!(".word {:#010x}", word), |instruction| disassemble(&instruction))
+}
+
+// Like `disassemble`, for the instruction at `address`: the targets of its jump or branch, if any,
+// are shown as labels instead of offsets (see `label`)
+pub fn disassemble_at(instruction: &RV32Instruction, address: Word, symbols: &SymbolTable) -> String {
+    let target = |offset: i64| label(address.wrapping_add(offset as Word), symbols);
+
+    match instruction {
+        RV32Instruction::ConditionBranch(b_type) => format!(
+            "{} {}, {}, {}",
+            mnemonic(instruction),
+            x(b_type.rs1()),
+            x(b_type.rs2()),
+            target(b_type.immediate())
+        ),
+        RV32Instruction::UnconditionalJump(j_type) if j_type.rd().value() == 0 => {
+            format!("j {}", target(j_type.immediate()))
+        }
+        RV32Instruction::UnconditionalJump(j_type) => {
+            format!("{} {}, {}", mnemonic(instruction), x(j_type.rd()), target(j_type.immediate()))
+        }
+        _ => disassemble(instruction),
+    }
+}
+
+// The name `symbols` gives to `address`, or else one made after it, e.g. "L_8000abcd"
+pub fn label(address: Word, symbols: &SymbolTable) -> String {
+    symbols
+        .get(&address)
+        .cloned()
+        .unwrap_or_else(|| format!("L_{:08x}", address))
+}
+
+// Recognizes the canonical forms of the common pseudo-instructions, as listed in the RISC-V assembly manual
+fn pseudo_instruction(instruction: &RV32Instruction) -> Option<String> {
+    match *instruction {
+        RV32Instruction::IntegerRegisterImmediate(i_type) if RV32I.match_instruction(*instruction, &ADDI) => {
+            if i_type.rs1().value() == 0 {
+                Some(format!("li {}, {}", x(i_type.rd()), i_type.immediate()))
+            } else if i_type.immediate() == 0 {
+                Some(format!("mv {}, {}", x(i_type.rd()), x(i_type.rs1())))
+            } else {
+                None
+            }
+        }
+        RV32Instruction::UnconditionalJump(j_type)
+            if j_type.rd().value() == 0 && RV32I.match_instruction(*instruction, &JAL) =>
+        {
+            Some(format!("j {}", j_type.immediate()))
+        }
+        RV32Instruction::JumpAndLinkRegister(i_type)
+            if i_type.rd().value() == 0
+                && i_type.rs1().value() == 1
+                && i_type.immediate() == 0
+                && RV32I.match_instruction(*instruction, &JALR) =>
+        {
+            Some("ret".to_string())
+        }
+        _ => None,
+    }
+}
+
+// Instructions known by no descriptor are named after their opcode group, at least
+fn opcode_group(instruction: &RV32Instruction) -> &'static str {
+    let opcode = RV32I.get_opcode(*instruction).value();
+    classify_opcode(opcode).map_or(UNKNOWN_MNEMONIC, |opcode| opcode.asm_group())
+}
+
+fn x(register: Rs5) -> String { format!("x{}", register.value()) }
+
+// The machine-readable counterpart of `disassemble`: the mnemonic of an instruction and its operands,
+// e.g. {"mnemonic": "addi", "rd": "x1", "rs1": "x0", "imm": 5}. Immediates are signed, as returned by
+// `RV32Instruction::fields`; ECALL and the like have none.
+#[cfg(feature = "serde")]
+pub fn to_json(instruction: &RV32Instruction) -> Value {
+    let mut json = Map::new();
+    json.insert("mnemonic".to_string(), Value::from(mnemonic(instruction)));
+
+    if instruction.kind() != InstructionKind::EnvironmentCallAndBreakpoint {
+        for (field, value) in instruction.fields() {
+            let (name, value) = match field {
+                FieldName::Rd => ("rd", Value::from(format!("x{value}"))),
+                FieldName::Rs1 => ("rs1", Value::from(format!("x{value}"))),
+                FieldName::Rs2 => ("rs2", Value::from(format!("x{value}"))),
+                FieldName::Imm => ("imm", Value::from(value as i64)),
+                _ => continue,
+            };
+            json.insert(name.to_string(), value);
+        }
+    }
+
+    Value::Object(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitfield::Opcode7Table;
+    use crate::encoder::b_type;
+    use crate::instruction::NOP;
+
+    #[test]
+    fn should_disassemble_addi() {
+        let instruction = RV32I.decode(0x0051_0093).unwrap();
+
+        assert_eq!(disassemble(&instruction), "addi x1, x2, 5");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn should_render_addi_as_json() {
+        let instruction = RV32I.decode(0x0050_0093).unwrap();
+
+        assert_eq!(
+            to_json(&instruction),
+            serde_json::json!({ "mnemonic": "addi", "rd": "x1", "rs1": "x0", "imm": 5 })
+        );
+    }
+
+    #[test]
+    fn should_disassemble_undecodable_words_as_data() {
+        assert_eq!(disassemble_word(0xFFFF_FFFF), ".word 0xffffffff");
+        assert_eq!(disassemble_word(0x0051_0093), "addi x1, x2, 5");
+    }
+
+    #[test]
+    fn should_disassemble_the_canonical_nop() {
+        let nop = RV32I.decode(NOP).unwrap();
+        let addi = RV32I.decode(0x0000_8013).unwrap(); // addi x0, x1, 0
+
+        assert_eq!(disassemble(&nop), "nop");
+        assert_eq!(disassemble(&addi), "mv x0, x1");
+    }
+
+    #[test]
+    fn should_disassemble_negative_immediates() {
+        let instruction = RV32I.decode(0xFFF0_8093).unwrap();
+
+        assert_eq!(disassemble(&instruction), "addi x1, x1, -1");
+    }
+
+    #[test]
+    fn should_disassemble_jumps() {
+        let jal = RV32I.decode(0x0080_00EF).unwrap();
+        let jalr = RV32I.decode(0x0042_8067).unwrap();
+
+        assert_eq!(disassemble(&jal), "jal x1, 8");
+        assert_eq!(disassemble(&jalr), "jalr x0, 4(x5)");
+    }
+
+    #[test]
+    fn should_label_a_backward_branch_after_its_target_symbol() {
+        let bne = b_type(Opcode7Table::Branch, Funct3BranchTable::BNE.into(), 1, 0, -8i32 as Word);
+        let bne = RV32I.decode(bne).unwrap(); // bne x1, x0, -8
+        let jal = RV32I.decode(0x0080_00EF).unwrap(); // jal x1, 8
+        let symbols = SymbolTable::from([(0x8000_0000, "loop".to_string())]);
+
+        assert_eq!(disassemble(&bne), "bne x1, x0, -8");
+        assert_eq!(disassemble_at(&bne, 0x8000_0008, &symbols), "bne x1, x0, loop");
+        assert_eq!(disassemble_at(&jal, 0x8000_0008, &symbols), "jal x1, L_80000010");
+    }
+
+    #[test]
+    fn should_disassemble_pseudo_instructions() {
+        let mv = RV32I.decode(0x0001_0513).unwrap(); // addi x10, x2, 0
+        let li = RV32I.decode(0xFFF0_0293).unwrap(); // addi x5, x0, -1
+        let j = RV32I.decode(0xFFDF_F06F).unwrap(); // jal x0, -4
+        let ret = RV32I.decode(0x0000_8067).unwrap(); // jalr x0, 0(x1)
+
+        assert_eq!(disassemble(&mv), "mv x10, x2");
+        assert_eq!(disassemble(&li), "li x5, -1");
+        assert_eq!(disassemble(&j), "j -4");
+        assert_eq!(disassemble(&ret), "ret");
+    }
+
+    #[test]
+    fn should_disassemble_atomic_instructions() {
+        let lr = RV32I.decode(0x1005_22AF).unwrap();
+        let sc = RV32I.decode(0x1875_232F).unwrap();
+
+        assert_eq!(disassemble(&lr), "lr.w x5, (x10)");
+        assert_eq!(disassemble(&sc), "sc.w x6, x7, (x10)");
+    }
+
+    #[test]
+    fn should_name_the_base_mnemonic_of_pseudo_instructions() {
+        assert_eq!(mnemonic(&RV32I.decode(NOP).unwrap()), "addi");
+        assert_eq!(mnemonic(&RV32I.decode(0x0000_8067).unwrap()), "jalr"); // ret
+        assert_eq!(mnemonic(&RV32I.decode(0x0010_0073).unwrap()), "ebreak");
+    }
+
+    #[test]
+    fn should_disassemble_ecall() {
+        let instruction = RV32I.decode(0x0000_0073).unwrap();
+
+        assert_eq!(disassemble(&instruction), "ecall");
+    }
+
+    #[test]
+    fn should_disassemble_csr_instructions_and_mret() {
+        let csrrw = RV32I.decode(0x3050_9073).unwrap();
+        let csrrsi = RV32I.decode(0x3004_6173).unwrap();
+        let mret = RV32I.decode(0x3020_0073).unwrap();
+
+        assert_eq!(disassemble(&csrrw), "csrrw x0, 0x305, x1");
+        assert_eq!(disassemble(&csrrsi), "csrrsi x2, 0x300, 8");
+        assert_eq!(disassemble(&mret), "mret");
+    }
+}
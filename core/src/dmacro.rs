@@ -29,7 +29,8 @@ macro_rules! enum_aliases {
 
 #[macro_export]
 macro_rules! impl_common_bitfield_traits {
-    ($($enumType:ident),* $(,)?) => {
+    // Each bitfield type is followed by the opcodes (Opcode7Table variants) its format is used by
+    ($($enumType:ident : $($opcode:ident)|+),* $(,)?) => {
         $(
             impl derive_more::Display for $enumType {
                 fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -37,11 +38,53 @@ macro_rules! impl_common_bitfield_traits {
                 }
             }
 
-            impl From<crate::memory::Word> for $enumType {
-                fn from(value: crate::memory::Word) -> Self {
-                    $enumType::new_with_raw_value(value.value)
+            // Rejects words whose opcode does not belong to the format, instead of blindly reinterpreting them
+            impl TryFrom<crate::memory::Word> for $enumType {
+                type Error = anyhow::Error;
+
+                fn try_from(value: crate::memory::Word) -> anyhow::Result<Self> {
+                    let bitfield = $enumType::new_with_raw_value(value);
+
+                    match crate::bitfield::Opcode7Table::try_from(bitfield.opcode()) {
+                        $(Ok(crate::bitfield::Opcode7Table::$opcode))|+ => Ok(bitfield),
+                        _ => Err(anyhow::Error::msg(format!(
+                            "Opcode {:#09b} is not a {}",
+                            bitfield.opcode().value(),
+                            stringify!($enumType)
+                        ))),
+                    }
                 }
             }
+
+            impl $enumType {
+                // The whole instruction word backing the bitfield
+                pub fn raw(&self) -> crate::memory::Word { self.raw_value() }
+            }
+        )*
+    };
+}
+
+/// Assembles RV32 instructions into a `Vec<Word>`, for tests:
+///
+/// ```ignore
+/// let program = rv32asm! { addi x1, x0, 5; add x2, x1, x1 };
+/// ```
+#[cfg(any(test, feature = "testing"))]
+#[macro_export]
+macro_rules! rv32asm {
+    ($($tokens:tt)*) => {
+        $crate::encoder::assemble(stringify!($($tokens)*)).expect("Invalid assembly")
+    };
+}
+
+#[macro_export]
+macro_rules! impl_mnemonic_display {
+    // Displays each type as its mnemonic() (rather than the Rust variant name, which is what Debug shows)
+    ($($enumType:ident),* $(,)?) => {
+        $(
+            impl fmt::Display for $enumType {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(self.mnemonic()) }
+            }
         )*
     };
 }
@@ -0,0 +1,340 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use anyhow::{bail, Context, Result};
+use arbitrary_int::Number;
+use derive_more::{Display, Error};
+
+use crate::architecture::InstructionKind;
+use crate::bitfield::{Funct3, Opcode7Table};
+use crate::instruction::{
+    Descriptor, RV32A_INSTRUCTIONS, RV32I_INSTRUCTIONS, RV32M_INSTRUCTIONS, RV64I_INSTRUCTIONS, ZICSR_INSTRUCTIONS,
+};
+use crate::memory::Word;
+
+const ECALL: Word = 0x0000_0073;
+const EBREAK: Word = 0x0010_0073;
+const MRET: Word = 0x3020_0073;
+const SRET: Word = 0x1020_0073;
+const WFI: Word = 0x1050_0073;
+
+// Width of the immediates of each format, in bits
+const I_IMMEDIATE_BITS: u32 = 12;
+const S_IMMEDIATE_BITS: u32 = 12;
+const B_IMMEDIATE_BITS: u32 = 13;
+const U_IMMEDIATE_BITS: u32 = 20; // the upper 20 bits, as written in assembly (e.g. "lui x1, 0x12345")
+const J_IMMEDIATE_BITS: u32 = 21;
+const CSR_BITS: u32 = 12;
+const CSR_IMMEDIATE_BITS: u32 = 5;
+const SHAMT_BITS: u32 = 5;
+
+#[derive(Debug, Display, Eq, Error, PartialEq)]
+pub enum EncodeError {
+    // Immediates may be written either signed or unsigned: from -2^(bits - 1) to 2^bits - 1
+    #[display("Immediate {value} does not fit in {bits} bits")]
+    ImmediateOutOfRange { value: i64, bits: u32 },
+}
+
+// Encodes RV32 assembly, e.g. "addi x1, x0, 5; add x2, x1, x1", one word per instruction.
+// Statements are separated by `;`; registers are given by number, immediates in decimal or hex.
+pub fn assemble(source: &str) -> Result<Vec<Word>> {
+    source
+        .split(';')
+        .map(normalize)
+        .filter(|statement| !statement.is_empty())
+        .map(|statement| assemble_statement(&statement))
+        .collect()
+}
+
+pub fn r_type(opcode: Opcode7Table, funct3: u8, funct7: u8, rd: Word, rs1: Word, rs2: Word) -> Word {
+    (funct7 as Word) << 25 | rs2 << 20 | rs1 << 15 | (funct3 as Word) << 12 | rd << 7 | u8::from(opcode) as Word
+}
+
+pub fn i_type(opcode: Opcode7Table, funct3: u8, rd: Word, rs1: Word, imm: Word) -> Word {
+    (imm & 0xFFF) << 20 | rs1 << 15 | (funct3 as Word) << 12 | rd << 7 | u8::from(opcode) as Word
+}
+
+pub fn s_type(opcode: Opcode7Table, funct3: u8, rs1: Word, rs2: Word, imm: Word) -> Word {
+    (imm >> 5 & 0x7F) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | (funct3 as Word) << 12
+        | (imm & 0x1F) << 7
+        | u8::from(opcode) as Word
+}
+
+pub fn b_type(opcode: Opcode7Table, funct3: u8, rs1: Word, rs2: Word, imm: Word) -> Word {
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3F) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | (funct3 as Word) << 12
+        | (imm >> 1 & 0xF) << 8
+        | (imm >> 11 & 1) << 7
+        | u8::from(opcode) as Word
+}
+
+// `imm` is the value loaded in rd, only its upper 20 bits are kept
+pub fn u_type(opcode: Opcode7Table, rd: Word, imm: Word) -> Word {
+    imm & 0xFFFF_F000 | rd << 7 | u8::from(opcode) as Word
+}
+
+pub fn j_type(opcode: Opcode7Table, rd: Word, imm: Word) -> Word {
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3FF) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xFF) << 12
+        | rd << 7
+        | u8::from(opcode) as Word
+}
+
+fn assemble_statement(statement: &str) -> Result<Word> {
+    let (mnemonic, operands) = statement.split_once(' ').unwrap_or((statement, ""));
+    let operands: Vec<&str> = operands.split(',').filter(|operand| !operand.is_empty()).collect();
+
+    match mnemonic {
+        "ecall" => return Ok(ECALL),
+        "ebreak" => return Ok(EBREAK),
+        "mret" => return Ok(MRET),
+        "sret" => return Ok(SRET),
+        "wfi" => return Ok(WFI),
+        _ => {}
+    }
+
+    let descr = RV32I_INSTRUCTIONS
+        .iter()
+        .chain(RV32M_INSTRUCTIONS)
+        .chain(RV32A_INSTRUCTIONS)
+        .chain(ZICSR_INSTRUCTIONS)
+        .chain(RV64I_INSTRUCTIONS)
+        .find(|descr| descr.mnemonic == mnemonic)
+        .with_context(|| format!("Unknown instruction: {}", mnemonic))?;
+
+    let opcode = descr.opcode.context("Instruction without opcode")?;
+    let funct3 = funct3(descr);
+
+    Ok(match (&descr.format, operands.as_slice()) {
+        (InstructionKind::IntegerRegisterImmediate, [rd, rs1, shamt]) if descr.funct7.is_some() => {
+            let funct7 = descr.funct7.map_or(0, u8::from) as Word;
+            let shamt = immediate(shamt, SHAMT_BITS)? & ((1 << SHAMT_BITS) - 1);
+            i_type(opcode, funct3, register(rd)?, register(rs1)?, funct7 << SHAMT_BITS | shamt)
+        }
+        (InstructionKind::IntegerRegisterImmediate, [rd, rs1, imm]) => {
+            i_type(opcode, funct3, register(rd)?, register(rs1)?, immediate(imm, I_IMMEDIATE_BITS)?)
+        }
+        (InstructionKind::IntegerRegisterRegister, [rd, rs1, rs2]) => {
+            let funct7 = descr.funct7.map_or(0, u8::from);
+            r_type(opcode, funct3, funct7, register(rd)?, register(rs1)?, register(rs2)?)
+        }
+        (InstructionKind::UnconditionalJump, [rd, imm]) => {
+            j_type(opcode, register(rd)?, immediate(imm, J_IMMEDIATE_BITS)?)
+        }
+        (InstructionKind::ConditionBranch, [rs1, rs2, imm]) => {
+            let offset = immediate(imm, B_IMMEDIATE_BITS)?;
+            if offset & 1 != 0 {
+                bail!("Branch offsets are even: {}", imm);
+            }
+            b_type(opcode, funct3, register(rs1)?, register(rs2)?, offset)
+        }
+        (InstructionKind::UpperImmediate, [rd, imm]) => {
+            u_type(opcode, register(rd)?, immediate(imm, U_IMMEDIATE_BITS)? << 12)
+        }
+        (InstructionKind::JumpAndLinkRegister | InstructionKind::Load, [rd, address]) => {
+            let (imm, rs1) = address_operand(address, I_IMMEDIATE_BITS)?;
+            i_type(opcode, funct3, register(rd)?, rs1, imm)
+        }
+        (InstructionKind::Store, [rs2, address]) => {
+            let (imm, rs1) = address_operand(address, S_IMMEDIATE_BITS)?;
+            s_type(opcode, funct3, rs1, register(rs2)?, imm)
+        }
+        (InstructionKind::ControlAndStatusRegister, [rd, csr, source]) => {
+            // csrrwi, csrrsi and csrrci take an immediate instead of rs1
+            let source = if funct3 & 0b100 != 0 { immediate(source, CSR_IMMEDIATE_BITS)? } else { register(source)? };
+            i_type(opcode, funct3, register(rd)?, source, immediate(csr, CSR_BITS)?)
+        }
+        (InstructionKind::AtomicMemoryOperation, [rd, rs2, address]) => {
+            atomic(descr, register(rd)?, register(rs2)?, address)?
+        }
+        (InstructionKind::AtomicMemoryOperation, [rd, address]) => atomic(descr, register(rd)?, 0, address)?, // LR
+        _ => bail!("Invalid operands: {}", statement),
+    })
+}
+
+// aq and rl are never set
+fn atomic(descr: &Descriptor, rd: Word, rs2: Word, address: &str) -> Result<Word> {
+    let (offset, rs1) = address_operand(address, I_IMMEDIATE_BITS)?;
+    if offset != 0 {
+        bail!("Atomic instructions take no offset: {}", address);
+    }
+
+    let funct7 = descr.funct5.map_or(0, u8::from) << 2;
+    Ok(r_type(Opcode7Table::AtomicMemoryOp, funct3(descr), funct7, rd, rs1, rs2))
+}
+
+fn funct3(descr: &Descriptor) -> u8 { descr.funct3.map_or(0, |funct3| Into::<Funct3>::into(funct3).value()) }
+
+fn register(operand: &str) -> Result<Word> {
+    operand
+        .strip_prefix('x')
+        .and_then(|number| number.parse().ok())
+        .filter(|number| *number < 32)
+        .with_context(|| format!("Invalid register: {}", operand))
+}
+
+fn immediate(operand: &str, bits: u32) -> Result<Word> {
+    let (negative, magnitude) = match operand.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, operand),
+    };
+
+    let value = match magnitude.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => magnitude.parse(),
+    }
+    .with_context(|| format!("Invalid immediate: {}", operand))?;
+
+    let value = if negative { -value } else { value };
+    if value < -(1 << (bits - 1)) || value >= 1 << bits {
+        return Err(EncodeError::ImmediateOutOfRange { value, bits }.into());
+    }
+
+    Ok(value as Word)
+}
+
+// "offset(register)", where the offset may be omitted
+fn address_operand(operand: &str, bits: u32) -> Result<(Word, Word)> {
+    let (offset, register_operand) = operand
+        .strip_suffix(')')
+        .and_then(|operand| operand.split_once('('))
+        .with_context(|| format!("Invalid address: {}", operand))?;
+
+    let offset = if offset.is_empty() { 0 } else { immediate(offset, bits)? };
+    Ok((offset, register(register_operand)?))
+}
+
+// Drops the whitespace not separating two words, so that "lr . w x5 , ( x10 )" (as `stringify!`
+// may render it) reads "lr.w x5,(x10)"
+fn normalize(statement: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let chars: Vec<char> = statement.trim().chars().collect();
+    let mut normalized = String::with_capacity(chars.len());
+
+    for (index, c) in chars.iter().enumerate() {
+        if !c.is_whitespace() {
+            normalized.push(*c);
+            continue;
+        }
+
+        let previous = normalized.chars().last();
+        let next = chars[index..].iter().find(|c| !c.is_whitespace());
+
+        if previous.is_some_and(is_word) && next.is_some_and(|c| is_word(*c)) && !normalized.ends_with(' ') {
+            normalized.push(' ');
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_assemble_a_sequence_of_instructions() {
+        let words = assemble("addi x1, x0, 5; add x2, x1, x1; ecall").unwrap();
+
+        assert_eq!(words, vec![0x0050_0093, 0x0010_8133, 0x0000_0073]);
+    }
+
+    #[test]
+    fn should_assemble_with_the_rv32asm_macro() {
+        let words = crate::rv32asm! {
+            addi x1, x1, -1;
+            sw x10, 4(x11);
+            lr.w x5, (x10)
+        };
+
+        assert_eq!(words, vec![0xFFF0_8093, 0x00A5_A223, 0x1005_22AF]);
+    }
+
+    #[test]
+    fn should_assemble_jumps_and_atomics() {
+        assert_eq!(assemble("jal x0, -4").unwrap(), vec![0xFFDF_F06F]);
+        assert_eq!(assemble("jalr x0, 0(x1)").unwrap(), vec![0x0000_8067]);
+        assert_eq!(assemble("sc.w x6, x7, (x10)").unwrap(), vec![0x1875_232F]);
+        assert_eq!(assemble("sub x3, x1, x2").unwrap(), vec![0x4020_81B3]);
+    }
+
+    #[test]
+    fn should_assemble_shifts_by_an_immediate() {
+        assert_eq!(assemble("srai x1, x2, 3").unwrap(), vec![0x4031_5093]);
+        assert_eq!(assemble("srli x1, x2, 3").unwrap(), vec![0x0031_5093]);
+    }
+
+    #[test]
+    fn should_assemble_csr_instructions() {
+        assert_eq!(assemble("csrrw x0, 0x305, x1").unwrap(), vec![0x3050_9073]);
+        assert_eq!(assemble("csrrsi x2, 0x300, 8").unwrap(), vec![0x3004_6173]);
+        assert_eq!(assemble("mret").unwrap(), vec![0x3020_0073]);
+        assert_eq!(assemble("wfi").unwrap(), vec![0x1050_0073]);
+    }
+
+    #[test]
+    fn should_reject_immediates_out_of_range() {
+        let error = assemble("addi x1, x0, 4096").unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<EncodeError>(),
+            Some(&EncodeError::ImmediateOutOfRange { value: 4096, bits: 12 })
+        );
+        assert_eq!(assemble("addi x1, x0, 4095").unwrap(), vec![0xFFF0_0093]);
+        assert!(assemble("addi x1, x0, -2049").is_err());
+        assert!(assemble("sw x1, 0x1000(x2)").is_err());
+        assert!(assemble("jal x0, 0x200000").is_err());
+    }
+
+    #[test]
+    fn should_assemble_branches_and_upper_immediates() {
+        assert_eq!(assemble("beq x1, x2, 8").unwrap(), vec![0x0020_8463]);
+        assert_eq!(assemble("bgeu x1, x2, -4").unwrap(), vec![0xFE20_FEE3]);
+        assert_eq!(assemble("lui x1, 0x12345").unwrap(), vec![0x1234_50B7]);
+        assert_eq!(assemble("auipc x3, -1").unwrap(), vec![0xFFFF_F197]);
+    }
+
+    #[test]
+    fn should_reject_branch_and_upper_immediates_out_of_range() {
+        assert_eq!(
+            assemble("beq x1, x2, 8192").unwrap_err().downcast_ref::<EncodeError>(),
+            Some(&EncodeError::ImmediateOutOfRange { value: 8192, bits: 13 })
+        );
+        assert!(assemble("blt x1, x2, -4098").is_err());
+        assert!(assemble("bne x1, x2, 3").is_err());
+        assert_eq!(assemble("bne x1, x2, -4096").unwrap(), vec![0x8020_9063]);
+        assert_eq!(
+            assemble("lui x1, 0x100000").unwrap_err().downcast_ref::<EncodeError>(),
+            Some(&EncodeError::ImmediateOutOfRange { value: 0x10_0000, bits: 20 })
+        );
+        assert_eq!(assemble("lui x1, 0xFFFFF").unwrap(), vec![0xFFFF_F0B7]);
+    }
+
+    #[test]
+    fn should_reject_unknown_instructions_and_operands() {
+        assert!(assemble("frobnicate x1").is_err());
+        assert!(assemble("addi x1, x32, 0").is_err());
+        assert!(assemble("add x1, x2").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use crate::bitfield::{
+    Funct3BranchTable, Funct3LoadTable, Funct3OpImmediateTable, Funct3OpRegisterTable, Funct3StoreTable, Funct7Table,
+    Opcode7Table,
+};
+use crate::encoder::{b_type, i_type, j_type, r_type, s_type, u_type};
+use crate::memory::Word;
+
+const SHAMT_MASK: i32 = 0x1F;
+
+// Encodings of the RV32I instructions, e.g. `addi(1, 0, 5)` for "addi x1, x0, 5", for tests that
+// build programs out of words. Registers are given by number; immediates are signed, and only their
+// bits in the encoding are kept. Branch and jump offsets are relative to the instruction.
+
+macro_rules! register_immediate {
+    ($($name:ident = $funct3:ident),* $(,)?) => {
+        $(
+            pub fn $name(rd: Word, rs1: Word, imm: i32) -> Word {
+                i_type(Opcode7Table::OpImmediate, Funct3OpImmediateTable::$funct3.into(), rd, rs1, imm as Word)
+            }
+        )*
+    };
+}
+
+macro_rules! shift_immediate {
+    ($($name:ident = $funct3:ident, $funct7:ident),* $(,)?) => {
+        $(
+            pub fn $name(rd: Word, rs1: Word, shamt: i32) -> Word {
+                let funct7 = u8::from(Funct7Table::$funct7) as i32;
+                let imm = funct7 << 5 | shamt & SHAMT_MASK;
+                i_type(Opcode7Table::OpImmediate, Funct3OpImmediateTable::$funct3.into(), rd, rs1, imm as Word)
+            }
+        )*
+    };
+}
+
+macro_rules! register_register {
+    ($($name:ident = $funct3:ident, $funct7:ident),* $(,)?) => {
+        $(
+            pub fn $name(rd: Word, rs1: Word, rs2: Word) -> Word {
+                let (funct3, funct7) = (Funct3OpRegisterTable::$funct3.into(), Funct7Table::$funct7.into());
+                r_type(Opcode7Table::OpRegister, funct3, funct7, rd, rs1, rs2)
+            }
+        )*
+    };
+}
+
+macro_rules! load {
+    ($($name:ident = $funct3:ident),* $(,)?) => {
+        $(
+            pub fn $name(rd: Word, rs1: Word, offset: i32) -> Word {
+                i_type(Opcode7Table::Load, Funct3LoadTable::$funct3.into(), rd, rs1, offset as Word)
+            }
+        )*
+    };
+}
+
+macro_rules! store {
+    ($($name:ident = $funct3:ident),* $(,)?) => {
+        $(
+            pub fn $name(rs2: Word, rs1: Word, offset: i32) -> Word {
+                s_type(Opcode7Table::Store, Funct3StoreTable::$funct3.into(), rs1, rs2, offset as Word)
+            }
+        )*
+    };
+}
+
+macro_rules! branch {
+    ($($name:ident = $funct3:ident),* $(,)?) => {
+        $(
+            pub fn $name(rs1: Word, rs2: Word, offset: i32) -> Word {
+                b_type(Opcode7Table::Branch, Funct3BranchTable::$funct3.into(), rs1, rs2, offset as Word)
+            }
+        )*
+    };
+}
+
+register_immediate!(addi = ADDI, slti = SLTI, sltiu = SLTIU, xori = XORI, ori = ORI, andi = ANDI);
+shift_immediate!(slli = SLLI, Logical, srli = SRLI, Logical, srai = SRAI, Arithmetic);
+register_register!(
+    add = ADD, Logical,
+    sub = SUB, Arithmetic,
+    sll = SLL, Logical,
+    slt = SLT, Logical,
+    sltu = SLTU, Logical,
+    xor = XOR, Logical,
+    srl = SRL, Logical,
+    sra = SRA, Arithmetic,
+    or = OR, Logical,
+    and = AND, Logical,
+);
+load!(lb = LB, lh = LH, lw = LW, lbu = LBU, lhu = LHU);
+store!(sb = SB, sh = SH, sw = SW);
+branch!(beq = BEQ, bne = BNE, blt = BLT, bge = BGE, bltu = BLTU, bgeu = BGEU);
+
+// `imm` is the value loaded in rd, whose lower 12 bits are dropped
+pub fn lui(rd: Word, imm: i32) -> Word { u_type(Opcode7Table::LoadUpperImmediate, rd, imm as Word) }
+
+pub fn auipc(rd: Word, imm: i32) -> Word { u_type(Opcode7Table::AddUpperImmediatePC, rd, imm as Word) }
+
+pub fn jal(rd: Word, offset: i32) -> Word { j_type(Opcode7Table::JumpAndLink, rd, offset as Word) }
+
+pub fn jalr(rd: Word, rs1: Word, offset: i32) -> Word {
+    i_type(Opcode7Table::JumpAndLinkRegister, 0, rd, rs1, offset as Word)
+}
+
+// `predecessor` and `successor` are the sets of accesses ordered, as IORW bits, e.g. 0b1111 for "iorw"
+pub fn fence(predecessor: Word, successor: Word) -> Word {
+    i_type(Opcode7Table::MiscMemory, 0, 0, 0, predecessor << 4 | successor)
+}
+
+pub fn ecall() -> Word { 0x0000_0073 }
+
+pub fn ebreak() -> Word { 0x0010_0073 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected encodings as given by an assembler (riscv64-unknown-elf-as -march=rv32i, or
+    // llvm-mc -triple=riscv32 -show-encoding), one at least per helper
+    #[test]
+    fn should_encode_as_an_assembler_does() {
+        assert_eq!(addi(1, 0, 5), 0x0050_0093);
+        assert_eq!(addi(1, 1, -1), 0xFFF0_8093);
+        assert_eq!(slti(5, 6, -7), 0xFF93_2293);
+        assert_eq!(sltiu(5, 6, 9), 0x0093_3293);
+        assert_eq!(xori(5, 6, -1), 0xFFF3_4293);
+        assert_eq!(ori(7, 8, 2047), 0x7FF4_6393);
+        assert_eq!(andi(7, 8, 255), 0x0FF4_7393);
+        assert_eq!(slli(1, 2, 31), 0x01F1_1093);
+        assert_eq!(srli(1, 2, 3), 0x0031_5093);
+        assert_eq!(srai(1, 2, 3), 0x4031_5093);
+        assert_eq!(add(3, 1, 2), 0x0020_81B3);
+        assert_eq!(sub(3, 1, 2), 0x4020_81B3);
+        assert_eq!(sll(3, 1, 2), 0x0020_91B3);
+        assert_eq!(slt(3, 1, 2), 0x0020_A1B3);
+        assert_eq!(sltu(3, 1, 2), 0x0020_B1B3);
+        assert_eq!(xor(3, 1, 2), 0x0020_C1B3);
+        assert_eq!(srl(3, 1, 2), 0x0020_D1B3);
+        assert_eq!(sra(3, 1, 2), 0x4020_D1B3);
+        assert_eq!(or(3, 1, 2), 0x0020_E1B3);
+        assert_eq!(and(3, 1, 2), 0x0020_F1B3);
+        assert_eq!(lb(2, 2, -4), 0xFFC1_0103);
+        assert_eq!(lh(2, 3, 6), 0x0061_9103);
+        assert_eq!(lw(2, 2, -4), 0xFFC1_2103);
+        assert_eq!(lbu(9, 10, 2047), 0x7FF5_4483);
+        assert_eq!(lhu(9, 10, -2048), 0x8005_5483);
+        assert_eq!(sb(10, 11, 4), 0x00A5_8223);
+        assert_eq!(sh(10, 11, -2), 0xFEA5_9F23);
+        assert_eq!(sw(10, 11, 4), 0x00A5_A223);
+        assert_eq!(beq(1, 2, 16), 0x0020_8863);
+        assert_eq!(bne(1, 0, -8), 0xFE00_9CE3);
+        assert_eq!(blt(3, 4, 2048), 0x0041_C0E3);
+        assert_eq!(bge(3, 4, -4096), 0x8041_D063);
+        assert_eq!(bltu(5, 6, 8), 0x0062_E463);
+        assert_eq!(bgeu(5, 6, -2), 0xFE62_FFE3);
+        assert_eq!(lui(5, 0x12345000), 0x1234_52B7);
+        assert_eq!(auipc(6, -0x1000), 0xFFFF_F317);
+        assert_eq!(jal(1, 2048), 0x0010_00EF);
+        assert_eq!(jal(0, -4), 0xFFDF_F06F);
+        assert_eq!(jalr(0, 1, 0), 0x0000_8067);
+        assert_eq!(jalr(1, 5, -12), 0xFF42_80E7);
+        assert_eq!(fence(0b1111, 0b1111), 0x0FF0_000F);
+        assert_eq!(fence(0b0011, 0b0001), 0x0310_000F);
+        assert_eq!(ecall(), 0x0000_0073);
+        assert_eq!(ebreak(), 0x0010_0073);
+    }
+}
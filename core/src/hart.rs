@@ -14,69 +14,1687 @@
 // limitations under the Licence.
 //
 
-use crate::architecture::{Architecture, Instruction, InstructionSet, RV32Instruction, RV32I};
-use crate::instruction::ADDI;
-use crate::memory::{InstructionLength, Memory, VecMemory, Word};
-use crate::register::{RegisterValue64, Registers64};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::alu::{
+    add_xlen, div_xlen, divu_xlen, mulh_xlen, mulhsu_xlen, mulhu_xlen, rem_xlen, remu_xlen, sign_extend, sll_xlen,
+    sra_xlen, srl_xlen, truncate_xlen,
+};
+use crate::architecture::{
+    Architecture, Ext, FieldName, Instruction, InstructionSet, RV32Instruction, Xlen, RV32I, RV64I,
+};
+use crate::bus::Bus;
+use crate::bitfield::{
+    classify_opcode, Funct3BranchTable, Funct3Expr, Funct3SystemTable, Immediate11Table, Opcode7Table, RType32Bitfield,
+};
+use crate::compressed::decode_compressed;
+use crate::csr::{
+    ControlStatusRegisters, CsrAddress, MIE_MTIE, MIP_MTIP, MSTATUS_MIE, MSTATUS_MPIE, MSTATUS_MPP, MTVEC_MODE,
+    MTVEC_VECTORED,
+};
+use crate::instruction::{
+    encoded_length, instruction_length, Descriptor, ADD, ADDI, AMOADD_W, AMOAND_W, AMOMAXU_W, AMOMAX_W, AMOMINU_W,
+    AMOMIN_W, AMOOR_W, AMOSWAP_W, AMOXOR_W, AND, ANDI, DIV, DIVU, IALIGN, ILEN, JAL, JALR, LR_W, MUL, MULH, MULHSU,
+    MULHU, OR, ORI, REM, REMU, SC_W, SLL, SLLI, SLT, SLTI, SLTIU, SLTU, SRA, SRAI, SRL, SRLI, SUB, XOR, XORI,
+};
+use crate::memory::{Endianness, HalfWord, InstructionLength, Memory, Word};
+use crate::profile::ProfileReport;
+use crate::register::{RV32E_REGISTERS_COUNT, RegisterValue64, Registers64, X0WritePolicy};
+use crate::trace::{TraceEvent, Tracer};
+use crate::trap::{decode_mcause, Interrupt, TrapCause};
+
+// Where harts start fetching, unless configured otherwise (see `SimpleRV32IHart::set_reset_vector`)
+pub const DEFAULT_RESET_VECTOR: RegisterValue64 = 0;
+
+// The decode cache is emptied once it holds that many instructions
+const DECODE_CACHE_CAPACITY: usize = 4096;
+
+// Opcodes left by the specification for custom instructions (see `SimpleRV32IHart::set_custom_handler`)
+pub const CUSTOM_OPCODES: [Opcode7Table; 4] = [
+    Opcode7Table::Custom0,
+    Opcode7Table::Custom1,
+    Opcode7Table::Custom2Rv128,
+    Opcode7Table::Custom3Rv128,
+];
+
+// What a custom instruction gets to change: the registers of its hart, the PC (already past the
+// instruction) included, and the memory the hart sees through its bus
+pub struct HartState<'a> {
+    pub hart_id: usize,
+    pub registers: &'a mut Registers64,
+    pub memory: &'a mut dyn Memory,
+}
 
 // TODO support variable amount of registers
 #[derive(Debug)]
 pub struct SimpleRV32IHart {
     registers: Registers64,
-    ram: VecMemory,
+    csrs: ControlStatusRegisters,
+    reset_vector: RegisterValue64,
+    memory_size: usize,              // the stack pointer is reset to its top
+    aligned_access_required: bool,   // misaligned loads and stores trap, instead of being carried out
+    data_endianness: Endianness,     // of loads, stores and atomics; never of instruction fetches
+    extensions: HashSet<Ext>,        // instructions of other extensions are illegal
+    xlen: Xlen,                      // LD and SD are illegal under an XLEN of 32
+    instruction_pc: RegisterValue64, // address of the instruction being executed; `registers.pc` is past it
+    hart_id: usize,                  // mhartid
+    icount: u64,                     // instructions retired, ever (not reset)
+    bus: Arc<Mutex<Bus>>,            // shared with the other harts of the machine
+    tracer: Option<Tracer>,
+    x0_watch: bool, // traces the results written to x0 (see `TraceEvent::DiscardedWrite`)
+    x0_write_trapped: bool, // set when the x0 write policy refused an instruction, until the next step
+    decode_cache: Option<HashMap<Word, RV32Instruction>>, // 32-bit instructions, by encoding
+    profile: Option<ProfileReport>,                       // gathered while profiling
+    custom_handlers: HashMap<Opcode7Table, CustomHandler>,
+    waiting_for_interrupt: bool, // set by WFI, until the next step
 }
 
+// Executes the instructions of a custom opcode, given their encoding; it may be shared by several
+// harts (see `Machine::set_custom_handler`), which may run on other threads
+#[derive(Clone)]
+pub struct CustomHandler(Arc<Mutex<dyn FnMut(&mut HartState<'_>, Word) -> Result<(), TrapCause> + Send>>);
+
 pub trait Hart<I: InstructionSet, F: Instruction> {
     type ISA = I;
     type Instruction = F;
 
-    fn execute(&mut self, inst: Self::Instruction);
-    fn fetch(&mut self) -> Option<Self::Instruction>;
+    fn execute(&mut self, inst: Self::Instruction) -> Result<(), TrapCause>;
+
+    // An undecodable chomp is reported as an illegal instruction
+    fn fetch(&mut self) -> Result<Self::Instruction, TrapCause>;
+
+    fn step(&mut self) -> Result<(), TrapCause> {
+        let instruction = self.fetch()?;
+        self.execute(instruction)
+    }
 
     // TODO FINALLY use the disruptor pattern! EDIT: actually crossbeam
     //      each Hart (cpu) should process instructions in their own disruptor
-    //      that way we can gain speed?
+    //      that way we can gain speed? Harts already share their bus (see Machine::with_harts), but
+    //      are still stepped in turns from a single thread.
 }
 
 impl SimpleRV32IHart {
+    // A single hart, alone on its bus
     pub(crate) fn new(memory_size: usize) -> Self {
-        let registers = Registers64::new(memory_size);
-        let ram = VecMemory::new(memory_size);
-        Self { registers, ram }
+        Self::with_shared_bus(0, Arc::new(Mutex::new(Bus::with_ram(memory_size))), memory_size)
+    }
+
+    pub(crate) fn with_shared_bus(hart_id: usize, bus: Arc<Mutex<Bus>>, memory_size: usize) -> Self {
+        let mut hart = Self {
+            registers: Registers64::new(memory_size),
+            csrs: ControlStatusRegisters::new(hart_id),
+            reset_vector: DEFAULT_RESET_VECTOR,
+            memory_size,
+            aligned_access_required: false,
+            data_endianness: Endianness::Little,
+            extensions: HashSet::from(Ext::ALL),
+            xlen: Xlen::X32,
+            instruction_pc: 0,
+            hart_id,
+            icount: 0,
+            bus,
+            tracer: None,
+            x0_watch: false,
+            x0_write_trapped: false,
+            decode_cache: None,
+            profile: None,
+            custom_handlers: HashMap::new(),
+            waiting_for_interrupt: false,
+        };
+        hart.reset();
+        hart
+    }
+
+    // Puts the registers back in their initial state, with the PC at the reset vector. Memory is
+    // left untouched.
+    pub fn reset(&mut self) {
+        let x0_write_policy = self.registers.x0_write_policy();
+        self.registers = Registers64::new(self.memory_size);
+        self.registers.set_x0_write_policy(x0_write_policy);
+        self.csrs = ControlStatusRegisters::new(self.hart_id);
+        self.registers.pc = self.reset_vector;
+        self.instruction_pc = self.reset_vector;
+        self.waiting_for_interrupt = false;
+        self.x0_write_trapped = false;
+    }
+
+    pub fn reset_vector(&self) -> RegisterValue64 { self.reset_vector }
+
+    // Takes effect on the next reset
+    pub fn set_reset_vector(&mut self, reset_vector: RegisterValue64) { self.reset_vector = reset_vector }
+
+    // RISC-V lets each execution environment decide whether misaligned loads and stores are carried
+    // out or trap; they are carried out by default. Atomic instructions always require alignment.
+    pub fn set_aligned_access_required(&mut self, required: bool) { self.aligned_access_required = required }
+
+    pub fn data_endianness(&self) -> Endianness { self.data_endianness }
+
+    pub fn set_data_endianness(&mut self, endianness: Endianness) { self.data_endianness = endianness }
+
+    pub fn extensions(&self) -> &HashSet<Ext> { &self.extensions }
+
+    // Every extension is enabled by default
+    pub fn set_extensions(&mut self, extensions: HashSet<Ext>) { self.extensions = extensions }
+
+    pub fn xlen(&self) -> Xlen { self.xlen }
+
+    // Harts run RV32 by default. Cached decodings may not hold under another XLEN, and are dropped.
+    pub fn set_xlen(&mut self, xlen: Xlen) {
+        self.xlen = xlen;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.clear();
+        }
+    }
+
+    fn check_alignment(&self, address: Word, size: InstructionLength, cause: TrapCause) -> Result<(), TrapCause> {
+        if self.aligned_access_required && address % size.bytes() as Word != 0 {
+            return Err(cause);
+        }
+
+        Ok(())
+    }
+
+    pub fn registers(&self) -> &Registers64 { &self.registers }
+
+    pub fn registers_mut(&mut self) -> &mut Registers64 { &mut self.registers }
+
+    pub fn csrs(&self) -> &ControlStatusRegisters { &self.csrs }
+
+    pub fn csrs_mut(&mut self) -> &mut ControlStatusRegisters { &mut self.csrs }
+
+    pub fn hart_id(&self) -> usize { self.hart_id }
+
+    pub fn icount(&self) -> u64 { self.icount }
+
+    // Whether the last step executed a WFI, the hart having nothing to do until an interrupt comes
+    pub fn waiting_for_interrupt(&self) -> bool { self.waiting_for_interrupt }
+
+    // A hart that panicked while holding the bus cannot leave it half-written: accesses are atomic
+    pub fn bus(&self) -> MutexGuard<'_, Bus> { self.bus.lock().unwrap_or_else(PoisonError::into_inner) }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) { self.tracer = tracer }
+
+    // A diagnostic aid for hand-written programs: ALU instructions targeting x0 are traced, the
+    // canonical NOP aside. Jumps and CSR accesses discarding their result there (j, ret, csrw...) are
+    // idioms, and are not.
+    pub fn set_x0_watch(&mut self, enabled: bool) { self.x0_watch = enabled }
+
+    // Whether the error of the last step came from the x0 write policy (see `X0WritePolicy::Trap`),
+    // the PC being left at the instruction. It is never taken at mtvec: the host, not the guest, acts.
+    pub fn x0_write_trapped(&self) -> bool { self.x0_write_trapped }
+
+    // Saves decoding the same instructions over and over (e.g. in loops). Being keyed by encoding, it
+    // never goes stale: once code is overwritten, the new words are looked up (and decoded) instead.
+    pub fn set_decode_cache(&mut self, enabled: bool) { self.decode_cache = enabled.then(HashMap::new) }
+
+    // Counts, per branch, how often it is taken or not; enabling it again starts over
+    pub fn set_profiling(&mut self, enabled: bool) { self.profile = enabled.then(ProfileReport::default) }
+
+    pub fn profile_report(&self) -> Option<&ProfileReport> { self.profile.as_ref() }
+
+    // Instructions of the custom opcodes are illegal, unless handled here. Handlers run whatever the
+    // enabled extensions, once the PC is past the (32-bit) instruction; their errors are trapped as
+    // any other.
+    pub fn set_custom_handler(&mut self, opcode: Opcode7Table, handler: Option<CustomHandler>) {
+        assert!(CUSTOM_OPCODES.contains(&opcode), "{:?} is not a custom opcode", opcode);
+
+        match handler {
+            Some(handler) => self.custom_handlers.insert(opcode, handler),
+            None => self.custom_handlers.remove(&opcode),
+        };
+    }
+
+    // Decodes the instruction at `pc` without fetching it: the PC, the counters and the decode cache
+    // are left untouched, e.g. for a debugger to disassemble around the PC
+    pub fn peek_instruction(&self, pc: RegisterValue64) -> Option<RV32Instruction> {
+        let (halfword, word) = self.read_instruction(pc as Word);
+        if encoded_length(halfword) > ILEN as u32 {
+            return None;
+        }
+
+        match instruction_length(halfword) {
+            InstructionLength::Word => self.decode_uncached(word),
+            _ if self.extensions.contains(&Ext::C) => decode_compressed(halfword),
+            _ => None,
+        }
+    }
+
+    // Effective address of a jump, load or store; wrapping around is legal, but worth tracing
+    fn address(&mut self, base: RegisterValue64, offset: RegisterValue64) -> RegisterValue64 {
+        let (address, overflowed) = add_xlen(base, offset);
+
+        if overflowed {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.emit(&TraceEvent::AddressOverflow {
+                    pc: self.instruction_pc,
+                    address,
+                });
+            }
+        }
+
+        address
+    }
+
+    // Atomically replaces the word at `address` by `operation` applied to it, returning the old word
+    fn read_modify_write(
+        &mut self,
+        address: Word,
+        operation: impl FnOnce(RegisterValue64) -> RegisterValue64,
+    ) -> Result<RegisterValue64, TrapCause> {
+        let endianness = self.data_endianness;
+        let mut bus = self.bus();
+        let old = bus
+            .load(address, InstructionLength::Word)
+            .ok_or(TrapCause::StoreAccessFault)?;
+        let old = sign_extend(endianness.convert(old, InstructionLength::Word), 32);
+
+        let new = endianness.convert(operation(old), InstructionLength::Word);
+        bus.store(address, InstructionLength::Word, new)
+            .ok_or(TrapCause::StoreAccessFault)?;
+
+        Ok(old)
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<RV32Instruction, TrapCause> {
+        if !self.instruction_mapped(self.registers.pc as Word) {
+            self.instruction_pc = self.registers.pc;
+            return Err(TrapCause::InstructionAccessFault);
+        }
+
+        let instruction = self.fetch()?;
+        self.execute(instruction)?;
+        Ok(instruction)
+    }
+
+    // The handler of the custom instruction at the PC, if there is one, and its encoding
+    fn custom_instruction(&self) -> Option<(CustomHandler, Word)> {
+        if self.custom_handlers.is_empty() {
+            return None;
+        }
+
+        let (_, word) = self.read_instruction(self.registers.pc as Word);
+        let handler = self.custom_handlers.get(&classify_opcode(word as u8 & 0x7F)?)?;
+
+        Some((handler.clone(), word))
+    }
+
+    fn execute_custom(&mut self, handler: &CustomHandler, word: Word) -> Result<(), TrapCause> {
+        self.instruction_pc = self.registers.pc;
+        self.registers.pc = self.next_pc(InstructionLength::Word)?;
+
+        let mut bus = self.bus.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut state = HartState {
+            hart_id: self.hart_id,
+            registers: &mut self.registers,
+            memory: &mut *bus,
+        };
+
+        let mut execute = handler.0.lock().unwrap_or_else(PoisonError::into_inner);
+        (&mut *execute)(&mut state, word)
+    }
+
+    // The address past the instruction at the PC. It never wraps around the top of the address space,
+    // where the PC would silently start over from 0: the instruction faults instead, the PC left on it.
+    fn next_pc(&self, length: InstructionLength) -> Result<RegisterValue64, TrapCause> {
+        let length = length.bytes();
+        (self.registers.pc as Word).checked_add(length as Word).ok_or(TrapCause::InstructionAccessFault)?;
+        Ok(self.registers.pc.wrapping_add(length as RegisterValue64))
+    }
+
+    // Whether every parcel of the instruction at `address` is mapped, short of the top of the address space
+    fn instruction_mapped(&self, address: Word) -> bool {
+        let bus = self.bus();
+        let parcel = |index: u32| {
+            let address = address.checked_add(2 * index)?;
+            bus.load(address, InstructionLength::HalfWord)
+        };
+        let Some(first) = parcel(0) else {
+            return false;
+        };
+
+        let parcels = encoded_length(first as HalfWord).min(ILEN as u32) / HalfWord::BITS;
+        (1..parcels).all(|index| parcel(index).is_some())
+    }
+
+    // The instruction at `address`, assembled from ILEN bits worth of little-endian 16-bit parcels,
+    // and its first parcel, which tells the length of the instruction
+    fn read_instruction(&self, address: Word) -> (HalfWord, Word) {
+        let bus = self.bus();
+        let parcels = ILEN as u32 / HalfWord::BITS;
+        // Parcels past the top of the address space read as 0, rather than wrapping around to address 0
+        let word = (0..parcels).rev().fold(0, |word: Word, parcel| {
+            let parcel = address.checked_add(2 * parcel).map_or(0, |address| bus.read_half_word(address));
+            word << HalfWord::BITS | parcel as Word
+        });
+
+        (word as HalfWord, word)
+    }
+
+    fn decode(&mut self, word: Word) -> Option<RV32Instruction> {
+        let Some(cache) = &mut self.decode_cache else {
+            return self.decode_uncached(word);
+        };
+
+        if let Some(instruction) = cache.get(&word) {
+            return Some(*instruction);
+        }
+
+        let instruction = self.decode_uncached(word)?;
+        if cache.len() >= DECODE_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(word, instruction);
+
+        Some(instruction)
+    }
+
+    // The instruction set decoding the words fetched depends on the XLEN
+    fn decode_uncached(&self, word: Word) -> Option<RV32Instruction> {
+        match self.xlen {
+            Xlen::X32 => RV32I.decode_opt(word),
+            Xlen::X64 => RV64I.decode_opt(word),
+        }
+    }
+
+    fn describe(&self, instruction: RV32Instruction) -> Option<&'static Descriptor> {
+        match self.xlen {
+            Xlen::X32 => RV32I.describe(instruction),
+            Xlen::X64 => RV64I.describe(instruction),
+        }
+    }
+
+    // The timer interrupt is pending while the CLINT says so; it is taken only when enabled
+    fn pending_interrupt(&mut self) -> Option<Interrupt> {
+        if self.bus().timer_interrupt(self.hart_id) {
+            self.csrs.mip |= MIP_MTIP;
+        } else {
+            self.csrs.mip &= !MIP_MTIP;
+        }
+
+        let enabled = self.csrs.mstatus & MSTATUS_MIE != 0;
+        (enabled && self.csrs.mie & self.csrs.mip & MIE_MTIE != 0).then_some(Interrupt::MachineTimer)
+    }
+
+    // Enters the trap handler at mtvec, in machine mode (the only one there is). mepc is the
+    // instruction that trapped, or the one an interrupt kept from being executed. In vectored mode,
+    // interrupts enter the handler at BASE + 4 * cause, while exceptions still enter it at BASE; the
+    // reserved modes are taken as direct.
+    fn take_trap(&mut self, mcause: RegisterValue64, mepc: RegisterValue64) {
+        let mpie = if self.csrs.mstatus & MSTATUS_MIE != 0 { MSTATUS_MPIE } else { 0 };
+        let base = self.csrs.mtvec & !MTVEC_MODE;
+        let handler = match (self.csrs.mtvec & MTVEC_MODE, decode_mcause(mcause)) {
+            (MTVEC_VECTORED, (true, code)) => base.wrapping_add(4 * code),
+            _ => base,
+        };
+
+        self.csrs.mepc = mepc;
+        self.csrs.mcause = mcause;
+        self.csrs.mtval = 0;
+        self.csrs.mstatus = self.csrs.mstatus & !(MSTATUS_MIE | MSTATUS_MPIE) | mpie | MSTATUS_MPP;
+        self.registers.pc = truncate_xlen(handler);
+    }
+
+    // MRET: returns from the trap handler to mepc, restoring the interrupt enable bit
+    fn trap_return(&mut self) {
+        let mie = if self.csrs.mstatus & MSTATUS_MPIE != 0 { MSTATUS_MIE } else { 0 };
+
+        self.csrs.mstatus = self.csrs.mstatus & !MSTATUS_MIE | mie | MSTATUS_MPIE;
+        self.registers.pc = self.csrs.mepc;
+    }
+
+    // Links rd to the next instruction, whatever its length was, then jumps. IALIGN is only relaxed to
+    // 16 bits by the C extension: without it, targets must be 4-byte aligned.
+    fn jump(&mut self, rd: usize, target: RegisterValue64) -> Result<(), TrapCause> {
+        let alignment = if self.extensions.contains(&Ext::C) { IALIGN } else { InstructionLength::Word };
+        if target % alignment.bytes() as RegisterValue64 != 0 {
+            return Err(TrapCause::InstructionAddressMisaligned);
+        }
+
+        self.registers.write(rd, self.registers.pc);
+        self.registers.pc = target;
+
+        Ok(())
     }
 }
 
 impl Hart<RV32I, RV32Instruction> for SimpleRV32IHart {
-    fn execute(&mut self, instruction: RV32Instruction) {
+    fn execute(&mut self, instruction: RV32Instruction) -> Result<(), TrapCause> {
+        // Fast path: nothing to do, the PC is already past it
+        if instruction.is_nop() {
+            return Ok(());
+        }
+
+        let extension = match instruction {
+            // Told by the decoded funct7, which doesn't depend on any of them having a descriptor
+            RV32Instruction::IntegerRegisterRegister(r_type) if is_mul_div(r_type) => Some(Ext::M),
+            _ => self.describe(instruction).and_then(Descriptor::extension),
+        };
+        if extension.is_some_and(|extension| !self.extensions.contains(&extension)) {
+            return Err(TrapCause::IllegalInstruction);
+        }
+
+        // Applies to the same instructions as `set_x0_watch`
+        if self.registers.x0_write_policy() == X0WritePolicy::Trap && discards_result(&instruction) {
+            self.registers.pc = self.instruction_pc;
+            self.x0_write_trapped = true;
+            return Err(TrapCause::IllegalInstruction);
+        }
+
+        if self.extensions.contains(&Ext::E) && uses_upper_registers(&instruction) {
+            return Err(TrapCause::IllegalInstruction);
+        }
+
         match instruction {
             RV32Instruction::IntegerRegisterImmediate(i_type) => {
-                let rd = i_type.rd();
-                let rs1 = i_type.rs1();
-                let imm = i_type.imm();
+                let rd = i_type.rd().value() as usize;
+                let rs1 = self.registers.read(i_type.rs1().value() as usize);
+                let imm = i_type.immediate() as RegisterValue64;
 
-                if RV32I.match_instruction(instruction, ADDI) {
-                    self.registers.array[rd] = self.registers.array[rs1].wrapping_add(imm);
+                if RV32I.match_instruction(instruction, &ADDI) {
+                    self.registers.write(rd, add_xlen(rs1, imm).0);
+                } else if RV32I.match_instruction(instruction, &SLTI) {
+                    self.registers.write(rd, ((rs1 as i64) < (imm as i64)) as RegisterValue64);
+                } else if RV32I.match_instruction(instruction, &SLTIU) {
+                    // The immediate is sign-extended first, then compared as unsigned
+                    self.registers.write(rd, (rs1 < imm) as RegisterValue64);
+                } else if RV32I.match_instruction(instruction, &ANDI) {
+                    self.registers.write(rd, rs1 & imm);
+                } else if RV32I.match_instruction(instruction, &ORI) {
+                    self.registers.write(rd, rs1 | imm);
+                } else if RV32I.match_instruction(instruction, &XORI) {
+                    self.registers.write(rd, rs1 ^ imm);
+                } else if RV32I.match_instruction(instruction, &SLLI) {
+                    // Only the shamt bits of the immediate count (see `alu::sll`)
+                    self.registers.write(rd, sll_xlen(rs1, imm));
+                } else if RV32I.match_instruction(instruction, &SRLI) {
+                    self.registers.write(rd, srl_xlen(rs1, imm));
+                } else if RV32I.match_instruction(instruction, &SRAI) {
+                    self.registers.write(rd, sra_xlen(rs1, imm));
+                } else {
+                    // A shift with an unknown funct7, e.g. with bit 25 (shamt[5], reserved on RV32) set
+                    return Err(TrapCause::IllegalInstruction);
                 }
             }
-            RV32Instruction::IntegerRegisterRegister(r_type) => {}
-            RV32Instruction::UnconditionalJump(j_type) => {}
-            RV32Instruction::ConditionBranch(b_type) => {}
-            RV32Instruction::Load(i_type) => {}
-            RV32Instruction::Store(s_type) => {}
-            RV32Instruction::Fence(if_type) => {}
-            RV32Instruction::ControlAndStatusRegister(i_type) => {}
+            RV32Instruction::IntegerRegisterRegister(r_type) => {
+                let rd = r_type.rd().value() as usize;
+                let rs1 = self.registers.read(r_type.rs1().value() as usize);
+                let rs2 = self.registers.read(r_type.rs2().value() as usize);
+
+                if RV32I.match_instruction(instruction, &ADD) {
+                    self.registers.write(rd, add_xlen(rs1, rs2).0);
+                } else if RV32I.match_instruction(instruction, &SUB) {
+                    self.registers.write(rd, truncate_xlen(rs1.wrapping_sub(rs2)));
+                } else if RV32I.match_instruction(instruction, &SLT) {
+                    self.registers.write(rd, ((rs1 as i64) < (rs2 as i64)) as RegisterValue64);
+                } else if RV32I.match_instruction(instruction, &SLTU) {
+                    // Also used as "snez rd, rs2" (rs1 = x0). Values are sign-extended, which keeps
+                    // their unsigned XLEN order
+                    self.registers.write(rd, (rs1 < rs2) as RegisterValue64);
+                } else if RV32I.match_instruction(instruction, &AND) {
+                    self.registers.write(rd, rs1 & rs2);
+                } else if RV32I.match_instruction(instruction, &OR) {
+                    self.registers.write(rd, rs1 | rs2);
+                } else if RV32I.match_instruction(instruction, &XOR) {
+                    self.registers.write(rd, rs1 ^ rs2);
+                } else if RV32I.match_instruction(instruction, &SLL) {
+                    self.registers.write(rd, sll_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &SRL) {
+                    self.registers.write(rd, srl_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &SRA) {
+                    self.registers.write(rd, sra_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &MUL) {
+                    // The lower XLEN bits of the product are the same, whether signed or unsigned
+                    self.registers.write(rd, truncate_xlen(rs1.wrapping_mul(rs2)));
+                } else if RV32I.match_instruction(instruction, &MULH) {
+                    self.registers.write(rd, mulh_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &MULHSU) {
+                    self.registers.write(rd, mulhsu_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &MULHU) {
+                    self.registers.write(rd, mulhu_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &DIV) {
+                    self.registers.write(rd, div_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &DIVU) {
+                    self.registers.write(rd, divu_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &REM) {
+                    self.registers.write(rd, rem_xlen(rs1, rs2));
+                } else if RV32I.match_instruction(instruction, &REMU) {
+                    self.registers.write(rd, remu_xlen(rs1, rs2));
+                } else {
+                    return Err(TrapCause::IllegalInstruction);
+                }
+            }
+            RV32Instruction::UnconditionalJump(j_type) => {
+                if RV32I.match_instruction(instruction, &JAL) {
+                    let target = self.address(self.instruction_pc, j_type.immediate() as RegisterValue64);
+                    self.jump(j_type.rd().value() as usize, target)?;
+                }
+            }
+            RV32Instruction::JumpAndLinkRegister(i_type) => {
+                if RV32I.match_instruction(instruction, &JALR) {
+                    let rs1 = self.registers.read(i_type.rs1().value() as usize);
+                    let target = self.address(rs1, i_type.immediate() as RegisterValue64) & !1;
+                    self.jump(i_type.rd().value() as usize, target)?;
+                }
+            }
+            RV32Instruction::ConditionBranch(b_type) => {
+                let rs1 = self.registers.read(b_type.rs1().value() as usize);
+                let rs2 = self.registers.read(b_type.rs2().value() as usize);
+
+                // Values are sign-extended, which keeps their unsigned XLEN order (see SLTU)
+                let taken = match Funct3BranchTable::try_from(b_type.funct3().value()) {
+                    Ok(Funct3BranchTable::BEQ) => rs1 == rs2,
+                    Ok(Funct3BranchTable::BNE) => rs1 != rs2,
+                    Ok(Funct3BranchTable::BLT) => (rs1 as i64) < (rs2 as i64),
+                    Ok(Funct3BranchTable::BGE) => (rs1 as i64) >= (rs2 as i64),
+                    Ok(Funct3BranchTable::BLTU) => rs1 < rs2,
+                    Ok(Funct3BranchTable::BGEU) => rs1 >= rs2,
+                    _ => return Err(TrapCause::IllegalInstruction),
+                };
+
+                // Only taken branches check the alignment of their target
+                if taken {
+                    let target = self.address(self.instruction_pc, b_type.immediate() as RegisterValue64);
+                    self.jump(0, target)?;
+                }
+
+                if let Some(profile) = &mut self.profile {
+                    profile.record_branch(self.instruction_pc, taken);
+                }
+            }
+            RV32Instruction::UpperImmediate(u_type) => {
+                // The immediate already holds the upper 20 bits, sign-extended
+                let rd = u_type.rd().value() as usize;
+                let imm = u_type.immediate() as RegisterValue64;
+
+                match classify_opcode(u_type.opcode().value()) {
+                    Some(Opcode7Table::LoadUpperImmediate) => self.registers.write(rd, imm),
+                    // Relative to the AUIPC itself, rather than to the next instruction
+                    Some(Opcode7Table::AddUpperImmediatePC) => {
+                        self.registers.write(rd, add_xlen(self.instruction_pc, imm).0)
+                    }
+                    _ => return Err(TrapCause::IllegalInstruction),
+                }
+            }
+            RV32Instruction::Load(i_type) => {
+                let funct3 = self.describe(instruction).and_then(|descr| descr.funct3.as_ref());
+
+                if let Some(Funct3Expr::Load(width)) = funct3 {
+                    let size = width.access_size().ok_or(TrapCause::IllegalInstruction)?;
+                    let rs1 = self.registers.read(i_type.rs1().value() as usize);
+                    let address = self.address(rs1, i_type.immediate() as RegisterValue64) as Word;
+                    self.check_alignment(address, size, TrapCause::LoadAddressMisaligned)?;
+
+                    let value = self.bus().load(address, size).ok_or(TrapCause::LoadAccessFault)?;
+                    let value = self.data_endianness.convert(value, size);
+                    let value = if width.is_unsigned() { value } else { sign_extend(value, size as u32) };
+                    self.registers.write(i_type.rd().value() as usize, value);
+                }
+            }
+            RV32Instruction::Store(s_type) => {
+                let funct3 = self.describe(instruction).and_then(|descr| descr.funct3.as_ref());
+
+                if let Some(Funct3Expr::Store(width)) = funct3 {
+                    let size = width.access_size().ok_or(TrapCause::IllegalInstruction)?;
+                    let rs1 = self.registers.read(s_type.rs1().value() as usize);
+                    let rs2 = self.registers.read(s_type.rs2().value() as usize);
+                    let address = self.address(rs1, s_type.immediate() as RegisterValue64) as Word;
+                    self.check_alignment(address, size, TrapCause::StoreAddressMisaligned)?;
+
+                    let value = self.data_endianness.convert(rs2, size);
+                    self.bus().store(address, size, value).ok_or(TrapCause::StoreAccessFault)?;
+                }
+            }
+            // A single hart, which sees its memory accesses in program order: there is nothing to wait for
+            RV32Instruction::Fence(_) => {}
+            RV32Instruction::ControlAndStatusRegister(i_type) => {
+                let funct3 = self.describe(instruction).and_then(|descr| descr.funct3.as_ref());
+                let Some(Funct3Expr::System(operation)) = funct3 else {
+                    return Err(TrapCause::IllegalInstruction);
+                };
+
+                let csr = i_type.imm().value() as CsrAddress;
+                let rs1 = i_type.rs1().value();
+
+                // The immediate forms take a zero-extended 5 bits value from the rs1 field
+                let source = match operation {
+                    Funct3SystemTable::CSRRWI | Funct3SystemTable::CSRRSI | Funct3SystemTable::CSRRCI => {
+                        rs1 as RegisterValue64
+                    }
+                    _ => self.registers.read(rs1 as usize),
+                };
+
+                // Setting or clearing no bits (rs1 = x0, or uimm = 0) does not write the CSR at all
+                let old = self.csrs.read(csr).ok_or(TrapCause::IllegalInstruction)?;
+                let new = match operation {
+                    Funct3SystemTable::CSRRW | Funct3SystemTable::CSRRWI => Some(source),
+                    Funct3SystemTable::CSRRS | Funct3SystemTable::CSRRSI if rs1 != 0 => Some(old | source),
+                    Funct3SystemTable::CSRRC | Funct3SystemTable::CSRRCI if rs1 != 0 => Some(old & !source),
+                    _ => None,
+                };
+
+                if let Some(new) = new {
+                    self.csrs.write(csr, new).ok_or(TrapCause::IllegalInstruction)?;
+                }
+                self.registers.write(i_type.rd().value() as usize, old);
+            }
             RV32Instruction::TimeAndCounter(i_type) => {}
-            RV32Instruction::EnvironmentCallAndBreakpoint(i_type) => {}
+            RV32Instruction::EnvironmentCallAndBreakpoint(r_type) => {
+                // Told apart by the immediate (funct12), which funct7 and rs2 are part of
+                match Immediate11Table::from((r_type.raw_value() >> 20) as u16) {
+                    Immediate11Table::ECALL => return Err(TrapCause::EnvironmentCallFromMMode),
+                    Immediate11Table::EBREAK => return Err(TrapCause::Breakpoint),
+                    Immediate11Table::MRET => self.trap_return(),
+                    // A hint: the hart goes on, but the machine may pause (see `waiting_for_interrupt`)
+                    Immediate11Table::WFI => self.waiting_for_interrupt = true,
+                    // Without a supervisor mode, SRET is as illegal as the unknown ones
+                    _ => return Err(TrapCause::IllegalInstruction),
+                }
+            }
+            RV32Instruction::AtomicMemoryOperation(a_type) => {
+                let rd = a_type.rd().value() as usize;
+                let address = self.registers.read(a_type.rs1().value() as usize) as Word;
+                let rs2 = self.registers.read(a_type.rs2().value() as usize);
+                let aligned = address % InstructionLength::Word.bytes() as Word == 0;
+
+                if RV32I.match_instruction(instruction, &LR_W) {
+                    if !aligned {
+                        return Err(TrapCause::LoadAddressMisaligned);
+                    }
+
+                    let value = self
+                        .bus()
+                        .load_reserved(self.hart_id, address)
+                        .ok_or(TrapCause::LoadAccessFault)?;
+                    let value = self.data_endianness.convert(value, InstructionLength::Word);
+                    self.registers.write(rd, sign_extend(value, 32));
+                } else if RV32I.match_instruction(instruction, &SC_W) {
+                    if !aligned {
+                        return Err(TrapCause::StoreAddressMisaligned);
+                    }
+
+                    let value = self.data_endianness.convert(rs2, InstructionLength::Word);
+                    let stored = self
+                        .bus()
+                        .store_conditional(self.hart_id, address, value)
+                        .ok_or(TrapCause::StoreAccessFault)?;
+                    self.registers.write(rd, !stored as RegisterValue64); // 0 on success
+                } else {
+                    let operation: fn(RegisterValue64, RegisterValue64) -> RegisterValue64 =
+                        if RV32I.match_instruction(instruction, &AMOSWAP_W) {
+                            |_, rs2| rs2
+                        } else if RV32I.match_instruction(instruction, &AMOADD_W) {
+                            |old, rs2| add_xlen(old, rs2).0
+                        } else if RV32I.match_instruction(instruction, &AMOXOR_W) {
+                            |old, rs2| old ^ rs2
+                        } else if RV32I.match_instruction(instruction, &AMOAND_W) {
+                            |old, rs2| old & rs2
+                        } else if RV32I.match_instruction(instruction, &AMOOR_W) {
+                            |old, rs2| old | rs2
+                        } else if RV32I.match_instruction(instruction, &AMOMIN_W) {
+                            |old, rs2| (old as i64).min(rs2 as i64) as RegisterValue64
+                        } else if RV32I.match_instruction(instruction, &AMOMAX_W) {
+                            |old, rs2| (old as i64).max(rs2 as i64) as RegisterValue64
+                        } else if RV32I.match_instruction(instruction, &AMOMINU_W) {
+                            |old, rs2| old.min(rs2) // sign extension keeps the unsigned XLEN order
+                        } else if RV32I.match_instruction(instruction, &AMOMAXU_W) {
+                            |old, rs2| old.max(rs2)
+                        } else {
+                            // A reserved funct5 (or a width other than .W)
+                            return Err(TrapCause::IllegalInstruction);
+                        };
+
+                    if !aligned {
+                        return Err(TrapCause::StoreAddressMisaligned);
+                    }
+
+                    let old = self.read_modify_write(address, |old| operation(old, rs2))?;
+                    self.registers.write(rd, old);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Traps are taken by the handler at mtvec, once one is installed; until then, they are left to the
+    // caller (e.g. `Machine::run` stops on them)
+    fn step(&mut self) -> Result<(), TrapCause> {
+        let pc = self.registers.pc;
+        self.waiting_for_interrupt = false;
+        self.x0_write_trapped = false;
+
+        if let Some(interrupt) = self.pending_interrupt() {
+            self.take_trap(interrupt.mcause(), pc);
+            return Ok(());
+        }
+
+        // Custom instructions are not RV32Instructions, and so are not traced
+        let executed = match self.custom_instruction() {
+            Some((handler, word)) => self.execute_custom(&handler, word).map(|()| None),
+            None => self.fetch_and_execute().map(Some),
+        };
+
+        match executed {
+            Ok(instruction) => {
+                self.icount += 1;
+
+                if let (Some(instruction), Some(tracer)) = (instruction, &mut self.tracer) {
+                    tracer.emit(&TraceEvent::Retired { pc, instruction });
+
+                    if self.x0_watch && discards_result(&instruction) {
+                        tracer.emit(&TraceEvent::DiscardedWrite { pc, instruction });
+                    }
+                }
+
+                Ok(())
+            }
+            Err(cause) if self.csrs.mtvec != 0 && !self.x0_write_trapped => {
+                self.take_trap(u8::from(cause) as RegisterValue64, self.instruction_pc);
+                Ok(())
+            }
+            Err(cause) => Err(cause),
+        }
+    }
+
+    // Instructions are read from memory in little-endian order, regardless of the data endianness:
+    // unlike loads, fetches never go through `Endianness::convert`
+    fn fetch(&mut self) -> Result<RV32Instruction, TrapCause> {
+        let (halfword, word) = self.read_instruction(self.registers.pc as Word);
+        self.instruction_pc = self.registers.pc;
+
+        // Encodings longer than ILEN are illegal here, rather than fetched in part: the PC is left on
+        // them, as a trap would
+        if encoded_length(halfword) > ILEN as u32 {
+            return Err(TrapCause::IllegalInstruction);
+        }
+
+        let length = instruction_length(halfword);
+        self.registers.pc = self.next_pc(length)?;
+
+        let instruction = match length {
+            InstructionLength::Word => self.decode(word),
+            _ if self.extensions.contains(&Ext::C) => decode_compressed(halfword),
+            _ => None,
+        };
+        instruction.ok_or(TrapCause::IllegalInstruction)
+    }
+}
+
+impl CustomHandler {
+    pub fn new(handler: impl FnMut(&mut HartState<'_>, Word) -> Result<(), TrapCause> + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(handler)))
+    }
+}
+
+impl Debug for CustomHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("CustomHandler") }
+}
+
+// Whether an R-type is one of the M extension's, MUL to REMU
+fn is_mul_div(r_type: RType32Bitfield) -> bool {
+    matches!(
+        Funct3Expr::try_from(r_type.opcode(), r_type.funct3(), Some(r_type.funct7())),
+        Ok(Funct3Expr::MulDiv(_))
+    )
+}
+
+// Whether an ALU instruction, other than the canonical NOP, writes its result to x0
+fn discards_result(instruction: &RV32Instruction) -> bool {
+    let rd = match instruction {
+        RV32Instruction::IntegerRegisterImmediate(i_type) => i_type.rd(),
+        RV32Instruction::IntegerRegisterRegister(r_type) => r_type.rd(),
+        _ => return false,
+    };
+
+    rd.value() == 0 && !instruction.is_nop()
+}
+
+// Whether an instruction names any of x16 to x31, which RV32E lacks. The CSR instructions with
+// funct3 1xx (CSRRWI and co.) hold an immediate where rs1 would be.
+fn uses_upper_registers(instruction: &RV32Instruction) -> bool {
+    let csr_immediate = matches!(
+        instruction,
+        RV32Instruction::ControlAndStatusRegister(i_type) if i_type.funct3().value() & 0b100 != 0
+    );
+
+    instruction.fields().into_iter().any(|(field, value)| match field {
+        FieldName::Rd | FieldName::Rs2 => value >= RV32E_REGISTERS_COUNT as u64,
+        FieldName::Rs1 => !csr_immediate && value >= RV32E_REGISTERS_COUNT as u64,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::bus::Region;
+    use crate::encodings::{auipc, beq, bge, bgeu, blt, bltu, bne, fence, lui};
+    use crate::instruction::NOP;
+    use crate::memory::VecMemory;
+
+    const OP: Word = 0b0110011;
+    const OP_IMM: Word = 0b0010011;
+
+    fn r_type(funct7: Word, funct3: Word, rd: Word, rs1: Word, rs2: Word) -> Word {
+        funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | OP
+    }
+
+    fn i_type(imm: i32, funct3: Word, rd: Word, rs1: Word) -> Word {
+        (imm as Word) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | OP_IMM
+    }
+
+    fn execute(hart: &mut SimpleRV32IHart, chomp: Word) {
+        let instruction = RV32I.decode(chomp).unwrap();
+        hart.execute(instruction).unwrap();
+    }
+
+    #[test]
+    fn should_compare_signed_and_unsigned_values_differently() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, -1i64 as RegisterValue64);
+        hart.registers_mut().write(2, 1);
+
+        execute(&mut hart, r_type(0, 0b010, 3, 1, 2)); // slt x3, x1, x2
+        execute(&mut hart, r_type(0, 0b011, 4, 1, 2)); // sltu x4, x1, x2
+
+        assert_eq!(hart.registers().read(3), 1);
+        assert_eq!(hart.registers().read(4), 0);
+    }
+
+    #[test]
+    fn should_set_if_not_zero_with_sltu_from_x0() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(2, 7);
+
+        execute(&mut hart, r_type(0, 0b011, 3, 0, 2)); // snez x3, x2
+        execute(&mut hart, r_type(0, 0b011, 4, 0, 0)); // snez x4, x0
+
+        assert_eq!(hart.registers().read(3), 1);
+        assert_eq!(hart.registers().read(4), 0);
+    }
+
+    #[test]
+    fn should_advance_pc_by_the_fetched_instruction_length() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.bus().write_word(0, &i_type(5, 0b000, 1, 0)); // addi x1, x0, 5
+        hart.bus().write_half_word(4, &0x0505); // c.addi x10, 1
+
+        assert!(hart.fetch().is_ok());
+        assert_eq!(hart.registers().pc, 4);
+
+        assert!(hart.fetch().is_ok());
+        assert_eq!(hart.registers().pc, 6);
+    }
+
+    #[test]
+    fn should_execute_c_addi_as_addi() {
+        let mut compressed = SimpleRV32IHart::new(1024);
+        compressed.bus().write_half_word(0, &0x0505); // c.addi x10, 1
+        let mut expanded = SimpleRV32IHart::new(1024);
+        expanded.bus().write_word(0, &i_type(1, 0b000, 10, 10)); // addi x10, x10, 1
+
+        compressed.step().unwrap();
+        expanded.step().unwrap();
+
+        assert_eq!(compressed.registers().read(10), 1);
+        assert_eq!(compressed.registers().read(10), expanded.registers().read(10));
+    }
+
+    #[test]
+    fn should_only_run_the_enabled_extensions() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_extensions(HashSet::from([Ext::M]));
+        hart.bus().write_half_word(0, &0x0505); // c.addi x10, 1
+        hart.bus().write_word(2, &0x1005_22AF); // lr.w x5, (x10)
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().read(10), 0);
+    }
+
+    #[test]
+    fn should_multiply_a_negative_rs1_by_a_large_unsigned_rs2_with_mulhsu() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, -2i64 as RegisterValue64);
+        hart.registers_mut().write(2, 0xFFFF_FFFF_FFFF_FFFF); // 0xFFFF_FFFF, unsigned
+
+        execute(&mut hart, r_type(0b0000001, 0b010, 3, 1, 2)); // mulhsu x3, x1, x2
+        execute(&mut hart, r_type(0b0000001, 0b001, 4, 1, 2)); // mulh x4, x1, x2
+        execute(&mut hart, r_type(0b0000001, 0b011, 5, 1, 2)); // mulhu x5, x1, x2
+
+        // -2 × (2^32 - 1) = -2^33 + 2, whose high word is -2; mulh sees -2 × -1 = 2
+        assert_eq!(hart.registers().read(3), -2i64 as RegisterValue64);
+        assert_eq!(hart.registers().read(4), 0);
+        assert_eq!(hart.registers().read(5), 0xFFFF_FFFF_FFFF_FFFD);
+    }
+
+    #[test]
+    fn should_step_over_a_fence() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.bus().write_word(0, &fence(0b1111, 0b1111)); // fence iorw, iorw
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().pc, 4);
+    }
+
+    #[test]
+    fn should_reject_instructions_longer_than_ilen() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_extensions(HashSet::from([Ext::C]));
+        hart.bus().write_half_word(0, &0b001_1111); // low parcel of a 48 bits instruction
+        hart.bus().write_word(2, &0x0050_0093); // addi x1, x0, 5
+
+        assert_eq!(hart.peek_instruction(0), None);
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().pc, 0);
+        assert_eq!(hart.registers().read(1), 0);
+    }
+
+    #[test]
+    fn should_only_reach_x0_to_x15_under_rv32e() {
+        let mut rv32e = SimpleRV32IHart::new(1024);
+        rv32e.set_extensions(HashSet::from([Ext::E]));
+        rv32e.bus().write_word(0, &0x0010_0813); // addi x16, x0, 1
+        let mut rv32i = SimpleRV32IHart::new(1024);
+        rv32i.bus().write_word(0, &0x0010_0813);
+
+        assert_eq!(rv32e.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(rv32e.registers().read(16), 0);
+        assert_eq!(rv32i.step(), Ok(()));
+        assert_eq!(rv32i.registers().read(16), 1);
+    }
+
+    #[test]
+    fn should_reject_shifts_by_an_immediate_with_bit_25_set() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 1);
+        hart.bus().write_word(0, &0x0230_9113); // slli x2, x1, 35
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().read(2), 0);
+    }
+
+    #[test]
+    fn should_discard_writes_to_x0_by_default() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 1);
+        hart.bus().write_word(0, &0x0020_8033); // add x0, x1, x2
+
+        assert_eq!(hart.registers().x0_write_policy(), X0WritePolicy::Ignore);
+        assert_eq!(hart.step(), Ok(()));
+        assert_eq!(hart.registers().read(0), 0);
+    }
+
+    #[test]
+    fn should_refuse_writes_to_x0_under_the_trap_policy_without_taking_the_trap() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().set_x0_write_policy(X0WritePolicy::Trap);
+        hart.reset();
+        hart.csrs_mut().mtvec = 0x100;
+        hart.bus().write_word(0, &0x0020_8033); // add x0, x1, x2
+        hart.bus().write_word(4, &NOP);
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert!(hart.x0_write_trapped());
+        assert_eq!((hart.registers().pc, hart.icount(), hart.csrs().mcause), (0, 0, 0));
+
+        hart.registers_mut().set_x0_write_policy(X0WritePolicy::Ignore);
+        assert_eq!(hart.step(), Ok(()));
+        assert!(!hart.x0_write_trapped());
+        assert_eq!(hart.registers().pc, 4);
+    }
+
+    #[test]
+    fn should_fetch_instructions_straddling_two_regions() {
+        let mut bus = Bus::with_ram(0x100);
+        bus.map(0x100..0x200, Region::Ram(VecMemory::new(0x100)));
+        let mut hart = SimpleRV32IHart::with_shared_bus(0, Arc::new(Mutex::new(bus)), 0x200);
+        hart.bus().write_half_word(0xFE, &0x0093); // addi x1, x0, 5, low half
+        hart.bus().write_half_word(0x100, &0x0050); // high half
+        hart.bus().write_half_word(0x1FE, &0x0093); // the high half would be unmapped
+
+        hart.registers_mut().pc = 0xFE;
+        assert_eq!(hart.step(), Ok(()));
+        assert_eq!(hart.registers().read(1), 5);
+
+        hart.registers_mut().pc = 0x1FE;
+        assert_eq!(hart.step(), Err(TrapCause::InstructionAccessFault));
+        assert_eq!(hart.registers().pc, 0x1FE);
+    }
+
+    #[test]
+    fn should_fault_rather_than_fetch_past_the_top_of_the_address_space() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.bus().write_word(0, &0x0050_0093); // addi x1, x0, 5, where the PC would wrap to
+        hart.registers_mut().pc = u64::MAX - 1; // 0xFFFF_FFFE, sign-extended
+
+        assert_eq!(hart.step(), Err(TrapCause::InstructionAccessFault));
+        assert_eq!(hart.registers().pc, u64::MAX - 1);
+        assert_eq!(hart.registers().read(1), 0);
+    }
+
+    #[test]
+    fn should_fault_rather_than_advance_the_pc_past_the_top_of_the_address_space() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().pc = 0xFFFF_FFFE; // unmapped, it reads as a 16-bit parcel ending at 2^32
+
+        assert_eq!(hart.fetch(), Err(TrapCause::InstructionAccessFault));
+        assert_eq!(hart.registers().pc, 0xFFFF_FFFE);
+    }
+
+    fn load_program(hart: &SimpleRV32IHart, address: Word, program: &[Word]) {
+        for (index, word) in program.iter().enumerate() {
+            hart.bus().write_word(address + 4 * index as Word, word);
+        }
+    }
+
+    #[test]
+    fn should_fail_sc_after_a_store_of_its_own_to_the_reserved_word() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0x100);
+        hart.registers_mut().write(7, 42);
+        load_program(&hart, 0, &crate::rv32asm! {
+            lr.w x5, (x10);
+            sw x0, 0(x10);
+            sc.w x6, x7, (x10);
+            lr.w x5, (x10);
+            sc.w x8, x7, (x10)
+        });
+
+        for _ in 0..5 {
+            hart.step().unwrap();
+        }
+
+        assert_eq!(hart.registers().read(6), 1); // failed, the store broke the reservation
+        assert_eq!(hart.registers().read(8), 0); // succeeded
+        assert_eq!(hart.bus().read_word(0x100), 42);
+    }
+
+    #[test]
+    fn should_not_run_stale_instructions_out_of_the_decode_cache() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_decode_cache(true);
+        load_program(&hart, 0, &crate::rv32asm! { addi x1, x0, 1 });
+        hart.step().unwrap();
+
+        load_program(&hart, 0, &crate::rv32asm! { addi x1, x0, 2 });
+        hart.registers_mut().pc = 0;
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(1), 2);
+        assert_eq!(hart.decode_cache.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn should_peek_at_instructions_without_fetching_them() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        load_program(&hart, 0, &crate::rv32asm! { addi x1, x0, 1; addi x2, x0, 2 });
+
+        let peeked = hart.peek_instruction(4);
+        assert_eq!(hart.registers().pc, 0);
+        assert_eq!(peeked, RV32I.decode_opt(0x0020_0113));
+
+        assert_eq!(hart.fetch().ok(), hart.peek_instruction(0));
+        assert_eq!(hart.registers().pc, 4);
+        assert_eq!(hart.icount(), 0);
+    }
+
+    #[test]
+    fn should_run_code_the_program_overwrote_itself() {
+        for cached in [false, true] {
+            let mut hart = SimpleRV32IHart::new(1024);
+            hart.set_decode_cache(cached);
+            load_program(&hart, 0, &crate::rv32asm! { addi x1, x1, 1; sw x5, 0(x0); jal x0, -8 });
+            hart.registers_mut().write(5, 0x0100_8093); // addi x1, x1, 16
+
+            for _ in 0..4 {
+                hart.step().unwrap();
+            }
+
+            assert_eq!(hart.registers().read(1), 17, "decode cache enabled: {cached}");
         }
     }
 
-    // This routine only works for 32 bits instructions
-    fn fetch(&mut self) -> Option<RV32Instruction> {
-        let index = self.registers.pc as Word;
-        let data = self.ram.read_word(index);
+    #[test]
+    fn should_fetch_little_endian_instructions_with_big_endian_data() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_data_endianness(Endianness::Big);
+        hart.registers_mut().write(1, 0x1122_3344);
+        load_program(&hart, 0, &crate::rv32asm! { sw x1, 0x100(x0); lw x2, 0x100(x0); lb x3, 0x100(x0) });
+
+        for _ in 0..3 {
+            hart.step().unwrap();
+        }
+
+        assert_eq!(hart.bus().region_slice(0x100..0x104).unwrap(), [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(hart.registers().read(2), 0x1122_3344);
+        assert_eq!(hart.registers().read(3), 0x11);
+    }
+
+    #[test]
+    fn should_report_traps_until_a_handler_is_installed() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        load_program(&hart, 0, &crate::rv32asm! { ecall });
+
+        assert_eq!(hart.step(), Err(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(hart.csrs().mepc, 0);
+    }
+
+    #[test]
+    fn should_take_traps_at_mtvec_and_return_with_mret() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.csrs_mut().mstatus |= MSTATUS_MIE;
+        load_program(&hart, 0x000, &crate::rv32asm! {
+            addi x1, x0, 0x100;
+            csrrw x0, 0x305, x1;
+            addi x0, x0, 0
+        });
+        hart.bus().write_word(0x00C, &0); // illegal instruction
+        load_program(&hart, 0x100, &crate::rv32asm! {
+            csrrs x2, 0x341, x0;
+            addi x2, x2, 4;
+            csrrw x0, 0x341, x2;
+            mret
+        });
+
+        for _ in 0..4 {
+            hart.step().unwrap();
+        }
+
+        assert_eq!(hart.registers().pc, 0x100);
+        assert_eq!(hart.csrs().mcause, u8::from(TrapCause::IllegalInstruction) as RegisterValue64);
+        assert_eq!(hart.csrs().mepc, 0x00C);
+        assert_eq!(hart.csrs().mstatus & (MSTATUS_MIE | MSTATUS_MPIE), MSTATUS_MPIE);
+
+        for _ in 0..4 {
+            hart.step().unwrap();
+        }
+
+        assert_eq!(hart.registers().pc, 0x010);
+        assert_eq!(hart.csrs().mstatus & MSTATUS_MIE, MSTATUS_MIE);
+    }
+
+    #[test]
+    fn should_treat_unknown_csrs_as_illegal() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        load_program(&hart, 0, &crate::rv32asm! { csrrs x1, 0x7C0, x0 });
 
-        self.registers.pc += InstructionLength::Word as RegisterValue64;
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+    }
+
+    #[test]
+    fn should_not_divide_without_the_m_extension() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_extensions(HashSet::new());
+        hart.bus().write_word(0, &0x0220_C1B3); // div x3, x1, x2
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().read(3), 0);
+    }
+
+    #[test]
+    fn should_store_and_load_words() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0xFFFF_FFFF_8000_0001);
+        hart.registers_mut().write(11, 0x100);
+        hart.bus().write_half_word(0, &0xC1C8); // c.sw x10, 4(x11)
+        hart.bus().write_half_word(2, &0x41D0); // c.lw x12, 4(x11)
+
+        hart.step().unwrap();
+        hart.step().unwrap();
+
+        assert_eq!(hart.bus().read_word(0x104), 0x8000_0001);
+        assert_eq!(hart.registers().read(12), 0xFFFF_FFFF_8000_0001);
+    }
+
+    #[test]
+    fn should_jump_and_link_past_the_jump() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.bus().write_word(0, &0x0080_00EF); // jal x1, 8
+        hart.bus().write_half_word(8, &0x8082); // c.jr x1
+
+        hart.step().unwrap();
+        assert_eq!(hart.registers().pc, 8);
+        assert_eq!(hart.registers().read(1), 4);
+
+        hart.step().unwrap();
+        assert_eq!(hart.registers().pc, 4);
+    }
+
+    #[test]
+    fn should_require_4_byte_aligned_jump_targets_without_the_c_extension() {
+        let mut rv32i = SimpleRV32IHart::new(1024);
+        rv32i.set_extensions(HashSet::new());
+        rv32i.bus().write_word(0, &0x0060_006F); // jal x0, 6
+        let mut rv32ic = SimpleRV32IHart::new(1024);
+        rv32ic.bus().write_word(0, &0x0060_006F);
+
+        assert_eq!(rv32i.step(), Err(TrapCause::InstructionAddressMisaligned));
+        assert_eq!(rv32ic.step(), Ok(()));
+        assert_eq!(rv32ic.registers().pc, 6);
+    }
+
+    #[test]
+    fn should_compare_branch_operands_as_signed_or_unsigned() {
+        let branches = [
+            (beq(1, 2, 8), false),
+            (bne(1, 2, 8), true),
+            (blt(1, 2, 8), true),
+            (bge(1, 2, 8), false),
+            (bltu(1, 2, 8), false),
+            (bgeu(1, 2, 8), true),
+        ];
+
+        for (branch, taken) in branches {
+            let mut hart = SimpleRV32IHart::new(1024);
+            hart.registers_mut().write(1, -1_i64 as RegisterValue64);
+            hart.registers_mut().write(2, 1);
+            hart.bus().write_word(0, &branch);
+
+            assert_eq!(hart.step(), Ok(()));
+            assert_eq!(hart.registers().pc, if taken { 8 } else { 4 }, "{:#010x}", branch);
+        }
+    }
+
+    #[test]
+    fn should_trap_only_taken_branches_to_misaligned_targets() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_extensions(HashSet::new());
+        load_program(&hart, 0, &[beq(0, 1, 6), beq(0, 0, 6)]);
+        hart.registers_mut().write(1, 1);
+
+        assert_eq!(hart.step(), Ok(()));
+        assert_eq!(hart.step(), Err(TrapCause::InstructionAddressMisaligned));
+    }
+
+    #[test]
+    fn should_load_upper_immediates_absolute_and_pc_relative() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        let program = [lui(1, 0x1234_5000), lui(2, 0x8000_0000_u32 as i32), auipc(3, 0x1000), auipc(4, -0x1000)];
+        load_program(&hart, 0, &program);
+
+        for _ in 0..4 {
+            hart.step().unwrap();
+        }
 
-        RV32I.decode(data)
+        assert_eq!(hart.registers().read(1), 0x1234_5000);
+        assert_eq!(hart.registers().read(2), 0xFFFF_FFFF_8000_0000);
+        assert_eq!(hart.registers().read(3), 0x1008);
+        assert_eq!(hart.registers().read(4), 0xFFFF_FFFF_FFFF_F00C);
+    }
+
+    #[test]
+    fn should_trace_address_overflows() {
+        let overflows = Arc::new(Mutex::new(Vec::new()));
+        let sink = overflows.clone();
+
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_tracer(Some(Tracer::new(move |event| {
+            if let TraceEvent::AddressOverflow { address, .. } = event {
+                sink.lock().unwrap().push(*address)
+            }
+        })));
+        hart.registers_mut().write(1, 0x7FFF_FFFF);
+        hart.bus().write_word(0, &0x0010_8067); // jalr x0, 1(x1)
+
+        hart.step().unwrap();
+        assert_eq!(*overflows.lock().unwrap(), vec![0xFFFF_FFFF_8000_0000]);
+    }
+
+    #[test]
+    fn should_trace_results_discarded_into_x0_when_watching() {
+        let discarded = Arc::new(Mutex::new(Vec::new()));
+        let sink = discarded.clone();
+
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_x0_watch(true);
+        hart.set_tracer(Some(Tracer::new(move |event| {
+            if let TraceEvent::DiscardedWrite { pc, .. } = event {
+                sink.lock().unwrap().push(*pc)
+            }
+        })));
+        load_program(&hart, 0, &crate::rv32asm! { addi x0, x0, 0; add x0, x1, x2; jal x0, 4 });
+
+        for _ in 0..3 {
+            hart.step().unwrap();
+        }
+
+        assert_eq!(*discarded.lock().unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn should_sign_or_zero_extend_loaded_bytes() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0x0000_8000);
+        hart.bus().write_word(0, &0x0015_0283); // lb x5, 1(x10)
+        hart.bus().write_word(4, &0x0015_4303); // lbu x6, 1(x10)
+
+        hart.step().unwrap();
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), 0xFFFF_FFFF_FFFF_FF80);
+        assert_eq!(hart.registers().read(6), 0x80);
+    }
+
+    #[test]
+    fn should_sign_extend_loaded_halfwords_and_words_to_bit_63() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0x0000_8000);
+        hart.bus().write_word(0x104, &0x8000_0000);
+        hart.bus().write_word(0, &0x0005_1283); // lh x5, 0(x10)
+        hart.bus().write_word(4, &0x0045_2303); // lw x6, 4(x10)
+
+        hart.step().unwrap();
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), 0xFFFF_FFFF_FFFF_8000);
+        assert_eq!(hart.registers().read(6), 0xFFFF_FFFF_8000_0000);
+    }
+
+    #[test]
+    fn should_store_and_load_doublewords_under_an_xlen_of_64() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_xlen(Xlen::X64);
+        hart.registers_mut().write(10, 0x100);
+        hart.registers_mut().write(6, 0x8877_6655_4433_2211);
+        load_program(&hart, 0, &crate::rv32asm! { sd x6, 8(x10); ld x7, 8(x10) });
+
+        hart.step().unwrap();
+        hart.step().unwrap();
+
+        assert_eq!(hart.bus().read_double_word(0x108), 0x8877_6655_4433_2211);
+        assert_eq!(hart.registers().read(7), 0x8877_6655_4433_2211);
+    }
+
+    #[test]
+    fn should_reject_doubleword_loads_and_stores_on_rv32() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_double_word(0x108, &0x8877_6655_4433_2211);
+        load_program(&hart, 0, &crate::rv32asm! { ld x7, 8(x10); sd x7, 16(x10) });
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().read(7), 0);
+
+        hart.registers_mut().pc = 4;
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.bus().read_double_word(0x110), 0);
+    }
+
+    #[test]
+    fn should_carry_out_misaligned_accesses_by_default() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0x4433_2211);
+        hart.bus().write_word(0x104, &0x0000_0055);
+        hart.bus().write_word(0, &0x0015_2283); // lw x5, 1(x10)
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), 0x5544_3322);
+    }
+
+    #[test]
+    fn should_trap_on_misaligned_accesses_when_alignment_is_required() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_aligned_access_required(true);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0, &0x0015_2283); // lw x5, 1(x10)
+        hart.bus().write_word(4, &0x0065_10A3); // sh x6, 1(x10)
+
+        assert_eq!(hart.step(), Err(TrapCause::LoadAddressMisaligned));
+        hart.registers_mut().pc = 4;
+        assert_eq!(hart.step(), Err(TrapCause::StoreAddressMisaligned));
+    }
+
+    #[test]
+    fn should_always_allow_byte_accesses() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.set_aligned_access_required(true);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0x0000_2A00);
+        hart.bus().write_word(0, &0x0015_0283); // lb x5, 1(x10)
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), 42);
+    }
+
+    #[test]
+    fn should_add_atomically() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(6, 3);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0xFFFF_FFFF);
+        hart.bus().write_word(0, &0x0065_22AF); // amoadd.w x5, x6, (x10)
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), -1i64 as RegisterValue64);
+        assert_eq!(hart.bus().read_word(0x100), 2);
+    }
+
+    #[test]
+    fn should_swap_atomically() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(6, 42);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &7);
+        hart.bus().write_word(0, &0x0865_22AF); // amoswap.w x5, x6, (x10)
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().read(5), 7);
+        assert_eq!(hart.bus().read_word(0x100), 42);
+    }
+
+    #[test]
+    fn should_reject_atomics_with_a_reserved_funct5() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(6, 42);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &7);
+        hart.bus().write_word(0, &0x2865_22AF); // funct5 = 0b00101
+
+        assert_eq!(hart.step(), Err(TrapCause::IllegalInstruction));
+        assert_eq!(hart.registers().read(5), 0);
+        assert_eq!(hart.bus().read_word(0x100), 7);
+    }
+
+    #[test]
+    fn should_tell_signed_and_unsigned_atomic_minimums_apart() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(6, 1);
+        hart.registers_mut().write(10, 0x100);
+        hart.bus().write_word(0x100, &0xFFFF_FFFF);
+        hart.bus().write_word(0, &0x8065_22AF); // amomin.w x5, x6, (x10)
+        hart.bus().write_word(4, &0xC065_22AF); // amominu.w x5, x6, (x10)
+
+        hart.step().unwrap();
+        assert_eq!(hart.bus().read_word(0x100), 0xFFFF_FFFF);
+
+        hart.step().unwrap();
+        assert_eq!(hart.bus().read_word(0x100), 1);
+    }
+
+    #[test]
+    fn should_fault_on_unmapped_loads() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(11, 0x1000);
+        hart.bus().write_half_word(0, &0x41D0); // c.lw x12, 4(x11)
+
+        assert_eq!(hart.step(), Err(TrapCause::LoadAccessFault));
+    }
+
+    #[test]
+    fn should_add_and_subtract_with_wrap_around() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0x7FFF_FFFF);
+        hart.registers_mut().write(2, 1);
+
+        execute(&mut hart, r_type(0b0000000, 0b000, 3, 1, 2)); // add x3, x1, x2
+        execute(&mut hart, r_type(0b0100000, 0b000, 4, 0, 2)); // sub x4, x0, x2
+
+        assert_eq!(hart.registers().read(3), 0xFFFF_FFFF_8000_0000);
+        assert_eq!(hart.registers().read(4), -1i64 as RegisterValue64);
+    }
+
+    #[test]
+    fn should_apply_bitwise_operations() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0b1100);
+        hart.registers_mut().write(2, 0b1010);
+
+        execute(&mut hart, r_type(0, 0b111, 3, 1, 2)); // and x3, x1, x2
+        execute(&mut hart, r_type(0, 0b110, 4, 1, 2)); // or x4, x1, x2
+        execute(&mut hart, r_type(0, 0b100, 5, 1, 2)); // xor x5, x1, x2
+
+        assert_eq!(hart.registers().read(3), 0b1000);
+        assert_eq!(hart.registers().read(4), 0b1110);
+        assert_eq!(hart.registers().read(5), 0b0110);
+    }
+
+    #[test]
+    fn should_shift_left_by_the_low_5_bits_of_rs2() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0x8000_0001);
+        hart.registers_mut().write(2, 33); // masked to 1
+
+        execute(&mut hart, r_type(0, 0b001, 3, 1, 2)); // sll x3, x1, x2
+
+        assert_eq!(hart.registers().read(3), 2);
+    }
+
+    #[test]
+    fn should_propagate_the_sign_only_on_arithmetic_shifts() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, -16i64 as RegisterValue64);
+        hart.registers_mut().write(2, 2);
+
+        execute(&mut hart, r_type(0b0000000, 0b101, 3, 1, 2)); // srl x3, x1, x2
+        execute(&mut hart, r_type(0b0100000, 0b101, 4, 1, 2)); // sra x4, x1, x2
+
+        assert_eq!(hart.registers().read(3), 0x3FFF_FFFC);
+        assert_eq!(hart.registers().read(4), -4i64 as RegisterValue64);
+    }
+
+    #[test]
+    fn should_propagate_the_sign_bit_of_0x8000_0000_on_sra_and_srai() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0x8000_0000);
+        hart.registers_mut().write(2, 1);
+        hart.registers_mut().write(3, 31);
+
+        execute(&mut hart, r_type(0b0100000, 0b101, 4, 1, 2)); // sra x4, x1, x2
+        execute(&mut hart, r_type(0b0100000, 0b101, 5, 1, 3)); // sra x5, x1, x3
+        execute(&mut hart, i_type(0x400 | 1, 0b101, 6, 1)); // srai x6, x1, 1
+        execute(&mut hart, i_type(0x400 | 31, 0b101, 7, 1)); // srai x7, x1, 31
+        execute(&mut hart, i_type(1, 0b101, 8, 1)); // srli x8, x1, 1
+
+        assert_eq!(hart.registers().read(4), 0xFFFF_FFFF_C000_0000);
+        assert_eq!(hart.registers().read(5), u64::MAX);
+        assert_eq!(hart.registers().read(6), 0xFFFF_FFFF_C000_0000);
+        assert_eq!(hart.registers().read(7), u64::MAX);
+        assert_eq!(hart.registers().read(8), 0x4000_0000);
+    }
+
+    #[test]
+    fn should_only_advance_the_pc_on_nop() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        load_program(&hart, 0, &[NOP]);
+        let registers = hart.registers().array;
+
+        hart.step().unwrap();
+
+        assert_eq!(hart.registers().pc, 4);
+        assert_eq!(hart.registers().array, registers);
+    }
+
+    #[test]
+    fn should_invert_bits_with_xori_minus_one() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0x0F0F_0F0F);
+
+        execute(&mut hart, i_type(-1, 0b100, 2, 1)); // not x2, x1
+
+        assert_eq!(hart.registers().read(2), 0xFFFF_FFFF_F0F0_F0F0);
+        assert_eq!(hart.registers().read(2) as Word, !0x0F0F_0F0F);
+    }
+
+    #[test]
+    fn should_apply_logical_immediates() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, 0b1100);
+
+        execute(&mut hart, i_type(0b1010, 0b111, 2, 1)); // andi x2, x1, 10
+        execute(&mut hart, i_type(0b1010, 0b110, 3, 1)); // ori x3, x1, 10
+        execute(&mut hart, i_type(-8, 0b111, 4, 1)); // andi x4, x1, -8
+
+        assert_eq!(hart.registers().read(2), 0b1000);
+        assert_eq!(hart.registers().read(3), 0b1110);
+        assert_eq!(hart.registers().read(4), 0b1000);
+    }
+
+    #[test]
+    fn should_compare_against_immediates() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, -5i64 as RegisterValue64);
+
+        execute(&mut hart, i_type(3, 0b010, 2, 1)); // slti x2, x1, 3
+        execute(&mut hart, i_type(3, 0b011, 3, 1)); // sltiu x3, x1, 3
+        execute(&mut hart, i_type(1, 0b011, 4, 0)); // seqz x4, x0
+
+        assert_eq!(hart.registers().read(2), 1);
+        assert_eq!(hart.registers().read(3), 0);
+        assert_eq!(hart.registers().read(4), 1);
+    }
+
+    #[test]
+    fn should_divide_towards_zero_and_by_zero_without_trapping() {
+        let mut hart = SimpleRV32IHart::new(1024);
+        hart.registers_mut().write(1, -7i64 as RegisterValue64);
+        hart.registers_mut().write(2, 2);
+
+        execute(&mut hart, 0x0220_C1B3); // div x3, x1, x2
+        execute(&mut hart, 0x0220_E233); // rem x4, x1, x2
+        execute(&mut hart, 0x0200_D2B3); // divu x5, x1, x0
+        execute(&mut hart, 0x0200_F333); // remu x6, x1, x0
+
+        assert_eq!(hart.registers().read(3), -3i64 as RegisterValue64);
+        assert_eq!(hart.registers().read(4), -1i64 as RegisterValue64);
+        assert_eq!(hart.registers().read(5), RegisterValue64::MAX);
+        assert_eq!(hart.registers().read(6), -7i64 as RegisterValue64);
+    }
+
+    proptest! {
+        // Untrusted programs may contain anything: decode and execute must trap, never panic
+        #[test]
+        fn should_never_panic_on_arbitrary_words(chomp in any::<Word>(), registers in any::<[RegisterValue64; 32]>()) {
+            let mut hart = SimpleRV32IHart::new(1024);
+            for (index, value) in registers.into_iter().enumerate() {
+                hart.registers_mut().write(index, value);
+            }
+            hart.bus().write_word(0, &chomp);
+
+            let _ = hart.step();
+            let _ = hart.step();
+        }
+
+        #[test]
+        fn should_never_panic_from_an_arbitrary_pc(pc in any::<RegisterValue64>()) {
+            let mut hart = SimpleRV32IHart::new(1024);
+            hart.registers_mut().pc = pc;
+
+            prop_assert!(hart.step().is_err());
+        }
     }
 }
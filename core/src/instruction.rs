@@ -14,19 +14,32 @@
 // limitations under the Licence.
 //
 
-use crate::architecture::{InstructionKind, RV32I};
+use anyhow::{ensure, Context, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::architecture::{
+    Architecture, Ext, FieldName, InstructionKind, RV32Instruction, Zicsr, RV32A, RV32I, RV32M, RV64I,
+};
 use crate::bitfield::{
-    BType32Bitfield, Funct3Expr, Funct3OpRegisterTable, Funct7Table, IFenceType32Bitfield, IType32Bitfield,
-    Immediate11Table, JType32Bitfield, Opcode7Table, RType32Bitfield, SType32Bitfield,
+    AType32Bitfield, BType32Bitfield, Funct3, Funct3AtomicTable, Funct3BranchTable, Funct3Expr, Funct3JALRTable,
+    Funct3LoadTable, Funct3MulDivTable, Funct3OpImmediateTable, Funct3OpRegisterTable, Funct3StoreTable,
+    Funct3SystemTable, Funct5AtomicTable, Funct7, Funct7Table, IFenceType32Bitfield, IType32Bitfield, Immediate11Table,
+    JType32Bitfield, Opcode7Table, RType32Bitfield, SType32Bitfield, UType32Bitfield,
 };
-use crate::memory::{InstructionLength, Word};
+use crate::compressed::decode_compressed;
+use crate::memory::{HalfWord, InstructionLength, Word};
 use std::fmt::Debug;
+use std::iter;
+use std::ops::RangeInclusive;
 
 // We use the term IALIGN (measured in bits) to refer to the instruction-address alignment
 // constraint  the implementation enforces. IALIGN is 32 bits in the base ISA, but some ISA
 // extensions, including the compressed ISA extension, relax IALIGN to 16 bits. IALIGN may not take
 // on any value other than  16 or 32.
-pub const IALIGN: InstructionLength = InstructionLength::Word;
+// Compressed instructions are supported (see `compressed.rs`), hence 16 bits; harts without the C
+// extension enabled still require 32 bits (see `SimpleRV32IHart::jump`).
+pub const IALIGN: InstructionLength = InstructionLength::HalfWord;
 
 // We use the term ILEN (measured in bits) to refer to the maximum instruction length supported by
 // an/ implementation, and which is always a multiple of IALIGN. For implementations supporting only
@@ -38,12 +51,18 @@ pub const ILEN: InstructionLength = InstructionLength::Word;
 // refer to the width of an integer register in bits (either 32 or 64).
 pub const XLEN: InstructionLength = InstructionLength::Word;
 
+// The canonical NOP, "addi x0, x0, 0"; other instructions writing x0 have no effect either, but
+// this one is recognized (see `RV32Instruction::is_nop`)
+pub const NOP: Word = 0x0000_0013;
+
 pub const ADDI: Descriptor = Descriptor {
     set: RV32I.name(),
     name: "Add Immediate",
+    mnemonic: "addi",
     format: InstructionKind::IntegerRegisterImmediate,
     opcode: Some(Opcode7Table::OpImmediate),
-    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::ADD)),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::ADDI)),
+    funct5: None,
     funct7: None,
     imm11: None,
 };
@@ -51,9 +70,11 @@ pub const ADDI: Descriptor = Descriptor {
 pub const SLTI: Descriptor = Descriptor {
     set: RV32I.name(),
     name: "Set Less Than Immediate",
+    mnemonic: "slti",
     format: InstructionKind::IntegerRegisterImmediate,
     opcode: Some(Opcode7Table::OpImmediate),
-    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLT)),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::SLTI)),
+    funct5: None,
     funct7: None,
     imm11: None,
 };
@@ -61,9 +82,11 @@ pub const SLTI: Descriptor = Descriptor {
 pub const SLTIU: Descriptor = Descriptor {
     set: RV32I.name(),
     name: "Set Less Than Immediate Unsigned",
+    mnemonic: "sltiu",
     format: InstructionKind::IntegerRegisterImmediate,
     opcode: Some(Opcode7Table::OpImmediate),
-    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLTU)),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::SLTIU)),
+    funct5: None,
     funct7: None,
     imm11: None,
 };
@@ -71,15 +94,822 @@ pub const SLTIU: Descriptor = Descriptor {
 pub const ANDI: Descriptor = Descriptor {
     set: RV32I.name(),
     name: "AND Immediate",
+    mnemonic: "andi",
     format: InstructionKind::IntegerRegisterImmediate,
     opcode: Some(Opcode7Table::OpImmediate),
-    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLTU)), // TODO review
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::ANDI)),
+    funct5: None,
     funct7: None,
     imm11: None,
 };
 
-// TODO variable instruction length;
-//      see https://riscv.org/wp-content/uploads/2017/05/riscv-spec-v2.2.pdf page 5
+pub const ORI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "OR Immediate",
+    mnemonic: "ori",
+    format: InstructionKind::IntegerRegisterImmediate,
+    opcode: Some(Opcode7Table::OpImmediate),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::ORI)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const XORI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Exclusive OR Immediate",
+    mnemonic: "xori",
+    format: InstructionKind::IntegerRegisterImmediate,
+    opcode: Some(Opcode7Table::OpImmediate),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::XORI)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+// Shifts by an immediate hold a funct7 in the upper bits of their immediate, which tells SRLI and
+// SRAI apart; the shift amount (shamt) is in the lower 5 bits
+pub const SLLI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Left Logical Immediate",
+    mnemonic: "slli",
+    format: InstructionKind::IntegerRegisterImmediate,
+    opcode: Some(Opcode7Table::OpImmediate),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::SLLI)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SRLI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Right Logical Immediate",
+    mnemonic: "srli",
+    format: InstructionKind::IntegerRegisterImmediate,
+    opcode: Some(Opcode7Table::OpImmediate),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::SRAI)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SRAI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Right Arithmetic Immediate",
+    mnemonic: "srai",
+    format: InstructionKind::IntegerRegisterImmediate,
+    opcode: Some(Opcode7Table::OpImmediate),
+    funct3: Some(Funct3Expr::OpImmediate(Funct3OpImmediateTable::SRAI)),
+    funct5: None,
+    funct7: Some(Funct7Table::Arithmetic),
+    imm11: None,
+};
+
+pub const ADD: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Add",
+    mnemonic: "add",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::ADD)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SUB: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Subtract",
+    mnemonic: "sub",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SUB)),
+    funct5: None,
+    funct7: Some(Funct7Table::Arithmetic),
+    imm11: None,
+};
+
+pub const SLT: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Set Less Than",
+    mnemonic: "slt",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLT)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SLTU: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Set Less Than Unsigned",
+    mnemonic: "sltu",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLTU)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const AND: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "AND",
+    mnemonic: "and",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::AND)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const OR: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "OR",
+    mnemonic: "or",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::OR)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const XOR: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Exclusive OR",
+    mnemonic: "xor",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::XOR)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SLL: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Left Logical",
+    mnemonic: "sll",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SLL)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SRL: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Right Logical",
+    mnemonic: "srl",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegister(Funct3OpRegisterTable::SRL)),
+    funct5: None,
+    funct7: Some(Funct7Table::Logical),
+    imm11: None,
+};
+
+pub const SRA: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Shift Right Arithmetic",
+    mnemonic: "sra",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::OpRegisterArithmetic(Funct3OpRegisterTable::SRA)),
+    funct5: None,
+    funct7: Some(Funct7Table::Arithmetic),
+    imm11: None,
+};
+
+pub const JAL: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Jump And Link",
+    mnemonic: "jal",
+    format: InstructionKind::UnconditionalJump,
+    opcode: Some(Opcode7Table::JumpAndLink),
+    funct3: None,
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const JALR: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Jump And Link Register",
+    mnemonic: "jalr",
+    format: InstructionKind::JumpAndLinkRegister,
+    opcode: Some(Opcode7Table::JumpAndLinkRegister),
+    funct3: Some(Funct3Expr::JumpAndLinkRegister(Funct3JALRTable::JALR)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BEQ: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Equal",
+    mnemonic: "beq",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BEQ)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BNE: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Not Equal",
+    mnemonic: "bne",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BNE)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BLT: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Less Than",
+    mnemonic: "blt",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BLT)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BGE: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Greater or Equal",
+    mnemonic: "bge",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BGE)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BLTU: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Less Than, Unsigned",
+    mnemonic: "bltu",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BLTU)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const BGEU: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Branch Greater or Equal, Unsigned",
+    mnemonic: "bgeu",
+    format: InstructionKind::ConditionBranch,
+    opcode: Some(Opcode7Table::Branch),
+    funct3: Some(Funct3Expr::Branch(Funct3BranchTable::BGEU)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LB: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Byte",
+    mnemonic: "lb",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LB)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LH: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Halfword",
+    mnemonic: "lh",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LH)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LW: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Word",
+    mnemonic: "lw",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LW)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LBU: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Byte Unsigned",
+    mnemonic: "lbu",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LBU)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LHU: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Halfword Unsigned",
+    mnemonic: "lhu",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LHU)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const SB: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Store Byte",
+    mnemonic: "sb",
+    format: InstructionKind::Store,
+    opcode: Some(Opcode7Table::Store),
+    funct3: Some(Funct3Expr::Store(Funct3StoreTable::SB)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const SH: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Store Halfword",
+    mnemonic: "sh",
+    format: InstructionKind::Store,
+    opcode: Some(Opcode7Table::Store),
+    funct3: Some(Funct3Expr::Store(Funct3StoreTable::SH)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const SW: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Store Word",
+    mnemonic: "sw",
+    format: InstructionKind::Store,
+    opcode: Some(Opcode7Table::Store),
+    funct3: Some(Funct3Expr::Store(Funct3StoreTable::SW)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LUI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Load Upper Immediate",
+    mnemonic: "lui",
+    format: InstructionKind::UpperImmediate,
+    opcode: Some(Opcode7Table::LoadUpperImmediate),
+    funct3: None,
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const AUIPC: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Add Upper Immediate to PC",
+    mnemonic: "auipc",
+    format: InstructionKind::UpperImmediate,
+    opcode: Some(Opcode7Table::AddUpperImmediatePC),
+    funct3: None,
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const ECALL: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Environment Call",
+    mnemonic: "ecall",
+    format: InstructionKind::EnvironmentCallAndBreakpoint,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::ECALL)),
+    funct5: None,
+    funct7: None,
+    imm11: Some(Immediate11Table::ECALL),
+};
+
+pub const EBREAK: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Environment Break",
+    mnemonic: "ebreak",
+    format: InstructionKind::EnvironmentCallAndBreakpoint,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::ECALL)),
+    funct5: None,
+    funct7: None,
+    imm11: Some(Immediate11Table::EBREAK),
+};
+
+// Privileged instructions, encoded as ECALL and EBREAK are
+pub const MRET: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Machine-mode Trap Return",
+    mnemonic: "mret",
+    format: InstructionKind::EnvironmentCallAndBreakpoint,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::ECALL)),
+    funct5: None,
+    funct7: None,
+    imm11: Some(Immediate11Table::MRET),
+};
+
+pub const SRET: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Supervisor-mode Trap Return",
+    mnemonic: "sret",
+    format: InstructionKind::EnvironmentCallAndBreakpoint,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::ECALL)),
+    funct5: None,
+    funct7: None,
+    imm11: Some(Immediate11Table::SRET),
+};
+
+pub const WFI: Descriptor = Descriptor {
+    set: RV32I.name(),
+    name: "Wait For Interrupt",
+    mnemonic: "wfi",
+    format: InstructionKind::EnvironmentCallAndBreakpoint,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::ECALL)),
+    funct5: None,
+    funct7: None,
+    imm11: Some(Immediate11Table::WFI),
+};
+
+// RV64I loads and stores of doublewords. Harts run them under an XLEN of 64 only (see `Xlen`), their
+// registers being 64 bits wide, even though the ALU is still 32 bits.
+pub const LD: Descriptor = Descriptor {
+    set: RV64I.name(),
+    name: "Load Doubleword",
+    mnemonic: "ld",
+    format: InstructionKind::Load,
+    opcode: Some(Opcode7Table::Load),
+    funct3: Some(Funct3Expr::Load(Funct3LoadTable::LD)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const SD: Descriptor = Descriptor {
+    set: RV64I.name(),
+    name: "Store Doubleword",
+    mnemonic: "sd",
+    format: InstructionKind::Store,
+    opcode: Some(Opcode7Table::Store),
+    funct3: Some(Funct3Expr::Store(Funct3StoreTable::SD)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRW: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read/Write CSR",
+    mnemonic: "csrrw",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRW)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRS: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read and Set Bits in CSR",
+    mnemonic: "csrrs",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRS)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRC: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read and Clear Bits in CSR",
+    mnemonic: "csrrc",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRC)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRWI: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read/Write CSR Immediate",
+    mnemonic: "csrrwi",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRWI)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRSI: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read and Set Bits in CSR Immediate",
+    mnemonic: "csrrsi",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRSI)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const CSRRCI: Descriptor = Descriptor {
+    set: Zicsr.name(),
+    name: "Atomic Read and Clear Bits in CSR Immediate",
+    mnemonic: "csrrci",
+    format: InstructionKind::ControlAndStatusRegister,
+    opcode: Some(Opcode7Table::System),
+    funct3: Some(Funct3Expr::System(Funct3SystemTable::CSRRCI)),
+    funct5: None,
+    funct7: None,
+    imm11: None,
+};
+
+pub const LR_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Load-Reserved Word",
+    mnemonic: "lr.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::LR),
+    funct7: None,
+    imm11: None,
+};
+
+pub const SC_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Store-Conditional Word",
+    mnemonic: "sc.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::SC),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOSWAP_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Swap Word",
+    mnemonic: "amoswap.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOSWAP),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOADD_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Add Word",
+    mnemonic: "amoadd.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOADD),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOXOR_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: XOR Word",
+    mnemonic: "amoxor.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOXOR),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOAND_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: AND Word",
+    mnemonic: "amoand.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOAND),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOOR_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: OR Word",
+    mnemonic: "amoor.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOOR),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOMIN_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Minimum Word",
+    mnemonic: "amomin.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOMIN),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOMAX_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Maximum Word",
+    mnemonic: "amomax.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOMAX),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOMINU_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Minimum Word, Unsigned",
+    mnemonic: "amominu.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOMINU),
+    funct7: None,
+    imm11: None,
+};
+
+pub const AMOMAXU_W: Descriptor = Descriptor {
+    set: RV32A.name(),
+    name: "Atomic Memory Operation: Maximum Word, Unsigned",
+    mnemonic: "amomaxu.w",
+    format: InstructionKind::AtomicMemoryOperation,
+    opcode: Some(Opcode7Table::AtomicMemoryOp),
+    funct3: Some(Funct3Expr::Atomic(Funct3AtomicTable::W)),
+    funct5: Some(Funct5AtomicTable::AMOMAXU),
+    funct7: None,
+    imm11: None,
+};
+
+pub const MUL: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Multiply",
+    mnemonic: "mul",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::MUL)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const MULH: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Multiply High",
+    mnemonic: "mulh",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::MULH)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const MULHSU: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Multiply High, Signed by Unsigned",
+    mnemonic: "mulhsu",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::MULHSU)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const MULHU: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Multiply High, Unsigned",
+    mnemonic: "mulhu",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::MULHU)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const DIV: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Divide",
+    mnemonic: "div",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::DIV)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const DIVU: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Divide, Unsigned",
+    mnemonic: "divu",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::DIVU)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const REM: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Remainder",
+    mnemonic: "rem",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::REM)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+pub const REMU: Descriptor = Descriptor {
+    set: RV32M.name(),
+    name: "Remainder, Unsigned",
+    mnemonic: "remu",
+    format: InstructionKind::IntegerRegisterRegister,
+    opcode: Some(Opcode7Table::OpRegister),
+    funct3: Some(Funct3Expr::MulDiv(Funct3MulDivTable::REMU)),
+    funct5: None,
+    funct7: Some(Funct7Table::MulDiv),
+    imm11: None,
+};
+
+// Instructions known by RV32I, in the order they are looked up by `Architecture::describe`
+pub const RV32I_INSTRUCTIONS: &[Descriptor] = &[
+    ADDI, SLTI, SLTIU, ANDI, ORI, XORI, SLLI, SRLI, SRAI, ADD, SUB, SLT, SLTU, AND, OR, XOR, SLL, SRL, SRA, JAL, JALR,
+    BEQ, BNE, BLT, BGE, BLTU, BGEU, LB, LH, LW, LBU, LHU, SB, SH, SW, LUI, AUIPC, ECALL, EBREAK,
+];
+
+// Instructions known by RV32M, looked up after the RV32I ones
+pub const RV32M_INSTRUCTIONS: &[Descriptor] = &[MUL, MULH, MULHSU, MULHU, DIV, DIVU, REM, REMU];
+
+// Instructions known by RV32A, looked up after the RV32M ones
+pub const RV32A_INSTRUCTIONS: &[Descriptor] = &[
+    LR_W, SC_W, AMOSWAP_W, AMOADD_W, AMOXOR_W, AMOAND_W, AMOOR_W, AMOMIN_W, AMOMAX_W, AMOMINU_W, AMOMAXU_W,
+];
+
+// Instructions known by Zicsr, looked up after the RV32A ones
+pub const ZICSR_INSTRUCTIONS: &[Descriptor] = &[CSRRW, CSRRS, CSRRC, CSRRWI, CSRRSI, CSRRCI];
+
+// Instructions of RV64I beyond RV32I, looked up by RV64I only, after the RV32I ones
+pub const RV64I_INSTRUCTIONS: &[Descriptor] = &[LD, SD];
+
+// Instructions of the privileged architecture, looked up last
+pub const PRIVILEGED_INSTRUCTIONS: &[Descriptor] = &[MRET, SRET, WFI];
 
 #[repr(C)]
 pub union ChompRV32 {
@@ -87,6 +917,7 @@ pub union ChompRV32 {
     pub integer_register_immediate: IType32Bitfield,
     pub integer_register_register: RType32Bitfield,
     pub unconditional_jump: JType32Bitfield,
+    pub jump_and_link_register: IType32Bitfield,
     pub condition_branch: BType32Bitfield,
     pub load: IType32Bitfield,
     pub store: SType32Bitfield,
@@ -94,15 +925,226 @@ pub union ChompRV32 {
     pub control_and_status_register: IType32Bitfield,
     pub time_and_counter: IType32Bitfield,
     pub environment_call_and_breakpoint: RType32Bitfield,
+    pub atomic_memory_operation: AType32Bitfield,
+    pub upper_immediate: UType32Bitfield,
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct Descriptor {
     pub set: &'static str,
     pub name: &'static str,
+    pub mnemonic: &'static str,
     pub format: InstructionKind,
     pub opcode: Option<Opcode7Table>,
     pub funct3: Option<Funct3Expr>,
+    pub funct5: Option<Funct5AtomicTable>,
     pub funct7: Option<Funct7Table>,
     pub imm11: Option<Immediate11Table>,
 }
+
+impl Descriptor {
+    // The raw funct3 of the instruction, checked against its opcode: decoding it back must yield
+    // the same Funct3Expr
+    pub fn encode_funct3(&self) -> Result<Funct3> {
+        let funct3 = self.funct3.with_context(|| format!("{} has no funct3", self.mnemonic))?;
+        let opcode = self.opcode.with_context(|| format!("{} has no opcode", self.mnemonic))?;
+        let funct7 = self.funct7.map(|funct7| Funct7::masked_new(u8::from(funct7)));
+
+        let encoded = funct3.encode();
+        ensure!(
+            Funct3Expr::try_from(opcode.into(), encoded, funct7).ok() == Some(funct3),
+            "funct3 of {} does not belong to its opcode",
+            self.mnemonic
+        );
+
+        Ok(encoded)
+    }
+
+    // The extension a machine must be built with to run the instruction, if any
+    pub fn extension(&self) -> Option<Ext> {
+        match self.set {
+            set if set == RV32M.name() => Some(Ext::M),
+            set if set == RV32A.name() => Some(Ext::A),
+            set if set == Zicsr.name() => Some(Ext::Zicsr),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a single 32 bits RV32I instruction, without going through a hart.
+///
+/// ```
+/// use monologvm_core::architecture::RV32Instruction;
+/// use monologvm_core::instruction::decode_rv32;
+///
+/// let instruction = decode_rv32(0x0050_0093).unwrap(); // addi x1, x0, 5
+///
+/// match instruction {
+///     RV32Instruction::IntegerRegisterImmediate(i_type) => {
+///         assert_eq!(i_type.rd().value(), 1);
+///         assert_eq!(i_type.rs1().value(), 0);
+///         assert_eq!(i_type.imm().value(), 5);
+///     }
+///     _ => panic!("not an immediate instruction"),
+/// }
+/// ```
+pub fn decode_rv32(word: u32) -> Option<RV32Instruction> { RV32I.decode_opt(word) }
+
+// Same as `decode_rv32`, along with the bits each field of the instruction occupies in `word` (see
+// `RV32Instruction::field_bits`)
+pub fn decode_rv32_with_provenance(word: u32) -> Option<(RV32Instruction, Vec<(FieldName, RangeInclusive<u32>)>)> {
+    decode_rv32(word).map(|instruction| (instruction, instruction.field_bits()))
+}
+
+// Decodes a whole code buffer (e.g. a .text section) loaded at `base`, yielding each instruction
+// address along with the decoded instruction. A trailing partial instruction yields None.
+pub fn decode_stream(bytes: &[u8], base: u32) -> impl Iterator<Item = (u32, Option<RV32Instruction>)> + '_ {
+    let mut offset = 0;
+
+    iter::from_fn(move || {
+        let rest = bytes.get(offset..).filter(|rest| !rest.is_empty())?;
+        let address = base.wrapping_add(offset as u32);
+
+        let Some(halfword) = rest.get(..2).map(|le| HalfWord::from_le_bytes([le[0], le[1]])) else {
+            offset = bytes.len();
+            return Some((address, None));
+        };
+
+        let length = instruction_length(halfword);
+        let instruction = match (length, rest.get(..length.bytes())) {
+            (InstructionLength::HalfWord, _) => decode_compressed(halfword),
+            (_, Some(le)) => decode_rv32(Word::from_le_bytes([le[0], le[1], le[2], le[3]])),
+            (_, None) => None,
+        };
+
+        offset = (offset + length.bytes()).min(bytes.len());
+        Some((address, instruction))
+    })
+}
+
+// Instructions are encoded in a variable length, given by the lowest bits of their first halfword;
+// see https://riscv.org/wp-content/uploads/2017/05/riscv-spec-v2.2.pdf page 5. Only the 16 bits
+// (compressed) and 32 bits lengths are supported: longer encodings are reported as 32 bits, and
+// told apart by encoded_length.
+pub fn instruction_length(halfword: HalfWord) -> InstructionLength {
+    if halfword & 0b11 == 0b11 {
+        InstructionLength::Word
+    } else {
+        InstructionLength::HalfWord
+    }
+}
+
+// The length in bits the first halfword of an instruction encodes, supported or not: 48 bits and
+// longer encodings set bits [4:0], then [5], then [6], and from 80 bits on the length is given by
+// bits [14:12] (all set being reserved for 192 bits and longer).
+pub fn encoded_length(halfword: HalfWord) -> u32 {
+    match halfword {
+        _ if halfword & 0b11 != 0b11 => 16,
+        _ if halfword & 0b1_1100 != 0b1_1100 => 32,
+        _ if halfword & 0b10_0000 == 0 => 48,
+        _ if halfword & 0b100_0000 == 0 => 64,
+        _ => 80 + 16 * ((halfword as u32 >> 12) & 0b111),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_encode_the_funct3_of_descriptors() {
+        assert_eq!(ADDI.encode_funct3().unwrap().value(), 0b000);
+        assert_eq!(SUB.encode_funct3().unwrap().value(), 0b000);
+        assert_eq!(LW.encode_funct3().unwrap().value(), 0b010);
+        assert_eq!(CSRRSI.encode_funct3().unwrap().value(), 0b110);
+        assert!(JAL.encode_funct3().is_err());
+    }
+
+    #[test]
+    fn should_reject_a_funct3_foreign_to_the_opcode() {
+        let mismatched = Descriptor {
+            opcode: Some(Opcode7Table::Store),
+            ..LW
+        };
+
+        assert!(mismatched.encode_funct3().is_err());
+    }
+
+    #[test]
+    fn should_decode_a_stream_of_instructions() {
+        let bytes = [
+            0x93, 0x00, 0x50, 0x00, // addi x1, x0, 5
+            0x05, 0x05, // c.addi x10, 1
+            0x73, 0x00, 0x00, 0x00, // ecall
+        ];
+
+        let stream: Vec<_> = decode_stream(&bytes, 0x1000).collect();
+
+        assert_eq!(
+            stream,
+            vec![
+                (0x1000, decode_rv32(0x0050_0093)),
+                (0x1004, decode_rv32(0x0015_0513)),
+                (0x1006, decode_rv32(0x0000_0073)),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_yield_none_for_a_trailing_partial_instruction() {
+        let bytes = [0x93, 0x00, 0x50, 0x00, 0x93, 0x00];
+
+        let stream: Vec<_> = decode_stream(&bytes, 0).collect();
+
+        assert_eq!(stream, vec![(0, decode_rv32(0x0050_0093)), (4, None)]);
+    }
+
+    #[test]
+    fn should_predecode_32_bits_instructions() {
+        let addi = 0x0050_0093u32; // addi x1, x0, 5
+
+        assert_eq!(instruction_length(addi as HalfWord), InstructionLength::Word);
+        assert_eq!(instruction_length(0xFFFF), InstructionLength::Word);
+    }
+
+    #[test]
+    fn should_predecode_16_bits_compressed_instructions() {
+        assert_eq!(instruction_length(0x0505), InstructionLength::HalfWord); // c.addi x10, 1
+        assert_eq!(instruction_length(0x0000), InstructionLength::HalfWord); // quadrant 0 (illegal)
+        assert_eq!(instruction_length(0x8082), InstructionLength::HalfWord); // c.jr x1
+    }
+
+    #[test]
+    fn should_tell_the_bits_of_each_field_while_decoding() {
+        let (_, addi) = decode_rv32_with_provenance(0x0050_0093).unwrap(); // addi x1, x0, 5
+        let (_, bne) = decode_rv32_with_provenance(0xFE00_9CE3).unwrap(); // bne x1, x0, -8
+
+        assert!(addi.contains(&(FieldName::Imm, 20..=31)));
+        assert!(addi.contains(&(FieldName::Rd, 7..=11)));
+        assert_eq!(bne.iter().map(|(_, bits)| bits.clone().count()).sum::<usize>(), 32);
+    }
+
+    #[test]
+    fn should_tell_the_encoded_length_of_longer_instructions() {
+        assert_eq!(encoded_length(0x0505), 16); // c.addi x10, 1
+        assert_eq!(encoded_length(0x0093), 32); // addi
+        assert_eq!(encoded_length(0b001_1111), 48);
+        assert_eq!(encoded_length(0b011_1111), 64);
+        assert_eq!(encoded_length(0x107F), 96);
+        assert!(encoded_length(0xFFFF) > ILEN as u32);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn should_serialize_a_descriptor_to_json_and_back() {
+        // The &'static str fields borrow from the input, which a literal outlives
+        const JSON: &str = concat!(
+            r#"{"set":"RV32I","name":"Add Immediate","mnemonic":"addi","format":"IntegerRegisterImmediate","#,
+            r#""opcode":"OpImmediate","funct3":{"OpImmediate":"ADDI"},"funct5":null,"funct7":null,"imm11":null}"#,
+        );
+
+        assert_eq!(serde_json::to_string(&ADDI).unwrap(), JSON);
+        assert_eq!(serde_json::from_str::<Descriptor>(JSON).unwrap(), ADDI);
+    }
+}
@@ -19,10 +19,27 @@
 
 pub mod dmacro;
 
+pub mod alu;
+pub mod analysis;
 pub mod architecture;
 pub mod bitfield;
+pub mod bus;
+pub mod compliance;
+pub mod compressed;
+pub mod csr;
+pub mod device;
+pub mod disassembler;
+pub mod encoder;
+#[cfg(any(test, feature = "testing"))]
+pub mod encodings;
 pub mod hart;
 pub mod instruction;
+pub mod loader;
 pub mod machine;
 pub mod memory;
+pub mod profile;
 pub mod register;
+pub mod syscall;
+pub mod trace;
+pub mod trap;
+pub mod util;
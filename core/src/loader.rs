@@ -0,0 +1,317 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use anyhow::{ensure, Context, Result};
+
+use crate::disassembler::SymbolTable;
+use crate::machine::{HostInterface, Machine};
+use crate::memory::{Byte, Memory, Word};
+use crate::register::RegisterValue64;
+
+const ELF_MAGIC: [Byte; 4] = [0x7F, b'E', b'L', b'F'];
+const ELF_CLASS_32: Byte = 1;
+const ELF_DATA_LITTLE_ENDIAN: Byte = 1;
+const ELF_MACHINE_RISCV: u16 = 243;
+const ELF_HEADER_SIZE: usize = 52;
+const ELF_PROGRAM_LOAD: u32 = 1;
+const ELF_PROGRAM_EXECUTABLE: u32 = 1; // PF_X
+const ELF_SECTION_SYMBOL_TABLE: u32 = 2;
+const ELF_SECTION_UNDEFINED: u16 = 0;
+const ELF_SYMBOL_SIZE: usize = 16;
+const ELF_SYMBOL_NO_TYPE: Byte = 0;
+const ELF_SYMBOL_OBJECT: Byte = 1;
+const ELF_SYMBOL_FUNCTION: Byte = 2;
+
+const CONTEXT_OUT_OF_FILE: &str = "ELF file is truncated.";
+
+pub fn is_elf(bytes: &[Byte]) -> bool { bytes.starts_with(&ELF_MAGIC) }
+
+// A PT_LOAD segment of an ELF file: `data` is copied to `address`, then zero-filled up to `memory_size`
+struct Segment<'a> {
+    address: Word,
+    data: &'a [Byte],
+    memory_size: usize,
+    flags: u32,
+}
+
+impl Machine {
+    // Copies a flat binary image to `base` and points the PC at its first byte.
+    pub fn load_binary(&mut self, bytes: &[Byte], base: Word) -> Result<()> {
+        self.write_segment(base, bytes, bytes.len())?;
+        self.start_at(base);
+        Ok(())
+    }
+
+    // Loads the PT_LOAD segments of a 32-bit little-endian RISC-V executable and points the PC at
+    // its entry point, which is also returned. Executables naming a tohost word talk to the machine
+    // through it (see `HostInterface`).
+    pub fn load_elf(&mut self, bytes: &[Byte]) -> Result<Word> {
+        for segment in segments(bytes)? {
+            self.write_segment(segment.address, segment.data, segment.memory_size)?;
+        }
+
+        let entry = read_u32(bytes, 0x18)?;
+
+        self.set_host_interface(HostInterface::find(&elf_symbols(bytes).unwrap_or_default()));
+        self.start_at(entry);
+        Ok(entry)
+    }
+
+    // Same as `load_elf`, also returning the symbols of the executable (see `elf_symbols`)
+    pub fn load_elf_with_symbols(&mut self, bytes: &[Byte]) -> Result<(Word, SymbolTable)> {
+        let entry = self.load_elf(bytes)?;
+        Ok((entry, elf_symbols(bytes)?))
+    }
+
+    // Writes `data` at `address`, zero-filling up to `size` bytes (e.g. for .bss). The whole segment
+    // is written at once, through a single slice of RAM, or not at all.
+    fn write_segment(&mut self, address: Word, data: &[Byte], size: usize) -> Result<()> {
+        let end = Word::try_from(size.max(data.len()))
+            .ok()
+            .and_then(|size| address.checked_add(size))
+            .context("Segment does not fit in the address space.")?;
+
+        let mut bus = self.bus();
+        let segment = bus
+            .region_slice_mut(address..end)
+            .with_context(|| format!("Segment at {:#010x} is not backed by RAM.", address))?;
+        let (file, bss) = segment.split_at_mut(data.len());
+        file.copy_from_slice(data);
+        bss.fill(0);
+        drop(bus);
+
+        self.extend_program(end);
+        Ok(())
+    }
+
+    // Every hart starts (and restarts, on reset) at the same address; they can tell themselves apart
+    // by their mhartid
+    fn start_at(&mut self, pc: Word) {
+        for id in 0..self.harts().len() {
+            let hart = self.hart_mut(id);
+            hart.set_reset_vector(pc as RegisterValue64);
+            hart.registers_mut().pc = pc as RegisterValue64;
+        }
+    }
+}
+
+// The bytes of the executable segments of an ELF file, by load address, e.g. to disassemble its code
+// without the headers and data around it
+pub fn elf_code(bytes: &[Byte]) -> Result<Vec<(Word, &[Byte])>> {
+    Ok(segments(bytes)?
+        .into_iter()
+        .filter(|segment| segment.flags & ELF_PROGRAM_EXECUTABLE != 0)
+        .map(|segment| (segment.address, segment.data))
+        .collect())
+}
+
+// The PT_LOAD segments of a 32-bit little-endian RISC-V executable
+fn segments(bytes: &[Byte]) -> Result<Vec<Segment<'_>>> {
+    ensure!(is_elf(bytes), "Not an ELF file.");
+    ensure!(bytes.len() >= ELF_HEADER_SIZE, CONTEXT_OUT_OF_FILE);
+    ensure!(bytes[4] == ELF_CLASS_32, "Only 32-bit ELF files are supported.");
+    ensure!(bytes[5] == ELF_DATA_LITTLE_ENDIAN, "Only little-endian ELF files are supported.");
+    ensure!(read_u16(bytes, 0x12)? == ELF_MACHINE_RISCV, "Not a RISC-V ELF file.");
+
+    let program_header_offset = read_u32(bytes, 0x1C)? as usize;
+    let program_header_size = read_u16(bytes, 0x2A)? as usize;
+    let program_header_count = read_u16(bytes, 0x2C)? as usize;
+
+    let mut segments = Vec::new();
+    for index in 0..program_header_count {
+        let header = program_header_offset + index * program_header_size;
+
+        if read_u32(bytes, header)? != ELF_PROGRAM_LOAD {
+            continue;
+        }
+
+        let offset = read_u32(bytes, header + 4)? as usize;
+        let file_size = read_u32(bytes, header + 16)? as usize;
+
+        segments.push(Segment {
+            address: read_u32(bytes, header + 8)?,
+            data: bytes.get(offset..offset + file_size).context(CONTEXT_OUT_OF_FILE)?,
+            memory_size: read_u32(bytes, header + 20)? as usize,
+            flags: read_u32(bytes, header + 24)?,
+        });
+    }
+
+    Ok(segments)
+}
+
+// The addresses named by the symbol table (.symtab) of a valid ELF file, e.g. to label functions in
+// disassembly. Undefined symbols, and those naming sections or files, are left out; where several
+// name the same address, the first one wins. Stripped files have none.
+pub fn elf_symbols(bytes: &[Byte]) -> Result<SymbolTable> {
+    let section_header_offset = read_u32(bytes, 0x20)? as usize;
+    let section_header_size = read_u16(bytes, 0x2E)? as usize;
+    let section_header_count = read_u16(bytes, 0x30)? as usize;
+    let section = |index: usize| section_header_offset + index * section_header_size;
+
+    let mut symbols = SymbolTable::new();
+    for index in 0..section_header_count {
+        let header = section(index);
+
+        if read_u32(bytes, header + 4)? != ELF_SECTION_SYMBOL_TABLE {
+            continue;
+        }
+
+        let offset = read_u32(bytes, header + 16)? as usize;
+        let size = read_u32(bytes, header + 20)? as usize;
+        let strings = read_u32(bytes, section(read_u32(bytes, header + 24)? as usize) + 16)? as usize;
+
+        for symbol in (offset..offset + size).step_by(ELF_SYMBOL_SIZE) {
+            let kind = bytes.get(symbol + 12).context(CONTEXT_OUT_OF_FILE)? & 0xF;
+            let defined = read_u16(bytes, symbol + 14)? != ELF_SECTION_UNDEFINED;
+
+            if !defined || !matches!(kind, ELF_SYMBOL_NO_TYPE | ELF_SYMBOL_OBJECT | ELF_SYMBOL_FUNCTION) {
+                continue;
+            }
+
+            let name = read_string(bytes, strings + read_u32(bytes, symbol)? as usize)?;
+            if !name.is_empty() {
+                symbols.entry(read_u32(bytes, symbol + 4)?).or_insert(name);
+            }
+        }
+    }
+
+    Ok(symbols)
+}
+
+fn read_u16(bytes: &[Byte], offset: usize) -> Result<u16> {
+    let field = bytes.get(offset..offset + 2).context(CONTEXT_OUT_OF_FILE)?;
+    Ok(u16::from_le_bytes([field[0], field[1]]))
+}
+
+fn read_u32(bytes: &[Byte], offset: usize) -> Result<u32> {
+    let field = bytes.get(offset..offset + 4).context(CONTEXT_OUT_OF_FILE)?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+// A NUL-terminated string, as found in string tables
+fn read_string(bytes: &[Byte], offset: usize) -> Result<String> {
+    let tail = bytes.get(offset..).context(CONTEXT_OUT_OF_FILE)?;
+    let length = tail.iter().position(|&byte| byte == 0).context(CONTEXT_OUT_OF_FILE)?;
+    Ok(String::from_utf8_lossy(&tail[..length]).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    use crate::architecture::{Architecture, RV32I};
+    use crate::disassembler::disassemble_at;
+
+    fn hash(bytes: &[Byte]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn should_hash_a_loaded_program_through_the_ram_slice() {
+        let program: Vec<Byte> = (0..=255).collect();
+        let mut machine = Machine::with_memory_size(4096);
+
+        machine.load_binary(&program, 0x100).unwrap();
+        let bus = machine.bus();
+        let slice = bus.region_slice(0x100..0x200).unwrap();
+
+        assert_eq!(hash(slice), hash(&program));
+    }
+
+    #[test]
+    fn should_not_load_past_the_end_of_ram() {
+        let mut machine = Machine::with_memory_size(4096);
+
+        assert!(machine.load_binary(&[0; 16], 4090).is_err());
+    }
+
+    #[test]
+    fn should_leave_memory_untouched_when_the_bss_does_not_fit() {
+        let mut machine = Machine::with_memory_size(4096);
+
+        assert!(machine.write_segment(4000, &[0xFF; 16], 200).is_err());
+        assert_eq!(machine.bus().read_byte(4000), 0);
+    }
+
+    // An executable loading `code` at `base`, its entry point, with a single symbol naming `address`
+    // (as a global function). Its sections are the null one, .symtab and .strtab, all unnamed.
+    pub(crate) fn elf(base: Word, code: &[Word], symbol: &str, address: Word) -> Vec<Byte> {
+        let code: Vec<Byte> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let strings = 0x54 + code.len();
+        let symbols = (strings + symbol.len() + 2).next_multiple_of(4);
+        let sections = symbols + 2 * ELF_SYMBOL_SIZE;
+
+        let mut elf = vec![0; sections + 3 * 40];
+        let mut put = |offset: usize, bytes: &[Byte]| elf[offset..offset + bytes.len()].copy_from_slice(bytes);
+        let word = |value: usize| (value as u32).to_le_bytes();
+
+        put(0x00, &[0x7F, b'E', b'L', b'F', ELF_CLASS_32, ELF_DATA_LITTLE_ENDIAN, 1]);
+        put(0x12, &ELF_MACHINE_RISCV.to_le_bytes());
+        put(0x18, &base.to_le_bytes()); // entry
+        put(0x1C, &word(0x34)); // program headers
+        put(0x20, &word(sections)); // section headers
+        put(0x2A, &[32, 0, 1, 0, 40, 0, 3, 0]); // one 32-byte program header, three 40-byte section headers
+
+        put(0x34, &ELF_PROGRAM_LOAD.to_le_bytes());
+        put(0x38, &word(0x54));
+        put(0x3C, &base.to_le_bytes());
+        put(0x44, &word(code.len())); // file size
+        put(0x48, &word(code.len())); // memory size
+        put(0x4C, &word(5)); // readable and executable
+        put(0x54, &code);
+
+        put(strings + 1, symbol.as_bytes()); // after the empty name
+        put(symbols + ELF_SYMBOL_SIZE, &word(1));
+        put(symbols + ELF_SYMBOL_SIZE + 4, &address.to_le_bytes());
+        put(symbols + ELF_SYMBOL_SIZE + 12, &[0x12, 0, 1, 0]); // global function, defined
+
+        put(sections + 44, &ELF_SECTION_SYMBOL_TABLE.to_le_bytes());
+        put(sections + 56, &word(symbols));
+        put(sections + 60, &word(2 * ELF_SYMBOL_SIZE));
+        put(sections + 64, &word(2)); // link to .strtab
+        put(sections + 84, &word(3)); // string table
+        put(sections + 96, &word(strings));
+        put(sections + 100, &word(symbol.len() + 2));
+
+        elf
+    }
+
+    #[test]
+    fn should_find_the_code_of_an_elf_file() {
+        let code = crate::rv32asm! { addi x1, x0, 5; ecall };
+        let program = elf(0x1000, &code, "main", 0x1000);
+
+        let bytes: Vec<Byte> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert_eq!(elf_code(&program).unwrap(), [(0x1000, &bytes[..])]);
+    }
+
+    #[test]
+    fn should_label_disassembly_with_the_elf_symbols() {
+        let mut machine = Machine::with_memory_size(8192);
+
+        let program = elf(0x1000, &crate::rv32asm! { jal x1, 4; ecall }, "main", 0x1004);
+
+        let (entry, symbols) = machine.load_elf_with_symbols(&program).unwrap();
+        let jal = RV32I.decode(machine.bus().read_word(entry)).unwrap();
+
+        assert_eq!(symbols, SymbolTable::from([(0x1004, "main".to_string())]));
+        assert_eq!(disassemble_at(&jal, entry, &symbols), "jal x1, main");
+    }
+}
@@ -14,20 +14,1124 @@
 // limitations under the Licence.
 //
 
-use crate::hart::SimpleRV32IHart;
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::architecture::{Ext, RV32Instruction, Xlen};
+use crate::bitfield::Opcode7Table;
+use crate::bus::{Bus, Device, Region};
+use crate::disassembler::SymbolTable;
+use crate::hart::{CustomHandler, Hart, HartState, SimpleRV32IHart, DEFAULT_RESET_VECTOR};
+use crate::instruction::instruction_length;
+use crate::memory::{InstructionLength, Memory, Word};
+use crate::profile::ProfileReport;
+use crate::register::{RegisterValue64, X0WritePolicy};
+use crate::syscall::{
+    Newlib, Syscall, SyscallHandler, SyscallOutcome, SYSCALL_ARGUMENTS, SYSCALL_NUMBER, SYSCALL_RESULT,
+};
+use crate::trace::{TraceEvent, Tracer};
+use crate::trap::TrapCause;
 
 // Init memory as 128MB
 pub const DRAM_SIZE: usize = 1024 * 1024 * 128;
 
+// Frames followed by `Machine::backtrace` at most, in case the frame chain loops
+pub const MAX_BACKTRACE_DEPTH: usize = 64;
+
+const RETURN_ADDRESS: usize = 1; // ra
+const STACK_POINTER: usize = 2; // sp
+const FRAME_POINTER: usize = 8; // s0 / fp
+const FRAME_SLOT: Word = InstructionLength::Word.bytes() as Word;
+const TOHOST_SYMBOL: &str = "tohost";
+const FROMHOST_SYMBOL: &str = "fromhost";
+const HOST_SYSCALL_ARGUMENTS: usize = 6;
+
+// Harts share the memory through their bus; see `Bus` for the memory model.
 // TODO implement a *true* shareable memory between different processes
 pub struct Machine {
-    pub hart: SimpleRV32IHart,
+    harts: Vec<SimpleRV32IHart>,
+    bus: Arc<Mutex<Bus>>,
+    extensions: HashSet<Ext>,
+    syscall_handler: Box<dyn SyscallHandler>,
+    program_end: Word,
+    host: Option<HostInterface>,
+    instruction_budget: Option<u64>, // instructions left to retire, across runs
+}
+
+// Composes a machine step by step, e.g.:
+//
+//     Machine::builder().memory(4096).extension(Ext::M).device(0x1000..0x1008, Console::stdout()).build()
+//
+// Unless told otherwise, it has DRAM_SIZE bytes of RAM at address 0, a single hart, and no extensions
+// (RV32I only).
+pub struct MachineBuilder {
+    memory_size: usize,
+    ram_base: Word,
+    harts: usize,
+    reset_vector: RegisterValue64,
+    devices: Vec<(Range<Word>, Box<dyn Device>)>,
+    extensions: HashSet<Ext>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RunResult {
+    pub retired: u64,
+    pub stop: StopReason,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Trap(TrapCause),
+    Exit(i32),
+    StepLimit,
+    WaitForInterrupt, // a hart executed WFI; running again resumes past it
+    X0Write,          // a hart wrote to x0 under `X0WritePolicy::Trap`, its PC left at the instruction
+    BudgetExhausted,  // see `Machine::set_instruction_budget`
+}
+
+// The words through which a program talks to the machine, as on the HTIF of Spike (the riscv-tests
+// and the proxy kernel rely on it): storing an odd value to tohost exits with code `value >> 1`, and
+// an even one is the address of a syscall (see `Machine::host_syscall`). Once handled, the machine
+// clears tohost and writes 1 to fromhost.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HostInterface {
+    pub tohost: Word,
+    pub fromhost: Option<Word>,
+}
+
+impl HostInterface {
+    // The interface of a program naming its tohost (and fromhost) words
+    pub fn find(symbols: &SymbolTable) -> Option<Self> {
+        let address = |symbol: &str| symbols.iter().find_map(|(&address, name)| (name == symbol).then_some(address));
+
+        Some(Self {
+            tohost: address(TOHOST_SYMBOL)?,
+            fromhost: address(FROMHOST_SYMBOL),
+        })
+    }
+}
+
+// Memory as the harts see it, through their bus (see `Machine::memory`)
+struct MemoryView<'a>(MutexGuard<'a, Bus>);
+
+impl Deref for MemoryView<'_> {
+    type Target = dyn Memory;
+
+    fn deref(&self) -> &Self::Target { &*self.0 }
+}
+
+impl DerefMut for MemoryView<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut *self.0 }
 }
 
 impl Machine {
-    pub fn new() -> Self {
+    pub fn new() -> Self { Self::with_memory_size(DRAM_SIZE) }
+
+    pub fn with_memory_size(bytes: usize) -> Self { Self::with_harts(1, bytes) }
+
+    // Harts have their own registers, and share the memory; they are identified (mhartid) by their
+    // index, from 0 to `count - 1`.
+    pub fn with_harts(count: usize, bytes: usize) -> Self {
+        Self::with_bus(count, Bus::with_ram(bytes), bytes, HashSet::from(Ext::ALL))
+    }
+
+    pub fn builder() -> MachineBuilder { MachineBuilder::default() }
+
+    // The stack pointer of every hart is reset to `ram_end`
+    fn with_bus(count: usize, bus: Bus, ram_end: usize, extensions: HashSet<Ext>) -> Self {
+        assert!(count > 0, "A machine needs at least one hart");
+
+        let bus = Arc::new(Mutex::new(bus));
+        let harts = (0..count)
+            .map(|hart_id| {
+                let mut hart = SimpleRV32IHart::with_shared_bus(hart_id, bus.clone(), ram_end);
+                hart.set_extensions(extensions.clone());
+                hart
+            })
+            .collect();
+
         Self {
-            hart: SimpleRV32IHart::new(DRAM_SIZE),
+            harts,
+            bus,
+            extensions,
+            syscall_handler: Box::new(Newlib::stdio()),
+            program_end: 0,
+            host: None,
+            instruction_budget: None,
+        }
+    }
+
+    pub fn harts(&self) -> &[SimpleRV32IHart] { &self.harts }
+
+    pub fn hart(&self, id: usize) -> &SimpleRV32IHart { &self.harts[id] }
+
+    pub fn hart_mut(&mut self, id: usize) -> &mut SimpleRV32IHart { &mut self.harts[id] }
+
+    pub fn bus(&self) -> MutexGuard<'_, Bus> { self.bus.lock().unwrap_or_else(PoisonError::into_inner) }
+
+    // The address space of the harts, RAM and devices alike, e.g. for tools dumping the guest memory.
+    // It stays locked, for the harts of other threads, as long as it is borrowed.
+    pub fn memory(&self) -> impl Deref<Target = dyn Memory> + '_ { MemoryView(self.bus()) }
+
+    pub fn memory_mut(&mut self) -> impl DerefMut<Target = dyn Memory> + '_ { MemoryView(self.bus()) }
+
+    // Every hart has the same extensions enabled
+    pub fn extensions(&self) -> &HashSet<Ext> { &self.extensions }
+
+    // ECALLs stopping a hart are handled by the machine (see `run`), by default as the newlib syscalls
+    pub fn set_syscall_handler(&mut self, handler: impl SyscallHandler + 'static) {
+        self.syscall_handler = Box::new(handler);
+    }
+
+    pub fn host_interface(&self) -> Option<HostInterface> { self.host }
+
+    // Programs loaded from ELF files get theirs from their symbols (see `HostInterface::find`)
+    pub fn set_host_interface(&mut self, host: Option<HostInterface>) { self.host = host }
+
+    // Instructions the harts may retire from now on, all runs combined: once they did, runs stop at once
+    // (see `StopReason::BudgetExhausted`), until the budget is set again. There is none by default.
+    pub fn set_instruction_budget(&mut self, budget: u64) { self.instruction_budget = Some(budget) }
+
+    pub fn instruction_budget(&self) -> Option<u64> { self.instruction_budget }
+
+    // Grows the program loaded so far, which ends (and its heap starts) at `end`
+    pub(crate) fn extend_program(&mut self, end: Word) { self.program_end = self.program_end.max(end) }
+
+    // The same hook observes every hart, whichever thread runs it
+    pub fn set_trace_hook(&mut self, hook: impl FnMut(&TraceEvent) + Send + 'static) {
+        let hook = Arc::new(Mutex::new(hook));
+
+        for hart in &mut self.harts {
+            let hook = hook.clone();
+            hart.set_tracer(Some(Tracer::new(move |event| {
+                (&mut *hook.lock().unwrap_or_else(PoisonError::into_inner))(event)
+            })));
+        }
+    }
+
+    // The same handler executes the instructions of `opcode` (one of `CUSTOM_OPCODES`) on every hart,
+    // e.g. to experiment with new instructions
+    pub fn set_custom_handler(
+        &mut self,
+        opcode: Opcode7Table,
+        handler: impl FnMut(&mut HartState<'_>, Word) -> Result<(), TrapCause> + Send + 'static,
+    ) {
+        let handler = CustomHandler::new(handler);
+        self.harts
+            .iter_mut()
+            .for_each(|hart| hart.set_custom_handler(opcode, Some(handler.clone())));
+    }
+
+    // Sets the reset vector of every hart, and resets them all so they start from it
+    pub fn set_reset_vector(&mut self, reset_vector: RegisterValue64) {
+        for hart in &mut self.harts {
+            hart.set_reset_vector(reset_vector);
+            hart.reset();
+        }
+    }
+
+    pub fn set_aligned_access_required(&mut self, required: bool) {
+        self.harts.iter_mut().for_each(|hart| hart.set_aligned_access_required(required))
+    }
+
+    pub fn set_xlen(&mut self, xlen: Xlen) { self.harts.iter_mut().for_each(|hart| hart.set_xlen(xlen)) }
+
+    pub fn set_x0_watch(&mut self, enabled: bool) { self.harts.iter_mut().for_each(|hart| hart.set_x0_watch(enabled)) }
+
+    pub fn set_x0_write_policy(&mut self, policy: X0WritePolicy) {
+        self.harts.iter_mut().for_each(|hart| hart.registers_mut().set_x0_write_policy(policy))
+    }
+
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.harts.iter_mut().for_each(|hart| hart.set_decode_cache(enabled))
+    }
+
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.harts.iter_mut().for_each(|hart| hart.set_profiling(enabled))
+    }
+
+    // What all harts gathered since profiling was enabled, added up; empty when it is not
+    pub fn profile_report(&self) -> ProfileReport {
+        let mut report = ProfileReport::default();
+        for profile in self.harts.iter().filter_map(SimpleRV32IHart::profile_report) {
+            report.merge(profile);
+        }
+        report
+    }
+
+    pub fn reset(&mut self) { self.harts.iter_mut().for_each(SimpleRV32IHart::reset) }
+
+    pub fn step_hart(&mut self, id: usize) -> Result<(), TrapCause> { self.harts[id].step() }
+
+    // Register access by number (x0 to x31) on the first hart, e.g. to pass arguments in a0 and a1
+    // before running; see `hart_mut` for the other harts. Writing x0 is refused, being most likely a
+    // mistake.
+    pub fn read_register(&self, index: usize) -> RegisterValue64 { self.harts[0].registers().read(index) }
+
+    pub fn write_register(&mut self, index: usize, value: RegisterValue64) {
+        assert_ne!(index, 0, "x0 is hardwired to zero");
+        self.harts[0].registers_mut().write(index, value);
+    }
+
+    // Instructions retired so far, all harts combined. Unlike the step count of `run`, it ignores the
+    // steps spent taking traps, and is never reset: it identifies a point of a deterministic
+    // execution, e.g. to replay it up to there.
+    pub fn icount(&self) -> u64 { self.harts.iter().map(SimpleRV32IHart::icount).sum() }
+
+    // Steps the harts in turns (round-robin) until one of them traps (EBREAK and unhandled ECALLs
+    // included), exits through a syscall or `max_steps` instructions are retired, all harts combined.
+    // A trapping instruction, handled syscalls included, does not count as retired. The machine clock
+    // (see `Bus::tick`) ticks once per round.
+    pub fn run(&mut self, max_steps: u64) -> RunResult { self.run_until(|_, retired| retired >= max_steps) }
+
+    // Like `run`, but stops once `icount` reaches `icount`
+    pub fn run_until_icount(&mut self, icount: u64) -> RunResult {
+        self.run_until(|machine, _| machine.icount() >= icount)
+    }
+
+    // Executes the instruction at the PC of the first hart, as `run(1)` does, unless it is a call (a
+    // JAL or JALR linking into ra): the called function then runs until the PC reaches the instruction
+    // after the call, as on a temporary breakpoint, stopping as on a step limit. A recursive call
+    // returning there first, a trap or an exit stop it as well.
+    pub fn step_over(&mut self) -> RunResult {
+        let pc = self.harts[0].registers().pc;
+        let link = match self.harts[0].peek_instruction(pc) {
+            Some(RV32Instruction::UnconditionalJump(j_type)) => j_type.rd().value() as usize,
+            Some(RV32Instruction::JumpAndLinkRegister(i_type)) => i_type.rd().value() as usize,
+            _ => 0,
+        };
+
+        if link != RETURN_ADDRESS {
+            return self.run(1);
+        }
+
+        let length = instruction_length(self.bus().read_half_word(pc as Word)).bytes();
+        let breakpoint = (pc as Word).wrapping_add(length as Word);
+        self.run_until(|machine, _| machine.hart(0).registers().pc as Word == breakpoint)
+    }
+
+    // Runs the function the first hart is in until it returns, i.e. until the PC reaches the return
+    // address in ra, stopping as `step_over` does. As a heuristic, it relies on ra not being
+    // overwritten yet: calls made by the function must not have started.
+    pub fn step_out(&mut self) -> RunResult {
+        let return_address = self.read_register(RETURN_ADDRESS) as Word;
+        self.run_until(|machine, _| machine.hart(0).registers().pc as Word == return_address)
+    }
+
+    // Best-effort return addresses of the frames on the stack of hart `id`, innermost first. It follows
+    // the chain of frame pointers laid out by GCC and Clang when frames are kept: fp (s0) holds the sp on
+    // entry to the function, which saved ra at fp - 4 and the fp of its caller at fp - 8.
+    //
+    // Code built without frame pointers (-fomit-frame-pointer, implied by optimization) yields a short
+    // or meaningless list, and a leaf function that saves neither register is missing from it: its
+    // return address is still in ra. The walk stops at a null or misaligned fp, an fp not above the
+    // previous one (stacks grow down), a saved slot outside of memory, a null ra, or after
+    // MAX_BACKTRACE_DEPTH frames.
+    pub fn backtrace(&self, id: usize) -> Vec<u64> {
+        let registers = self.harts[id].registers();
+        let bus = self.bus();
+
+        let mut floor = registers.read(STACK_POINTER) as Word;
+        let mut fp = registers.read(FRAME_POINTER) as Word;
+        let mut addresses = Vec::new();
+
+        while fp != 0 && fp % FRAME_SLOT == 0 && fp > floor && addresses.len() < MAX_BACKTRACE_DEPTH {
+            let slot = |offset: Word| fp.checked_sub(offset).and_then(|slot| bus.load(slot, InstructionLength::Word));
+            let (Some(ra), Some(caller_fp)) = (slot(FRAME_SLOT), slot(2 * FRAME_SLOT)) else {
+                break;
+            };
+            if ra == 0 {
+                break;
+            }
+
+            addresses.push(ra);
+            floor = fp;
+            fp = caller_fp as Word;
+        }
+
+        addresses
+    }
+
+    pub(crate) fn run_until(&mut self, done: impl Fn(&Self, u64) -> bool) -> RunResult {
+        let mut retired = 0;
+
+        for id in (0..self.harts.len()).cycle() {
+            if done(self, retired) {
+                break;
+            }
+
+            if self.instruction_budget == Some(0) {
+                return RunResult {
+                    retired,
+                    stop: StopReason::BudgetExhausted,
+                };
+            }
+
+            if id == 0 {
+                self.bus().tick();
+            }
+
+            let stop = match self.step_hart(id) {
+                Ok(()) => {
+                    retired += 1;
+                    if let Some(budget) = &mut self.instruction_budget {
+                        *budget -= 1;
+                    }
+
+                    match self.poll_host(id) {
+                        Some(stop) => stop,
+                        None if self.harts[id].waiting_for_interrupt() => StopReason::WaitForInterrupt,
+                        None => continue,
+                    }
+                }
+                Err(_) if self.harts[id].x0_write_trapped() => StopReason::X0Write,
+                Err(TrapCause::EnvironmentCallFromMMode) => match self.syscall(id) {
+                    SyscallOutcome::Return(value) => {
+                        self.harts[id].registers_mut().write(SYSCALL_RESULT, value);
+                        continue;
+                    }
+                    SyscallOutcome::Exit(code) => StopReason::Exit(code),
+                    SyscallOutcome::Unhandled => StopReason::Trap(TrapCause::EnvironmentCallFromMMode),
+                },
+                Err(cause) => StopReason::Trap(cause),
+            };
+
+            return RunResult { retired, stop };
+        }
+
+        RunResult {
+            retired,
+            stop: StopReason::StepLimit,
+        }
+    }
+
+    fn syscall(&mut self, id: usize) -> SyscallOutcome {
+        let registers = self.harts[id].registers();
+        let number = registers.read(SYSCALL_NUMBER);
+        let args = std::array::from_fn(|index| registers.read(SYSCALL_ARGUMENTS + index));
+
+        let mut bus = self.bus.lock().unwrap_or_else(PoisonError::into_inner);
+        self.syscall_handler.handle(Syscall {
+            hart_id: id,
+            number,
+            args,
+            bus: &mut bus,
+            program_end: self.program_end,
+        })
+    }
+
+    // Checks tohost after each instruction retired by hart `id`, stopping the run on an exit. Syscalls
+    // made through it that the handler does not know stop it as an unhandled ECALL would.
+    fn poll_host(&mut self, id: usize) -> Option<StopReason> {
+        let host = self.host?;
+        let value = self.bus().load(host.tohost, InstructionLength::Word).filter(|&value| value != 0)?;
+
+        if value & 1 == 1 {
+            return Some(StopReason::Exit((value >> 1) as i32));
+        }
+
+        match self.host_syscall(id, value as Word) {
+            SyscallOutcome::Return(_) => {
+                let mut bus = self.bus();
+                bus.store(host.tohost, InstructionLength::Word, 0);
+                if let Some(fromhost) = host.fromhost {
+                    bus.store(fromhost, InstructionLength::Word, 1);
+                }
+                None
+            }
+            SyscallOutcome::Exit(code) => Some(StopReason::Exit(code)),
+            SyscallOutcome::Unhandled => Some(StopReason::Trap(TrapCause::EnvironmentCallFromMMode)),
+        }
+    }
+
+    // A syscall made through tohost points to 64-bit words holding its number then its arguments; the
+    // result overwrites the number.
+    fn host_syscall(&mut self, id: usize, address: Word) -> SyscallOutcome {
+        let mut bus = self.bus.lock().unwrap_or_else(PoisonError::into_inner);
+        let word = |bus: &Bus, index: usize| bus.read_double_word(address.wrapping_add(8 * index as Word));
+        let number = word(&bus, 0);
+        let args: [RegisterValue64; HOST_SYSCALL_ARGUMENTS] = std::array::from_fn(|index| word(&bus, index + 1));
+
+        let outcome = self.syscall_handler.handle(Syscall {
+            hart_id: id,
+            number,
+            args,
+            bus: &mut bus,
+            program_end: self.program_end,
+        });
+
+        if let SyscallOutcome::Return(result) = outcome {
+            bus.write_double_word(address, &result);
+        }
+        outcome
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self { Self::new() }
+}
+
+impl MachineBuilder {
+    // Bytes of RAM, mapped at `ram_base`
+    pub fn memory(mut self, bytes: usize) -> Self {
+        self.memory_size = bytes;
+        self
+    }
+
+    // Where RAM starts in the address space, e.g. 0x8000_0000 for programs linked there
+    pub fn ram_base(mut self, base: Word) -> Self {
+        self.ram_base = base;
+        self
+    }
+
+    pub fn harts(mut self, count: usize) -> Self {
+        self.harts = count;
+        self
+    }
+
+    pub fn reset_vector(mut self, reset_vector: RegisterValue64) -> Self {
+        self.reset_vector = reset_vector;
+        self
+    }
+
+    // Maps a memory-mapped device; like RAM, it must not overlap any other region (see `Bus::map`)
+    pub fn device(mut self, range: Range<Word>, device: impl Device + 'static) -> Self {
+        self.devices.push((range, Box::new(device)));
+        self
+    }
+
+    pub fn extension(mut self, extension: Ext) -> Self {
+        self.extensions.insert(extension);
+        self
+    }
+
+    pub fn build(self) -> Machine {
+        let mut bus = Bus::with_ram_at(self.ram_base, self.memory_size);
+        for (range, device) in self.devices {
+            bus.map(range, Region::Device(device));
+        }
+
+        let ram_end = self.ram_base as usize + self.memory_size;
+        let mut machine = Machine::with_bus(self.harts, bus, ram_end, self.extensions);
+        machine.set_reset_vector(self.reset_vector);
+        machine
+    }
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self {
+            memory_size: DRAM_SIZE,
+            ram_base: 0,
+            harts: 1,
+            reset_vector: DEFAULT_RESET_VECTOR,
+            devices: Vec::new(),
+            extensions: HashSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csr::{MIE_MTIE, MSTATUS_MIE, MTVEC_VECTORED};
+    use crate::device::{Clint, Console, SharedBuffer, CLINT_MTIME, CLINT_MTIMECMP, CLINT_SIZE};
+    use crate::encodings::{addi, bne, ecall};
+    use crate::memory::{Byte, InstructionLength, Memory};
+    use crate::profile::BranchStats;
+    use crate::syscall::SYS_WRITE;
+    use crate::trap::Interrupt;
+
+    // addi x1, x0, 5
+    // addi x1, x1, -1
+    // ecall
+    const PROGRAM: [u8; 12] = [
+        0x93, 0x00, 0x50, 0x00, //
+        0x93, 0x80, 0xF0, 0xFF, //
+        0x73, 0x00, 0x00, 0x00, //
+    ];
+
+    #[test]
+    fn should_run_until_ecall() {
+        let mut machine = Machine::with_memory_size(4096);
+        machine.load_binary(&PROGRAM, 0).unwrap();
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.hart(0).registers().read(1), 4 as RegisterValue64);
+    }
+
+    #[test]
+    fn should_exit_with_the_code_in_a0() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = bytes(&crate::rv32asm! { addi x17, x0, 93; addi x10, x0, 42; ecall });
+        machine.load_binary(&program, 0).unwrap();
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(result.stop, StopReason::Exit(42));
+    }
+
+    #[test]
+    fn should_stop_at_the_step_limit() {
+        let mut machine = Machine::with_memory_size(4096);
+        machine.load_binary(&PROGRAM, 0).unwrap();
+
+        let result = machine.run(1);
+
+        assert_eq!(result.retired, 1);
+        assert_eq!(result.stop, StopReason::StepLimit);
+        assert_eq!(machine.hart(0).registers().pc, 4);
+    }
+
+    #[test]
+    fn should_stop_with_an_error_on_a_write_to_x0_under_the_trap_policy() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = bytes(&crate::rv32asm! { addi x1, x0, 5; add x0, x1, x1; ecall });
+        machine.load_binary(&program, 0).unwrap();
+        machine.set_x0_write_policy(X0WritePolicy::Trap);
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 1);
+        assert_eq!(result.stop, StopReason::X0Write);
+        assert_eq!(machine.hart(0).registers().pc, 4);
+    }
+
+    #[test]
+    fn should_count_how_often_a_loop_branch_is_taken() {
+        let iterations = 5;
+        let mut machine = Machine::with_memory_size(4096);
+        let program = bytes(&[addi(1, 0, iterations), addi(1, 1, -1), bne(1, 0, -4), ecall()]);
+        machine.load_binary(&program, 0).unwrap();
+        machine.set_profiling(true);
+
+        machine.run(u64::MAX);
+
+        let report = machine.profile_report();
+        assert_eq!(report.branches.len(), 1);
+        assert_eq!(report.branches[&8], BranchStats {
+            taken: iterations as u64 - 1,
+            not_taken: 1,
+        });
+    }
+
+    #[test]
+    fn should_trace_retired_instructions() {
+        let retired = Arc::new(Mutex::new(Vec::new()));
+        let sink = retired.clone();
+
+        let mut machine = Machine::with_memory_size(4096);
+        machine.load_binary(&PROGRAM, 0).unwrap();
+        machine.set_trace_hook(move |event| {
+            if let TraceEvent::Retired { pc, .. } = event {
+                sink.lock().unwrap().push(*pc)
+            }
+        });
+
+        machine.run(u64::MAX);
+
+        assert_eq!(*retired.lock().unwrap(), vec![0, 4]);
+    }
+
+    #[test]
+    fn should_move_across_threads_with_a_trace_hook() {
+        let mut machine = Machine::with_memory_size(4096);
+        machine.load_binary(&PROGRAM, 0).unwrap();
+        machine.set_trace_hook(|_| {});
+
+        let result = std::thread::spawn(move || machine.run(u64::MAX)).join().unwrap();
+
+        assert_eq!(result.retired, 2);
+    }
+
+    #[test]
+    fn should_execute_custom_instructions_by_their_handler() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = [
+            0x8B, 0x02, 0x00, 0x00, // custom-0, rd = x5
+            0x73, 0x00, 0x00, 0x00, // ecall
+        ];
+        machine.load_binary(&program, 0).unwrap();
+        machine.set_custom_handler(Opcode7Table::Custom0, |hart, word| {
+            let rd = (word >> 7 & 0x1F) as usize;
+            let value = hart.registers.read(rd) + 1;
+            hart.registers.write(rd, value);
+            Ok(())
+        });
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 1);
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.hart(0).registers().read(5), 1);
+    }
+
+    #[test]
+    fn should_give_custom_handlers_the_registers_and_memory_of_their_hart() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = [
+            0x0050_0093, // addi x1, x0, 5
+            0x0000_81AB, // custom-1, rd = x3, rs1 = x1
+            0x0000_0073, // ecall
+        ];
+        machine.load_binary(&bytes(&program), 0).unwrap();
+        machine.memory_mut().write_word(0x100, &37);
+        machine.set_custom_handler(Opcode7Table::Custom1, |hart, word| {
+            let (rd, rs1) = ((word >> 7 & 0x1F) as usize, (word >> 15 & 0x1F) as usize);
+            let value = hart.registers.read(rs1) + hart.memory.read_word(0x100) as RegisterValue64;
+            hart.registers.write(rd, value);
+            Ok(())
+        });
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(machine.read_register(3), 42);
+    }
+
+    #[test]
+    fn should_set_sp_to_the_top_of_the_configured_memory() {
+        let machine = Machine::with_memory_size(4096);
+
+        assert_eq!(machine.hart(0).registers().read(2), 4096);
+    }
+
+    #[test]
+    fn should_run_harts_in_turns_over_the_same_memory() {
+        // addi x5, x0, 1
+        // sw x5, 0x100(x0)
+        // ecall
+        const SHARED_PROGRAM: [u8; 12] = [
+            0x93, 0x02, 0x10, 0x00, //
+            0x23, 0x20, 0x50, 0x10, //
+            0x73, 0x00, 0x00, 0x00, //
+        ];
+
+        let mut machine = Machine::with_harts(2, 4096);
+        machine.load_binary(&SHARED_PROGRAM, 0).unwrap();
+        machine.hart_mut(1).registers_mut().write(6, 42);
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 4);
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.hart(0).registers().read(5), 1);
+        assert_eq!(machine.hart(0).registers().read(6), 0);
+        assert_eq!(machine.hart(1).registers().read(5), 1);
+        assert_eq!(machine.hart(1).registers().read(6), 42);
+        assert_eq!(machine.bus().read_word(0x100), 1);
+    }
+
+    #[test]
+    fn should_step_a_single_hart() {
+        let mut machine = Machine::with_harts(2, 4096);
+        machine.load_binary(&PROGRAM, 0).unwrap();
+
+        machine.step_hart(1).unwrap();
+
+        assert_eq!(machine.hart(0).registers().read(1), 0);
+        assert_eq!(machine.hart(1).registers().read(1), 5);
+        assert_eq!(machine.hart(1).hart_id(), 1);
+    }
+
+    #[test]
+    fn should_walk_the_frame_pointer_chain_back_to_the_outermost_frame() {
+        let mut machine = Machine::with_memory_size(4096);
+        let registers = machine.hart_mut(0).registers_mut();
+        registers.write(STACK_POINTER, 0xF00);
+        registers.write(FRAME_POINTER, 0xF10);
+
+        let mut bus = machine.bus();
+        bus.write_word(0xF0C, &0x124); // ra of the innermost frame
+        bus.write_word(0xF08, &0xF40); // fp of its caller
+        bus.write_word(0xF3C, &0x248);
+        bus.write_word(0xF38, &0); // outermost frame
+        drop(bus);
+
+        assert_eq!(machine.backtrace(0), vec![0x124, 0x248]);
+    }
+
+    #[test]
+    fn should_not_backtrace_without_a_frame_pointer() {
+        let machine = Machine::with_memory_size(4096);
+
+        assert!(machine.backtrace(0).is_empty());
+    }
+
+    #[test]
+    fn should_start_from_the_reset_vector() {
+        let mut machine = Machine::with_memory_size(8192);
+        machine.set_reset_vector(0x1000);
+        machine.bus().region_slice_mut(0x1000..0x100C).unwrap().copy_from_slice(&PROGRAM);
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(machine.hart(0).registers().read(1), 4);
+        assert_eq!(machine.hart(0).registers().pc, 0x100C);
+    }
+
+    #[test]
+    fn should_reset_to_the_loaded_program() {
+        let mut machine = Machine::with_memory_size(8192);
+        machine.load_binary(&PROGRAM, 0x1000).unwrap();
+        machine.run(u64::MAX);
+
+        machine.reset();
+
+        assert_eq!(machine.hart(0).reset_vector(), 0x1000);
+        assert_eq!(machine.hart(0).registers().pc, 0x1000);
+        assert_eq!(machine.hart(0).registers().read(1), 0);
+        assert_eq!(machine.hart(0).registers().read(2), 8192);
+    }
+
+    const FLAG: Word = 0x100;
+
+    // Hart 0 runs `lr.w x5, (x10)` then `sc.w x6, x7, (x10)`, while hart 1 runs `hart_1_chomp`
+    fn lr_sc_machine(hart_1_chomp: Word) -> Machine {
+        let mut machine = Machine::with_harts(2, 4096);
+        {
+            let mut bus = machine.bus();
+            bus.write_word(0x00, &0x1005_22AF);
+            bus.write_word(0x04, &0x1875_232F);
+            bus.write_word(0x40, &hart_1_chomp);
         }
+
+        for id in 0..2 {
+            machine.hart_mut(id).registers_mut().write(10, FLAG as RegisterValue64);
+        }
+        machine.hart_mut(0).registers_mut().write(7, 1);
+        machine.hart_mut(1).registers_mut().write(9, 7);
+        machine.hart_mut(1).registers_mut().pc = 0x40;
+
+        machine
+    }
+
+    #[test]
+    fn should_let_a_hart_observe_a_flag_set_through_sc() {
+        let mut machine = lr_sc_machine(0x1005_242F); // lr.w x8, (x10)
+
+        machine.step_hart(0).unwrap();
+        machine.step_hart(0).unwrap();
+        machine.step_hart(1).unwrap();
+
+        assert_eq!(machine.hart(0).registers().read(6), 0); // SC succeeded
+        assert_eq!(machine.hart(1).registers().read(8), 1);
+    }
+
+    #[test]
+    fn should_fail_sc_after_another_hart_stored_to_the_reserved_word() {
+        let mut machine = lr_sc_machine(0x0095_2023); // sw x9, 0(x10)
+
+        machine.step_hart(0).unwrap();
+        machine.step_hart(1).unwrap();
+        machine.step_hart(0).unwrap();
+
+        assert_eq!(machine.hart(0).registers().read(6), 1); // SC failed
+        assert_eq!(machine.bus().read_word(FLAG), 7);
+    }
+
+    #[test]
+    fn should_run_an_add_on_registers_set_beforehand() {
+        let mut machine = Machine::with_memory_size(4096);
+        machine.load_binary(&bytes(&crate::rv32asm! { add x10, x10, x11 }), 0).unwrap();
+        machine.write_register(10, 40);
+        machine.write_register(11, 2);
+
+        machine.run(1);
+
+        assert_eq!(machine.read_register(10), 42);
+        assert_eq!(machine.read_register(11), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "x0 is hardwired to zero")]
+    fn should_refuse_to_write_x0() { Machine::with_memory_size(4096).write_register(0, 1) }
+
+    #[test]
+    fn should_step_over_a_call_to_the_instruction_after_it() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! {
+            jal x1, 12;
+            addi x2, x0, 7;
+            ecall;
+            addi x3, x0, 1;
+            addi x3, x3, 1;
+            jalr x0, 0(x1)
+        };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+
+        let call = machine.step_over();
+        let next = machine.step_over();
+
+        assert_eq!(call, RunResult {
+            retired: 4,
+            stop: StopReason::StepLimit,
+        });
+        assert_eq!(next.retired, 1);
+        assert_eq!(machine.hart(0).registers().pc, 8);
+        assert_eq!(machine.read_register(3), 2);
+        assert_eq!(machine.read_register(2), 7);
+    }
+
+    #[test]
+    fn should_step_out_of_a_subroutine_to_its_return_site() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! {
+            jal x1, 12;
+            addi x2, x0, 7;
+            ecall;
+            addi x3, x0, 1;
+            addi x3, x3, 1;
+            jalr x0, 0(x1)
+        };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+        machine.run(2); // the call, and the first instruction of the subroutine
+
+        let result = machine.step_out();
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(result.stop, StopReason::StepLimit);
+        assert_eq!(machine.hart(0).registers().pc, 4);
+        assert_eq!(machine.read_register(3), 2);
+    }
+
+    #[test]
+    fn should_exit_once_1_is_stored_to_tohost() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x0, 1; sw x1, 0x400(x0); jal x0, 0 };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+        machine.set_host_interface(Some(HostInterface {
+            tohost: 0x400,
+            fromhost: None,
+        }));
+
+        let result = machine.run(100);
+
+        assert_eq!(result.retired, 2);
+        assert_eq!(result.stop, StopReason::Exit(0));
+    }
+
+    #[test]
+    fn should_answer_syscalls_made_through_tohost() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x0, 0x300; sw x1, 0x400(x0); ebreak };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+        machine.set_host_interface(Some(HostInterface {
+            tohost: 0x400,
+            fromhost: Some(0x408),
+        }));
+        machine.bus().write_double_word(0x300, &SYS_WRITE);
+        machine.bus().write_double_word(0x308, &3); // not an open file
+
+        let result = machine.run(100);
+
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::Breakpoint));
+        assert_eq!(machine.bus().read_double_word(0x300), -9i64 as u64); // EBADF
+        assert_eq!(machine.bus().read_word(0x400), 0);
+        assert_eq!(machine.bus().read_word(0x408), 1);
+    }
+
+    #[test]
+    fn should_stop_once_the_instruction_budget_is_spent_and_resume_when_refilled() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x1, 1; jal x0, -4 };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+        machine.set_instruction_budget(5);
+
+        let spent = machine.run(100);
+        machine.set_instruction_budget(4);
+        let refilled = machine.run(100);
+
+        assert_eq!(spent.retired, 5);
+        assert_eq!(spent.stop, StopReason::BudgetExhausted);
+        assert_eq!(refilled.retired, 4);
+        assert_eq!(refilled.stop, StopReason::BudgetExhausted);
+        assert_eq!(machine.instruction_budget(), Some(0));
+        assert_eq!(machine.read_register(1), 5);
+    }
+
+    #[test]
+    fn should_pause_on_wfi_and_resume_past_it() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x0, 1; wfi; addi x1, x1, 1; ecall };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+
+        let paused = machine.run(100);
+        let resumed = machine.run(100);
+
+        assert_eq!(paused.retired, 2);
+        assert_eq!(paused.stop, StopReason::WaitForInterrupt);
+        assert_eq!(resumed.retired, 1);
+        assert_eq!(resumed.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.read_register(1), 2);
+    }
+
+    #[test]
+    fn should_read_the_stores_of_the_harts_through_memory() {
+        let mut machine = Machine::with_memory_size(4096);
+        let program = crate::rv32asm! { addi x1, x0, 42; sw x1, 0x100(x0) };
+        machine.load_binary(&bytes(&program), 0).unwrap();
+
+        machine.run(2);
+        machine.memory_mut().write_half_word(0x104, &0xBEEF);
+
+        assert_eq!(machine.memory().read_word(0x100), 42);
+        assert_eq!(machine.memory().read_word(0x104), 0xBEEF);
+    }
+
+    fn bytes(program: &[Word]) -> Vec<Byte> { program.iter().flat_map(|word| word.to_le_bytes()).collect() }
+
+    #[test]
+    fn should_build_a_machine_printing_a_product_to_its_console() {
+        let console = SharedBuffer::default();
+        let mut machine = Machine::builder()
+            .memory(2048)
+            .reset_vector(0x100)
+            .extension(Ext::M)
+            .device(0x800..0x808, Console::new(console.clone()))
+            .build();
+
+        assert_eq!(machine.hart(0).registers().pc, 0x100);
+        assert!(machine.extensions().contains(&Ext::M));
+
+        let program = bytes(&crate::rv32asm! {
+            addi x1, x0, 6;
+            addi x2, x0, 7;
+            mul x3, x1, x2;
+            addi x4, x0, 2047;
+            sb x3, 1(x4);
+            ecall
+        });
+        machine.load_binary(&program, 0x100).unwrap();
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.hart(0).registers().read(3), 42);
+        assert_eq!(*console.0.lock().unwrap(), b"*");
+    }
+
+    #[test]
+    fn should_trap_on_mul_unless_m_is_enabled() {
+        let program = bytes(&crate::rv32asm! { mul x3, x1, x2 });
+
+        let mut rv32i = Machine::builder().memory(4096).build();
+        rv32i.load_binary(&program, 0).unwrap();
+        let mut rv32im = Machine::builder().memory(4096).extension(Ext::M).build();
+        rv32im.load_binary(&program, 0).unwrap();
+
+        assert_eq!(rv32i.step_hart(0), Err(TrapCause::IllegalInstruction));
+        assert_eq!(rv32im.step_hart(0), Ok(()));
+    }
+
+    #[test]
+    fn should_trap_on_csr_instructions_unless_zicsr_is_enabled() {
+        let program = bytes(&crate::rv32asm! { csrrw x1, 0x340, x2 });
+
+        let mut rv32i = Machine::builder().memory(4096).build();
+        rv32i.load_binary(&program, 0).unwrap();
+        let mut with_zicsr = Machine::builder().memory(4096).extension(Ext::Zicsr).build();
+        with_zicsr.load_binary(&program, 0).unwrap();
+        with_zicsr.hart_mut(0).registers_mut().write(2, 42);
+
+        assert_eq!(rv32i.step_hart(0), Err(TrapCause::IllegalInstruction));
+        assert_eq!(with_zicsr.step_hart(0), Ok(()));
+        assert_eq!(with_zicsr.hart(0).csrs().mscratch, 42);
+    }
+
+    #[test]
+    fn should_run_code_linked_at_the_ram_base() {
+        const RAM_BASE: Word = 0x8000_0000;
+
+        let mut machine = Machine::builder().memory(64 * 1024).ram_base(RAM_BASE).build();
+        let program = bytes(&crate::rv32asm! { addi x1, x0, 5; sw x1, -4(x2); lw x3, -4(x2); ecall });
+        machine.load_binary(&program, RAM_BASE).unwrap();
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.retired, 3);
+        assert_eq!(result.stop, StopReason::Trap(TrapCause::EnvironmentCallFromMMode));
+        assert_eq!(machine.hart(0).registers().read(3), 5);
+        assert_eq!(machine.hart(0).registers().read(2), 0xFFFF_FFFF_8001_0000); // sign-extended
+        assert_eq!(machine.bus().read_word(0x8000_FFFC), 5);
+    }
+
+    #[test]
+    fn should_take_the_timer_interrupt_once_mtime_reaches_mtimecmp() {
+        const CLINT: Word = 0x0200_0000;
+
+        let mut machine = Machine::builder()
+            .memory(4096)
+            .device(CLINT..CLINT + CLINT_SIZE, Clint::new(1))
+            .build();
+        let program = bytes(&crate::rv32asm! { jal x0, 0 });
+        machine.load_binary(&program, 0).unwrap();
+        machine.bus().store(CLINT + CLINT_MTIMECMP, InstructionLength::DoubleWord, 10);
+        machine.bus().store(CLINT + CLINT_MTIME, InstructionLength::DoubleWord, 5);
+
+        let csrs = machine.hart_mut(0).csrs_mut();
+        csrs.mtvec = 0x100;
+        csrs.mie = MIE_MTIE;
+        csrs.mstatus |= MSTATUS_MIE;
+
+        machine.run(4);
+        assert_eq!(machine.hart(0).registers().pc, 0);
+
+        machine.run(1);
+        let csrs = machine.hart(0).csrs();
+        assert_eq!(machine.hart(0).registers().pc, 0x100);
+        assert_eq!(csrs.mcause, Interrupt::MachineTimer.mcause());
+        assert_eq!(csrs.mcause, 0xFFFF_FFFF_8000_0007);
+        assert_eq!(csrs.mstatus & MSTATUS_MIE, 0);
+    }
+
+    #[test]
+    fn should_vector_interrupts_but_not_exceptions() {
+        const CLINT: Word = 0x0200_0000;
+
+        let mut machine = Machine::builder()
+            .memory(4096)
+            .device(CLINT..CLINT + CLINT_SIZE, Clint::new(1))
+            .build();
+        let program = bytes(&crate::rv32asm! { jal x0, 0 });
+        machine.load_binary(&program, 0).unwrap();
+        machine.bus().store(CLINT + CLINT_MTIMECMP, InstructionLength::DoubleWord, 0); // pending at once
+
+        let csrs = machine.hart_mut(0).csrs_mut();
+        csrs.mtvec = 0x100 | MTVEC_VECTORED;
+        csrs.mie = MIE_MTIE;
+        csrs.mstatus |= MSTATUS_MIE;
+
+        machine.run(1);
+        assert_eq!(machine.hart(0).registers().pc, 0x100 + 4 * 7);
+
+        // The vector entry holds no valid instruction (zeroed memory), so it raises an exception
+        machine.run(1);
+        assert_eq!(machine.hart(0).registers().pc, 0x100);
+        assert_eq!(machine.hart(0).csrs().mcause, u8::from(TrapCause::IllegalInstruction) as RegisterValue64);
+    }
+
+    #[test]
+    fn should_run_until_an_icount() {
+        let mut machine = Machine::with_harts(2, 4096);
+        let program = bytes(&crate::rv32asm! { addi x1, x1, 1; jal x0, -4 });
+        machine.load_binary(&program, 0).unwrap();
+
+        machine.run_until_icount(10);
+        assert_eq!(machine.icount(), 10);
+
+        let result = machine.run_until_icount(20);
+        assert_eq!(result.retired, 10);
+        assert_eq!(machine.icount(), 20);
+        assert_eq!(machine.hart(0).icount() + machine.hart(1).icount(), 20);
+
+        machine.reset();
+        assert_eq!(machine.icount(), 20);
     }
 }
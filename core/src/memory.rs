@@ -14,6 +14,12 @@
 // limitations under the Licence.
 //
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use derive_more::{Display, Error};
+
 pub(crate) type Byte = u8;
 pub(crate) type HalfWord = u16;
 pub(crate) type Word = u32;
@@ -28,13 +34,54 @@ pub trait Memory {
     fn write_half_word(&mut self, address: Word, value: &HalfWord);
     fn write_word(&mut self, address: Word, value: &Word);
     fn write_double_word(&mut self, address: Word, value: &DoubleWord);
+
+    // Copies `data` to `address` onwards, after checking once that it fits in the address space
+    fn write_bytes(&mut self, address: Word, data: &[Byte]) -> Result<(), MemoryError> {
+        check_region(address, data.len(), 1 << Word::BITS)?;
+
+        for (offset, byte) in data.iter().enumerate() {
+            self.write_byte(address + offset as Word, byte);
+        }
+
+        Ok(())
+    }
+
+    // Sets `len` bytes from `start` to `value`. Panics if the region goes past the address space.
+    fn fill(&mut self, start: Word, len: usize, value: Byte) {
+        let end = start as u64 + len as u64;
+        assert!(end <= 1 << Word::BITS, "fill of {len} bytes at {start:#x} goes past the address space");
+
+        for offset in 0..len {
+            self.write_byte(start + offset as Word, &value);
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Display, Eq, Error, PartialEq)]
+pub enum MemoryError {
+    #[display("{len} bytes at {address:#010x} are out of bounds")]
+    OutOfBounds { address: Word, len: usize },
+}
+
+// Cloning takes a snapshot of the whole memory
+#[derive(Clone)]
 pub struct VecMemory {
     ram: Vec<Byte>,
 }
 
+// Allocates memory a page at a time, when first written to; untouched pages read as zero. It can
+// thus span the whole address space, at the cost of a lookup per access.
+#[derive(Debug, Default)]
+pub struct SparseMemory {
+    pages: BTreeMap<Word, Box<[Byte; PAGE_SIZE]>>, // by page number
+}
+
+pub const PAGE_SIZE: usize = 4096;
+
+// Bytes shown from each end of a VecMemory by Debug
+const DEBUG_PREVIEW: usize = 8;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum InstructionLength {
     Byte       = 8,
     HalfWord   = 16,
@@ -42,6 +89,15 @@ pub enum InstructionLength {
     DoubleWord = 64,
 }
 
+// Byte order of data accesses (see the MBE bit of mstatus). Instructions are always little-endian,
+// whatever the data endianness.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
 // TODO: I tried using functions and macros to avoid code duplication below, but I couldn't make it work.
 impl Memory for VecMemory {
     fn read_byte(&self, address: Word) -> Byte { self.ram[address as usize] }
@@ -61,27 +117,281 @@ impl Memory for VecMemory {
     fn write_byte(&mut self, address: Word, value: &Byte) { self.ram[address as usize] = *value }
 
     fn write_half_word(&mut self, address: Word, value: &HalfWord) {
-        for i in 0..HalfWord::BITS {
+        for i in 0..HalfWord::BITS / Byte::BITS {
             let actual_value = (value >> (i * Byte::BITS)) as Byte;
             self.write_byte(address + i as Word, &actual_value);
         }
     }
 
     fn write_word(&mut self, address: Word, value: &Word) {
-        for i in 0..Word::BITS {
+        for i in 0..Word::BITS / Byte::BITS {
             let actual_value = (value >> (i * Byte::BITS)) as Byte;
             self.write_byte(address + i as Word, &actual_value);
         }
     }
 
     fn write_double_word(&mut self, address: Word, value: &DoubleWord) {
-        for i in 0..DoubleWord::BITS {
+        for i in 0..DoubleWord::BITS / Byte::BITS {
             let actual_value = (value >> (i * Byte::BITS)) as Byte;
             self.write_byte(address + i as Word, &actual_value);
         }
     }
+
+    fn write_bytes(&mut self, address: Word, data: &[Byte]) -> Result<(), MemoryError> {
+        let start = address as usize;
+        check_region(address, data.len(), self.ram.len() as u64)?;
+
+        self.ram[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn fill(&mut self, start: Word, len: usize, value: Byte) {
+        let start = start as usize;
+        let end = start.checked_add(len).filter(|&end| end <= self.ram.len());
+        let end = end.unwrap_or_else(|| panic!("fill of {len} bytes at {start:#x} goes past the end of memory"));
+
+        self.ram[start..end].fill(value);
+    }
+}
+
+// Shows the size and both ends of the memory, instead of every byte of it
+impl Debug for VecMemory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[Byte]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ");
+
+        let preview = if self.ram.len() <= 2 * DEBUG_PREVIEW {
+            hex(&self.ram)
+        } else {
+            let tail = &self.ram[self.ram.len() - DEBUG_PREVIEW..];
+            format!("{} .. {}", hex(&self.ram[..DEBUG_PREVIEW]), hex(tail))
+        };
+
+        f.debug_struct("VecMemory")
+            .field("size", &self.ram.len())
+            .field("bytes", &format_args!("[{}]", preview))
+            .finish()
+    }
+}
+
+impl Memory for SparseMemory {
+    fn read_byte(&self, address: Word) -> Byte {
+        let (page, offset) = Self::locate(address);
+        self.pages.get(&page).map_or(0, |page| page[offset])
+    }
+
+    fn read_half_word(&self, address: Word) -> HalfWord {
+        let high = self.read_byte(address.wrapping_add(1)) as HalfWord;
+        self.read_byte(address) as HalfWord | (high << Byte::BITS)
+    }
+
+    fn read_word(&self, address: Word) -> Word {
+        let high = self.read_half_word(address.wrapping_add(2)) as Word;
+        self.read_half_word(address) as Word | (high << HalfWord::BITS)
+    }
+
+    fn read_double_word(&self, address: Word) -> DoubleWord {
+        let high = self.read_word(address.wrapping_add(4)) as DoubleWord;
+        self.read_word(address) as DoubleWord | (high << Word::BITS)
+    }
+
+    fn write_byte(&mut self, address: Word, value: &Byte) {
+        let (page, offset) = Self::locate(address);
+        self.pages.entry(page).or_insert_with(|| Box::new([0; PAGE_SIZE]))[offset] = *value;
+    }
+
+    fn write_half_word(&mut self, address: Word, value: &HalfWord) {
+        for (i, byte) in value.to_le_bytes().iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as Word), byte);
+        }
+    }
+
+    fn write_word(&mut self, address: Word, value: &Word) {
+        for (i, byte) in value.to_le_bytes().iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as Word), byte);
+        }
+    }
+
+    fn write_double_word(&mut self, address: Word, value: &DoubleWord) {
+        for (i, byte) in value.to_le_bytes().iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as Word), byte);
+        }
+    }
+}
+
+impl Endianness {
+    // Converts a `size`-wide value between this byte order and the (little-endian) order of memory
+    pub fn convert(self, value: DoubleWord, size: InstructionLength) -> DoubleWord {
+        match self {
+            Endianness::Little => value,
+            Endianness::Big => value.swap_bytes() >> (DoubleWord::BITS - size as u32),
+        }
+    }
+}
+
+impl InstructionLength {
+    pub const fn bytes(&self) -> usize { *self as usize / Byte::BITS as usize }
 }
 
 impl VecMemory {
     pub fn new(size: usize) -> Self { Self { ram: vec![0; size] } }
+
+    // Filled with a pseudo-random pattern, the same for the same seed: code reading memory it never
+    // wrote then misbehaves, reproducibly, instead of getting the zeroes it may have relied on
+    pub fn new_seeded(size: usize, seed: u64) -> Self {
+        let mut state = seed;
+        let ram = (0..size.div_ceil(8))
+            .flat_map(|_| splitmix64(&mut state).to_le_bytes())
+            .take(size)
+            .collect();
+
+        Self { ram }
+    }
+
+    pub fn size(&self) -> usize { self.ram.len() }
+
+    pub fn as_slice(&self) -> &[Byte] { &self.ram }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Byte] { &mut self.ram }
+}
+
+impl SparseMemory {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn allocated_pages(&self) -> usize { self.pages.len() }
+
+    // Page number, and offset within the page
+    fn locate(address: Word) -> (Word, usize) { (address / PAGE_SIZE as Word, address as usize % PAGE_SIZE) }
+}
+
+// The next number of the SplitMix64 generator, whose whole state is a single word
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let z = *state;
+    let z = (z ^ z >> 30).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let z = (z ^ z >> 27).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ z >> 31
+}
+
+// Whether `len` bytes from `address` end at `limit` at most
+fn check_region(address: Word, len: usize, limit: u64) -> Result<(), MemoryError> {
+    match (address as u64).checked_add(len as u64) {
+        Some(end) if end <= limit => Ok(()),
+        _ => Err(MemoryError::OutOfBounds { address, len }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_fill_seeded_memory_with_the_same_pattern_for_the_same_seed() {
+        let memory = VecMemory::new_seeded(1000, 42);
+
+        assert_eq!(memory.size(), 1000);
+        assert_eq!(memory.as_slice(), VecMemory::new_seeded(1000, 42).as_slice());
+        assert_ne!(memory.as_slice(), VecMemory::new_seeded(1000, 43).as_slice());
+        assert_ne!(memory.as_slice(), VecMemory::new(1000).as_slice());
+    }
+
+    #[test]
+    fn should_allocate_only_the_pages_written_to() {
+        let mut memory = SparseMemory::new();
+
+        memory.write_word(0x10, &0xDEAD_BEEF);
+        memory.write_word(0xFFFF_0000, &0x1234_5678);
+
+        assert_eq!(memory.allocated_pages(), 2);
+        assert_eq!(memory.read_word(0x10), 0xDEAD_BEEF);
+        assert_eq!(memory.read_word(0xFFFF_0000), 0x1234_5678);
+    }
+
+    #[test]
+    fn should_read_zero_from_untouched_pages_without_allocating_them() {
+        let mut memory = SparseMemory::new();
+        memory.write_half_word(PAGE_SIZE as Word - 1, &0xABCD); // across two pages
+
+        assert_eq!(memory.read_double_word(0x8000_0000), 0);
+        assert_eq!(memory.read_byte(PAGE_SIZE as Word), 0xAB);
+        assert_eq!(memory.allocated_pages(), 2);
+    }
+
+    #[test]
+    fn should_fill_a_region_leaving_adjacent_bytes_untouched() {
+        let mut memory = VecMemory::new(64);
+        memory.write_byte(0x20, &0x42);
+
+        memory.fill(0x10, 0x10, 0xFF);
+
+        assert_eq!(memory.read_byte(0x0F), 0);
+        assert_eq!(memory.read_word(0x10), 0xFFFF_FFFF);
+        assert_eq!(memory.read_byte(0x1F), 0xFF);
+        assert_eq!(memory.read_byte(0x20), 0x42);
+    }
+
+    #[test]
+    #[should_panic(expected = "goes past the end of memory")]
+    fn should_refuse_to_fill_past_the_end_of_memory() { VecMemory::new(64).fill(0x30, 0x11, 0) }
+
+    #[test]
+    fn should_fill_sparse_memory_byte_by_byte() {
+        let mut memory = SparseMemory::new();
+
+        memory.fill(PAGE_SIZE as Word - 2, 4, 0xFF);
+
+        assert_eq!(memory.read_word(PAGE_SIZE as Word - 2), 0xFFFF_FFFF);
+        assert_eq!(memory.read_byte(PAGE_SIZE as Word + 2), 0);
+        assert_eq!(memory.allocated_pages(), 2);
+    }
+
+    #[test]
+    fn should_write_bytes_in_bulk_as_byte_by_byte() {
+        let data: Vec<Byte> = (0..=255).collect();
+        let mut bytewise = VecMemory::new(1024);
+        let mut bulk = VecMemory::new(1024);
+        let mut sparse = SparseMemory::new();
+
+        data.iter().enumerate().for_each(|(i, byte)| bytewise.write_byte(0x101 + i as Word, byte));
+        bulk.write_bytes(0x101, &data).unwrap();
+        sparse.write_bytes(0x101, &data).unwrap();
+
+        assert_eq!(bulk.as_slice(), bytewise.as_slice());
+        assert_eq!(sparse.read_word(0x1FD), bulk.read_word(0x1FD));
+        assert_eq!(
+            bulk.write_bytes(0x3FF, &[1, 2]),
+            Err(MemoryError::OutOfBounds { address: 0x3FF, len: 2 })
+        );
+        assert_eq!(bulk.read_byte(0x3FF), 0);
+    }
+
+    #[test]
+    fn should_debug_memory_within_bounds_whatever_its_size() {
+        let mut large = VecMemory::new(16 * 1024 * 1024);
+        large.write_word(0, &0x0403_0201);
+
+        let small = format!("{:?}", VecMemory::new(4));
+        let debug = format!("{:?}", large);
+
+        assert_eq!(small, "VecMemory { size: 4, bytes: [00 00 00 00] }");
+        assert!(debug.starts_with("VecMemory { size: 16777216, bytes: [01 02 03 04 00 00 00 00 .. 00"));
+        assert!(debug.len() < 128);
+    }
+
+    #[test]
+    fn should_clone_a_snapshot_of_memory() {
+        let mut memory = VecMemory::new(16);
+        memory.write_byte(0, &1);
+
+        let snapshot = memory.clone();
+        memory.write_byte(0, &2);
+
+        assert_eq!(snapshot.read_byte(0), 1);
+    }
+
+    #[test]
+    fn should_swap_the_bytes_of_big_endian_values_within_their_size() {
+        assert_eq!(Endianness::Big.convert(0x1122_3344, InstructionLength::Word), 0x4433_2211);
+        assert_eq!(Endianness::Big.convert(0xFFFF_FFFF_FFFF_8001, InstructionLength::HalfWord), 0x0180);
+        assert_eq!(Endianness::Little.convert(0x1122_3344, InstructionLength::Word), 0x1122_3344);
+    }
 }
@@ -0,0 +1,52 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::collections::BTreeMap;
+
+use crate::register::RegisterValue64;
+
+// How often a conditional branch went each way
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BranchStats {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+// What harts gather about the guest control flow while profiling (see `Machine::set_profiling`)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProfileReport {
+    pub branches: BTreeMap<RegisterValue64, BranchStats>, // by address of the branch
+}
+
+impl ProfileReport {
+    pub(crate) fn record_branch(&mut self, pc: RegisterValue64, taken: bool) {
+        let stats = self.branches.entry(pc).or_default();
+        if taken {
+            stats.taken += 1;
+        } else {
+            stats.not_taken += 1;
+        }
+    }
+
+    // Adds up the report of another hart, which may have run the same code
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (&pc, stats) in &other.branches {
+            let merged = self.branches.entry(pc).or_default();
+            merged.taken += stats.taken;
+            merged.not_taken += stats.not_taken;
+        }
+    }
+}
@@ -14,10 +14,16 @@
 // limitations under the Licence.
 //
 
+use itertools::Itertools;
 use phf::phf_map;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::iter::Iterator;
 
-pub const REGISTERS_COUNT: usize = 30; // ignore PC and ZERO
+use crate::alu::truncate_xlen;
+
+pub const REGISTERS_COUNT: usize = 32; // x0 to x31, ignore PC
+pub const RV32E_REGISTERS_COUNT: usize = 16; // x0 to x15
 
 pub const PC: RT = RT {
     pos: -2,
@@ -218,6 +224,14 @@ pub const T6: RT = RT {
     saved_by: SavedBy::Caller,
 };
 
+// Registers in encoding order, x0 to x31
+pub const REGISTERS_BY_NUMBER: [&RT; REGISTERS_COUNT] = [
+    &ZERO, &RA, &SP, &GP, &TP, &T0, &T1, &T2, &S0_FP, &S1, &A0, &A1, &A2, &A3, &A4, &A5, &A6, &A7, &S2, &S3, &S4, &S5,
+    &S6, &S7, &S8, &S9, &S10, &S11, &T3, &T4, &T5, &T6,
+];
+
+const DUMP_COLUMNS: usize = 4;
+
 static REGISTERS_BASE_MAP: phf::Map<&'static str, &RT> = phf_map! {
     "pc" => &PC, "zero" => &ZERO, "ra" => &RA, "sp" => &SP, "gp" => &GP, "tp" => &TP,
     "t0" => &T0, "t1" => &T1, "t2" => &T2,
@@ -230,6 +244,7 @@ static REGISTERS_BASE_MAP: phf::Map<&'static str, &RT> = phf_map! {
 };
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct RegisterType {
     pos: i32,
     abi: &'static str,
@@ -241,16 +256,27 @@ pub type RegistersArray64 = [RegisterValue64; REGISTERS_COUNT];
 pub type RegisterValue64 = u64;
 
 #[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub enum SavedBy {
     None,
     Caller,
     Callee,
 }
 
+// What becomes of the results of ALU instructions targeting x0, the canonical NOP aside (see
+// `SimpleRV32IHart::set_x0_watch` for why the other instructions are left out)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum X0WritePolicy {
+    #[default]
+    Ignore, // discarded, as the spec has it
+    Trap,   // stops the run with an error (see `StopReason::X0Write`), to catch bugs in hand-written programs
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Registers64 {
     pub pc: RegisterValue64,
     pub array: RegistersArray64,
+    x0_write_policy: X0WritePolicy,
 }
 
 type RT = RegisterType;
@@ -261,24 +287,68 @@ impl Registers64 {
 
         let mut array: RegistersArray64 = [0; REGISTERS_COUNT];
 
+        // The stack grows down from the top of RAM; the ABI requires sp to be 16-byte aligned
         array[0] = 0;
-        array[2] = (ram_size - 1) as RegisterValue64;
+        array[2] = truncate_xlen((ram_size & !0xF) as RegisterValue64);
 
-        Self { pc, array }
+        Self {
+            pc,
+            array,
+            x0_write_policy: X0WritePolicy::Ignore,
+        }
     }
 
+    pub fn x0_write_policy(&self) -> X0WritePolicy { self.x0_write_policy }
+
+    pub fn set_x0_write_policy(&mut self, policy: X0WritePolicy) { self.x0_write_policy = policy }
+
     pub fn get(&self, reg: RT) -> RegisterValue64 {
-        match reg {
-            PC => self.pc,
-            ZERO => 0,
-            _ => self.array[reg.pos as usize],
+        match reg.number() {
+            None => self.pc,
+            Some(index) => self.read(index),
+        }
+    }
+
+    pub fn set(&mut self, rt: RT, v: RegisterValue64) {
+        match rt.number() {
+            None => self.pc = v,
+            Some(index) => self.write(index, v),
         }
     }
 
-    pub fn set(&mut self, rt: RT, v: RegisterValue64) { self.array[rt.pos as usize] = v }
+    // Access by register number (x0 to x31), as encoded in the rd/rs1/rs2 instruction fields.
+    // Writes to x0 are discarded.
+    pub fn read(&self, index: usize) -> RegisterValue64 { self.array[index] }
+
+    pub fn write(&mut self, index: usize, value: RegisterValue64) {
+        if index != 0 {
+            self.array[index] = value;
+        }
+    }
 }
 
 impl RegisterType {
+    // Every register, by position: the PC first, then x0 to x31
+    pub fn all() -> impl Iterator<Item = &'static Self> {
+        REGISTERS_BASE_MAP.values().copied().sorted_by_key(|rt| rt.pos)
+    }
+
+    // The register number (0 for x0, up to 31), as encoded in instructions; the PC has none
+    pub fn number(&self) -> Option<usize> { usize::try_from(self.pos + 1).ok() }
+
+    // x0 to x31, leaving out the PC
+    pub fn general() -> impl Iterator<Item = &'static Self> { Self::all().filter(|&rt| *rt != PC) }
+
+    // Registers a callee may clobber, which the caller must save across a call if it needs them
+    pub fn caller_saved() -> impl Iterator<Item = &'static Self> { Self::saved_by(SavedBy::Caller) }
+
+    // Registers a callee must preserve, restoring them before returning
+    pub fn callee_saved() -> impl Iterator<Item = &'static Self> { Self::saved_by(SavedBy::Callee) }
+
+    fn saved_by(saved_by: SavedBy) -> impl Iterator<Item = &'static Self> {
+        Self::all().filter(move |rt| rt.saved_by == saved_by)
+    }
+
     pub fn get_type_by_name(name: &str) -> Option<&Self> {
         REGISTERS_BASE_MAP
             .into_iter()
@@ -293,3 +363,70 @@ impl RegisterType {
             .map(|(_, &r)| r)
     }
 }
+
+// Lists the registers by their ABI names, DUMP_COLUMNS to a line, after the PC
+pub fn dump_registers(regs: &Registers64) -> String {
+    let mut dump = format!("{:>7}: {:#018x}\n", PC.abi, regs.pc);
+
+    for (row, registers) in REGISTERS_BY_NUMBER.chunks(DUMP_COLUMNS).enumerate() {
+        let cells: Vec<String> = registers
+            .iter()
+            .enumerate()
+            .map(|(column, rt)| format!("{:>7}: {:#018x}", rt.abi, regs.read(row * DUMP_COLUMNS + column)))
+            .collect();
+
+        dump.push_str(&cells.join("  "));
+        dump.push('\n');
+    }
+
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_dump_every_register_by_abi_name() {
+        let mut registers = Registers64::new(4096);
+        registers.write(10, 42);
+
+        let dump = dump_registers(&registers);
+
+        assert_eq!(dump.lines().count(), 1 + REGISTERS_COUNT / DUMP_COLUMNS);
+        assert!(dump.contains("     pc: 0x0000000000000000"));
+        assert!(dump.contains("     sp: 0x0000000000001000"));
+        assert!(dump.contains("     a0: 0x000000000000002a"));
+        assert!(dump.contains("s0 / fp"));
+    }
+
+    #[test]
+    fn should_list_the_general_registers_in_positional_order() {
+        let general: Vec<&RT> = RegisterType::general().collect();
+
+        assert_eq!(RegisterType::all().next(), Some(&PC));
+        assert_eq!(general.len(), REGISTERS_COUNT);
+        assert_eq!(general, REGISTERS_BY_NUMBER);
+    }
+
+    #[test]
+    fn should_tell_caller_saved_from_callee_saved_registers() {
+        assert!(RegisterType::caller_saved().any(|rt| *rt == RA));
+        assert!(RegisterType::callee_saved().any(|rt| *rt == SP));
+        assert!(!RegisterType::callee_saved().any(|rt| *rt == RA));
+        assert_eq!(RegisterType::caller_saved().count() + RegisterType::callee_saved().count(), 29);
+    }
+
+    #[test]
+    fn should_get_the_pc_and_x0_by_type() {
+        let mut registers = Registers64::new(4096);
+        registers.pc = 0x100;
+        registers.set(ZERO, 42);
+        registers.set(RA, 7);
+
+        assert_eq!(registers.get(PC), 0x100);
+        assert_eq!(registers.get(ZERO), 0);
+        assert_eq!(registers.get(RA), 7);
+        assert_eq!(registers.read(1), 7);
+    }
+}
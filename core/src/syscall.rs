@@ -0,0 +1,168 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::io;
+use std::io::Write;
+
+use crate::bus::Bus;
+use crate::memory::Word;
+use crate::register::RegisterValue64;
+
+// Syscall numbers, as in the Linux RISC-V ABI (which newlib's libgloss follows): the number goes
+// in a7, the arguments in a0 to a5, and the result comes back in a0
+pub const SYS_WRITE: RegisterValue64 = 64;
+pub const SYS_EXIT: RegisterValue64 = 93;
+pub const SYS_BRK: RegisterValue64 = 214;
+
+pub(crate) const SYSCALL_NUMBER: usize = 17; // a7
+pub(crate) const SYSCALL_ARGUMENTS: usize = 10; // a0, up to a5
+pub(crate) const SYSCALL_RESULT: usize = 10; // a0
+
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+
+// An ECALL, as seen by the machine running the guest
+pub struct Syscall<'a> {
+    pub hart_id: usize,
+    pub number: RegisterValue64,
+    pub args: [RegisterValue64; 6],
+    pub bus: &'a mut Bus,
+    pub program_end: Word, // first address past the loaded program, where its heap can start
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyscallOutcome {
+    Return(RegisterValue64), // written to a0; the guest resumes after the ECALL
+    Exit(i32),
+    Unhandled, // the machine stops on the ECALL, as on any other trap
+}
+
+// Handlers are Send, as the machines holding them (see `Device`)
+pub trait SyscallHandler: Send {
+    fn handle(&mut self, syscall: Syscall) -> SyscallOutcome;
+}
+
+// The few syscalls newlib needs to print and exit: write (to fd 1 and 2 only), exit and brk.
+// Errors are returned as negated errno values.
+pub struct Newlib {
+    stdout: Box<dyn Write + Send>,
+    stderr: Box<dyn Write + Send>,
+    program_break: Option<Word>, // starts at the end of the program
+}
+
+impl Newlib {
+    pub fn new(stdout: impl Write + Send + 'static, stderr: impl Write + Send + 'static) -> Self {
+        Self {
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+            program_break: None,
+        }
+    }
+
+    pub fn stdio() -> Self { Self::new(io::stdout(), io::stderr()) }
+
+    fn write(&mut self, fd: RegisterValue64, buffer: Word, length: Word, bus: &Bus) -> RegisterValue64 {
+        let output: &mut dyn Write = match fd {
+            1 => &mut self.stdout,
+            2 => &mut self.stderr,
+            _ => return errno(EBADF),
+        };
+        let Some(data) = buffer.checked_add(length).and_then(|end| bus.region_slice(buffer..end)) else {
+            return errno(EFAULT);
+        };
+
+        match output.write_all(data).and_then(|_| output.flush()) {
+            Ok(()) => length as RegisterValue64,
+            Err(_) => errno(EIO),
+        }
+    }
+
+    // Moves the break anywhere between the end of the program and the end of RAM; either way, returns
+    // the (possibly unchanged) break, as Linux does
+    fn brk(&mut self, address: Word, program_end: Word, bus: &Bus) -> RegisterValue64 {
+        let program_break = self.program_break.get_or_insert(program_end);
+
+        if address >= program_end && bus.region_slice(program_end..address).is_some() {
+            *program_break = address;
+        }
+
+        *program_break as RegisterValue64
+    }
+}
+
+impl SyscallHandler for Newlib {
+    fn handle(&mut self, syscall: Syscall) -> SyscallOutcome {
+        let [a0, a1, a2, ..] = syscall.args;
+
+        match syscall.number {
+            SYS_WRITE => SyscallOutcome::Return(self.write(a0, a1 as Word, a2 as Word, syscall.bus)),
+            SYS_EXIT => SyscallOutcome::Exit(a0 as i32),
+            SYS_BRK => SyscallOutcome::Return(self.brk(a0 as Word, syscall.program_end, syscall.bus)),
+            _ => SyscallOutcome::Unhandled,
+        }
+    }
+}
+
+fn errno(code: i64) -> RegisterValue64 { -code as RegisterValue64 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::SharedBuffer;
+    use crate::machine::{Machine, StopReason};
+
+    #[test]
+    fn should_write_a_guest_buffer_to_stdout() {
+        let stdout = SharedBuffer::default();
+        let mut machine = Machine::with_memory_size(4096);
+        machine.set_syscall_handler(Newlib::new(stdout.clone(), io::sink()));
+
+        let program: Vec<u8> = crate::rv32asm! {
+            addi x17, x0, 64;
+            addi x10, x0, 1;
+            addi x11, x0, 0x100;
+            addi x12, x0, 5;
+            ecall;
+            addi x5, x10, 0;
+            addi x17, x0, 93;
+            addi x10, x0, 0;
+            ecall
+        }
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect();
+        machine.load_binary(&program, 0).unwrap();
+        machine.bus().region_slice_mut(0x100..0x105).unwrap().copy_from_slice(b"hello");
+
+        let result = machine.run(u64::MAX);
+
+        assert_eq!(result.stop, StopReason::Exit(0));
+        assert_eq!(*stdout.0.lock().unwrap(), b"hello");
+        assert_eq!(machine.hart(0).registers().read(5), 5);
+    }
+
+    #[test]
+    fn should_move_the_break_within_ram() {
+        let bus = Bus::with_ram(4096);
+        let mut newlib = Newlib::new(io::sink(), io::sink());
+
+        assert_eq!(newlib.brk(0, 0x200, &bus), 0x200);
+        assert_eq!(newlib.brk(0x800, 0x200, &bus), 0x800);
+        assert_eq!(newlib.brk(0x2000, 0x200, &bus), 0x800);
+        assert_eq!(newlib.write(3, 0, 1, &bus), errno(EBADF));
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use crate::architecture::RV32Instruction;
+use crate::register::RegisterValue64;
+
+pub enum TraceEvent {
+    // An instruction completed without trapping; `pc` is the address it was fetched from
+    Retired {
+        pc: RegisterValue64,
+        instruction: RV32Instruction,
+    },
+
+    // An address computed by the instruction at `pc` (jump target, load or store address) wrapped
+    // around XLEN
+    AddressOverflow {
+        pc: RegisterValue64,
+        address: RegisterValue64,
+    },
+
+    // The instruction at `pc` computed a result into x0, where it was discarded: unless it is the
+    // canonical NOP, most likely an encoding mistake. Traced only when watching x0 (see
+    // `SimpleRV32IHart::set_x0_watch`).
+    DiscardedWrite {
+        pc: RegisterValue64,
+        instruction: RV32Instruction,
+    },
+}
+
+// Callback installed on a hart to observe its execution
+pub struct Tracer(Box<dyn FnMut(&TraceEvent) + Send>);
+
+impl Tracer {
+    pub fn new(hook: impl FnMut(&TraceEvent) + Send + 'static) -> Self { Self(Box::new(hook)) }
+
+    pub(crate) fn emit(&mut self, event: &TraceEvent) { (self.0)(event) }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("Tracer") }
+}
@@ -0,0 +1,58 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use num_enum::IntoPrimitive;
+
+use crate::alu::truncate_xlen;
+use crate::instruction::XLEN;
+use crate::register::RegisterValue64;
+
+// Exception codes as written to mcause (see the privileged spec, table 3.6). The hart only runs in
+// machine mode, so environment calls are always raised as `EnvironmentCallFromMMode`.
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum TrapCause {
+    InstructionAddressMisaligned = 0,
+    InstructionAccessFault       = 1,
+    IllegalInstruction           = 2,
+    Breakpoint                   = 3,
+    LoadAddressMisaligned        = 4,
+    LoadAccessFault              = 5,
+    StoreAddressMisaligned       = 6,
+    StoreAccessFault             = 7,
+    EnvironmentCallFromUMode     = 8,
+    EnvironmentCallFromSMode     = 9,
+    EnvironmentCallFromMMode     = 11,
+}
+
+// Interrupt codes as written to mcause, whose most significant (XLEN - 1) bit is then set
+#[derive(Clone, Copy, Debug, Eq, IntoPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum Interrupt {
+    MachineSoftware = 3,
+    MachineTimer    = 7,
+    MachineExternal = 11,
+}
+
+impl Interrupt {
+    pub fn mcause(self) -> RegisterValue64 { truncate_xlen(1 << (XLEN as u32 - 1) | u8::from(self) as RegisterValue64) }
+}
+
+// Splits an mcause value into whether it is an interrupt, and its exception or interrupt code
+pub fn decode_mcause(mcause: RegisterValue64) -> (bool, RegisterValue64) {
+    let interrupt_bit: RegisterValue64 = 1 << (XLEN as u32 - 1);
+    (mcause & interrupt_bit != 0, mcause & (interrupt_bit - 1))
+}
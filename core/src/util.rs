@@ -0,0 +1,53 @@
+// Copyright ©️ 2024 Rogério Senna. All rights reserved.
+//
+// Licensed under the EUPL, Version 1.2 or – as soon they will be approved by
+// the European Commission - subsequent versions of the EUPL (the "Licence");
+// You may not use this work except in compliance with the Licence.
+// You may obtain a copy of the Licence at:
+//
+// https://joinup.ec.europa.eu/software/page/eupl
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the Licence is distributed on an "AS IS" basis,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the Licence for the specific language governing permissions and
+// limitations under the Licence.
+//
+
+use std::num::ParseIntError;
+
+use crate::memory::Word;
+
+// Parses a word written in hex, with or without the 0x prefix, e.g. "0x00500093" or "00500093"
+pub fn word_from_hex(s: &str) -> Result<Word, ParseIntError> {
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    Word::from_str_radix(digits, 16)
+}
+
+// Formats a word as 8 zero-padded hex digits, prefixed with 0x
+pub fn word_to_hex(w: Word) -> String { format!("{:#010x}", w) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_round_trip_words_through_hex() {
+        assert_eq!(word_from_hex("0x00500093"), Ok(0x0050_0093)); // addi x1, x0, 5
+        assert_eq!(word_to_hex(0x0050_0093), "0x00500093");
+        assert_eq!(word_to_hex(word_from_hex("0x00500093").unwrap()), "0x00500093");
+    }
+
+    #[test]
+    fn should_parse_hex_without_prefix() {
+        assert_eq!(word_from_hex("00500093"), Ok(0x0050_0093));
+        assert_eq!(word_from_hex("FFFFFFFF"), Ok(Word::MAX));
+    }
+
+    #[test]
+    fn should_reject_invalid_hex() {
+        assert!(word_from_hex("0x").is_err());
+        assert!(word_from_hex("0x1_0000_0000").is_err());
+        assert!(word_from_hex("x0050").is_err());
+    }
+}